@@ -0,0 +1,228 @@
+use crate::common::StateId;
+
+use super::Nfa;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Subsumption {
+    /// (p, P) is covered by (p, Q) when Q ⊆ P
+    Subset,
+    /// (p, P) is covered by (q, Q) when q simulates p and each state of Q is simulated
+    /// by a state of P; sets are also reduced to states not simulated by others
+    Simulation,
+}
+
+/* Maximal forward direct simulation; sim[x * n + y] iff y simulates x */
+fn simulation(nfa: &Nfa) -> Vec<bool> {
+    let n = nfa.n_states();
+    let mut sim: Vec<bool> = (0..n * n).map(|i| {
+        !nfa.is_accepting((i / n) as StateId) || nfa.is_accepting((i % n) as StateId)
+    }).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for x in 0..n {
+            for y in 0..n {
+                if x == y || !sim[x * n + y] {
+                    continue;
+                }
+                let holds = (0..nfa.alphabet_size()).all(|a| {
+                    let ty = &nfa.get_transition(y as StateId, a).states;
+                    nfa.get_transition(x as StateId, a).states.iter().all(|x2| {
+                        ty.iter().any(|y2| sim[*x2 as usize * n + *y2 as usize])
+                    })
+                });
+                if !holds {
+                    sim[x * n + y] = false;
+                    changed = true;
+                }
+            }
+        }
+    }
+    sim
+}
+
+/* Simulation preorder; identity when simulations are not used */
+struct Preorder {
+    n: usize,
+    sim: Option<Vec<bool>>,
+}
+
+impl Preorder {
+    fn new(nfa: &Nfa, subsumption: Subsumption) -> Self {
+        Preorder {
+            n: nfa.n_states(),
+            sim: match subsumption {
+                Subsumption::Subset => None,
+                Subsumption::Simulation => Some(simulation(nfa)),
+            },
+        }
+    }
+
+    #[inline]
+    fn le(&self, x: StateId, y: StateId) -> bool {
+        match &self.sim {
+            Some(sim) => sim[x as usize * self.n + y as usize],
+            None => x == y,
+        }
+    }
+
+    /* Sorted set without states simulated by other states (one of equivalent states is kept) */
+    fn reduce(&self, mut set: Vec<StateId>) -> Vec<StateId> {
+        set.sort_unstable();
+        set.dedup();
+        if self.sim.is_none() {
+            return set;
+        }
+        set.iter().enumerate().filter(|(i, x)| {
+            !set.iter().enumerate().any(|(j, y)| j != *i && self.le(**x, *y) && (j < *i || !self.le(*y, **x)))
+        }).map(|(_, x)| *x).collect()
+    }
+
+    /* Every state of "smaller" is simulated by some state of "larger" */
+    fn covers(&self, smaller: &[StateId], larger: &[StateId]) -> bool {
+        smaller.iter().all(|y| larger.iter().any(|x| self.le(*y, *x)))
+    }
+}
+
+struct Macrostate {
+    state: StateId,
+    set: Vec<StateId>,
+    parent: Option<(usize, usize)>,
+    depth: usize,
+    alive: bool,
+}
+
+impl Nfa {
+    /* Shortest word accepted by self and not by other, None when L(self) ⊆ L(other) */
+    pub fn inclusion_witness(&self, other: &Nfa) -> Option<Vec<usize>> {
+        self.inclusion_witness_with(other, Subsumption::Simulation)
+    }
+
+    /* Breadth-first search over pairs (state of self, set of states of other); only pairs
+       that are minimal w.r.t. subsumption are kept, so other is never determinized */
+    pub fn inclusion_witness_with(&self, other: &Nfa, subsumption: Subsumption) -> Option<Vec<usize>> {
+        assert_eq!(self.n_tracks(), other.n_tracks());
+        let order1 = Preorder::new(self, subsumption);
+        let order2 = Preorder::new(other, subsumption);
+        let mut macrostates: Vec<Macrostate> = Vec::new();
+
+        let add = |macrostates: &mut Vec<Macrostate>, state: StateId, set: Vec<StateId>, parent: Option<(usize, usize)>| -> bool {
+            let depth = parent.map(|(p, _)| macrostates[p].depth + 1).unwrap_or(0);
+            if self.is_accepting(state) && !set.iter().any(|s| other.is_accepting(*s)) {
+                macrostates.push(Macrostate { state, set, parent, depth, alive: false });
+                return true;
+            }
+            if macrostates.iter().any(|m| m.alive && order1.le(state, m.state) && order2.covers(&m.set, &set)) {
+                return false;
+            }
+            /* Only macrostates that are not closer to the initial ones are dropped, so the witness stays the shortest */
+            for m in macrostates.iter_mut() {
+                if m.alive && m.depth >= depth && order1.le(m.state, state) && order2.covers(&set, &m.set) {
+                    m.alive = false;
+                }
+            }
+            macrostates.push(Macrostate { state, set, parent, depth, alive: true });
+            false
+        };
+
+        let mut init1: Vec<StateId> = self.initial_states().iter().copied().collect();
+        init1.sort_unstable();
+        let init2 = order2.reduce(other.initial_states().iter().copied().collect());
+        let mut found = init1.into_iter().any(|s| add(&mut macrostates, s, init2.clone(), None));
+
+        let mut i = 0;
+        while !found && i < macrostates.len() {
+            if macrostates[i].alive {
+                for a in 0..self.alphabet_size() {
+                    let set = order2.reduce(macrostates[i].set.iter().flat_map(|s| other.get_transition(*s, a).states.iter().copied()).collect());
+                    let targets = self.get_transition(macrostates[i].state, a).states.clone();
+                    if targets.into_iter().any(|s| add(&mut macrostates, s, set.clone(), Some((i, a)))) {
+                        found = true;
+                        break;
+                    }
+                }
+            }
+            i += 1;
+        }
+        if !found {
+            return None;
+        }
+
+        let mut word = Vec::new();
+        let mut m = macrostates.last().unwrap();
+        while let Some((parent, a)) = m.parent {
+            word.push(a);
+            m = &macrostates[parent];
+        }
+        word.reverse();
+        Some(word)
+    }
+
+    pub fn is_included(&self, other: &Nfa) -> bool {
+        self.inclusion_witness(other).is_none()
+    }
+
+    /* Shortest word that is not accepted */
+    pub fn universality_witness(&self) -> Option<Vec<usize>> {
+        Nfa::universal(self.n_tracks()).inclusion_witness(self)
+    }
+
+    pub fn is_universal(&self) -> bool {
+        self.universality_witness().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlevel::parser::parse_setdef;
+    use crate::solver::commands::build_set;
+
+    use super::*;
+
+    fn nfa(setdef: &str) -> Nfa {
+        build_set(&parse_setdef(setdef).unwrap()).unwrap().into_nfa()
+    }
+
+    fn check_witness(a: &Nfa, b: &Nfa, expected_length: Option<usize>) {
+        let w1 = a.inclusion_witness_with(b, Subsumption::Subset);
+        let w2 = a.inclusion_witness_with(b, Subsumption::Simulation);
+        assert_eq!(w1.as_ref().map(|w| w.len()), expected_length);
+        assert_eq!(w2.as_ref().map(|w| w.len()), expected_length);
+        let (da, db) = (a.make_dfa(), b.make_dfa());
+        for w in w1.iter().chain(w2.iter()) {
+            assert!(da.test_input(w.iter().copied()));
+            assert!(!db.test_input(w.iter().copied()));
+        }
+    }
+
+    #[test]
+    fn test_inclusion() {
+        let a = nfa("{ x | x > 5 }");
+        let b = nfa("{ x | x > 3 }");
+        check_witness(&a, &b, None);
+        /* x = 4 */
+        check_witness(&b, &a, Some(3));
+
+        let a = nfa("{ x, y | x + y == 7 and y < 3 }");
+        let b = nfa("{ x, y | x > 4 }");
+        check_witness(&a, &b, None);
+        check_witness(&b, &a, Some(3));
+
+        /* Automata from projections and unions are non-deterministic */
+        let mut p = build_set(&parse_setdef("{ x | x == 2 * y }").unwrap()).unwrap();
+        p = p.union(build_set(&parse_setdef("{ x | x == 3 * y }").unwrap()).unwrap());
+        let p = p.into_nfa();
+        let q = nfa("{ x | exists(y)(x == 6 * y) }");
+        check_witness(&q, &p, None);
+        /* x = 2 */
+        check_witness(&p, &q, Some(2));
+    }
+
+    #[test]
+    fn test_universality() {
+        assert!(nfa("{ x | x > 3 or x < 5 }").is_universal());
+        assert!(nfa("{ x, y | x < y or x >= y }").is_universal());
+        assert_eq!(nfa("{ x | exists(y)(2 * y == x) }").universality_witness(), Some(vec![1]));
+        assert_eq!(nfa("{ x, y | not x == y }").universality_witness(), Some(vec![]));
+    }
+}
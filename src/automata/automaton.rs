@@ -1,9 +1,19 @@
-use super::{Dfa, Nfa};
+use std::convert::Infallible;
+
+use super::{dump_dfa, Dfa, Dumper, DumpStage, Minimizer, Nfa, SymbolicDfa};
+
+fn minimize_dumped<E, F: FnMut(usize) -> Result<(), E>>(dfa: Dfa, check: F, dump: Option<&Dumper>) -> Result<Dfa, E> {
+    dump_dfa(dump, DumpStage::Determinized, &dfa);
+    let dfa = dfa.minimize_checked(Minimizer::Hopcroft, check)?;
+    dump_dfa(dump, DumpStage::Minimized, &dfa);
+    Ok(dfa)
+}
 
 #[derive(Debug, Clone)]
 pub enum Automaton {
     Dfa(Dfa),
     Nfa(Nfa),
+    Symbolic(SymbolicDfa),
 }
 
 impl Automaton {
@@ -11,6 +21,7 @@ impl Automaton {
         match self {
             Self::Dfa(dfa) => dfa.to_nfa(),
             Self::Nfa(nfa) => nfa,
+            Self::Symbolic(sdfa) => sdfa.to_dfa().to_nfa(),
         }
     }
 
@@ -18,6 +29,15 @@ impl Automaton {
         match self {
             Self::Dfa(dfa) => dfa,
             Self::Nfa(nfa) => nfa.make_dfa(),
+            Self::Symbolic(sdfa) => sdfa.to_dfa(),
+        }
+    }
+
+    pub fn into_symbolic(self) -> SymbolicDfa {
+        match self {
+            Self::Dfa(dfa) => SymbolicDfa::from_dfa(&dfa),
+            Self::Nfa(nfa) => SymbolicDfa::from_dfa(&nfa.make_dfa()),
+            Self::Symbolic(sdfa) => sdfa,
         }
     }
 
@@ -25,13 +45,20 @@ impl Automaton {
         match self {
             Self::Dfa(dfa) => dfa.clone(),
             Self::Nfa(nfa) => nfa.make_dfa(),
+            Self::Symbolic(sdfa) => sdfa.to_dfa(),
         }
     }
 
+    #[inline]
+    pub fn is_symbolic(&self) -> bool {
+        matches!(self, Self::Symbolic(_))
+    }
+
     pub fn add_track(&mut self) {
         match self {
             Self::Dfa(dfa) => dfa.add_track(),
             Self::Nfa(nfa) => nfa.add_track(),
+            Self::Symbolic(sdfa) => sdfa.add_track(),
         }
     }
 
@@ -39,24 +66,57 @@ impl Automaton {
         match self {
             Self::Dfa(dfa) => dfa.swap_tracks(index1, index2),
             Self::Nfa(nfa) => nfa.swap_tracks(index1, index2),
+            Self::Symbolic(sdfa) => sdfa.swap_tracks(index1, index2),
         }
     }
 
+    /* Explicit minimal DFA; a symbolic automaton is expanded into 2^n_tracks columns */
     pub fn ensure_dfa(&mut self) -> &Dfa {
         match self {
             Self::Dfa(dfa) => dfa,
             Self::Nfa(nfa) => {
-                let dfa = Self::Dfa(nfa.determinize().minimize());
+                let dfa = Self::Dfa(minimize_dumped(nfa.determinize(), |_| Ok::<(), Infallible>(()), None).unwrap());
+                *self = dfa;
+                self.ensure_dfa()
+            }
+            Self::Symbolic(sdfa) => {
+                let dfa = Self::Dfa(sdfa.to_dfa());
                 *self = dfa;
                 self.ensure_dfa()
             }
         }
     }
 
+    /* Deterministic and minimal, but a symbolic automaton stays symbolic */
+    pub fn ensure_minimal(&mut self) {
+        if !self.is_symbolic() {
+            self.ensure_dfa();
+        }
+    }
+
+    /* Like ensure_minimal, determinization and minimization are stopped when "check" fails
+       (see Nfa::determinize_with and Dfa::minimize_checked); both automata can be dumped */
+    pub fn ensure_minimal_with<E, F: FnMut(usize) -> Result<(), E>>(&mut self, mut check: F, dump: Option<&Dumper>) -> Result<(), E> {
+        if let Self::Nfa(nfa) = self {
+            let dfa = nfa.determinize_with(&mut check)?;
+            *self = Self::Dfa(minimize_dumped(dfa, check, dump)?);
+        }
+        Ok(())
+    }
+
+    pub fn n_states(&self) -> usize {
+        match self {
+            Self::Dfa(dfa) => dfa.n_states(),
+            Self::Nfa(nfa) => nfa.n_states(),
+            Self::Symbolic(sdfa) => sdfa.n_states(),
+        }
+    }
+
     pub fn alphabet_size(&self) -> usize {
         match self {
             Self::Dfa(dfa) => dfa.alphabet_size(),
             Self::Nfa(nfa) => nfa.alphabet_size(),
+            Self::Symbolic(sdfa) => 1 << sdfa.n_tracks(),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,438 @@
+use hashbrown::{HashMap, HashSet};
+
+pub type NodeId = u32;
+
+/* Results of next_symbol_helper by (node, variable, bits so far are at least the bits of "from") */
+type SymbolMemo = HashMap<(NodeId, usize, bool), Option<(usize, u32)>>;
+
+/* Variables are tested in increasing order from the root; variable i is bit i of a symbol */
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+enum Node {
+    Leaf(u32),
+    Branch { var: u32, low: NodeId, high: NodeId },
+}
+
+/// Shared multi-terminal binary decision diagrams with u32 leaves
+#[derive(Debug, Clone, Default)]
+pub struct Mtbdd {
+    nodes: Vec<Node>,
+    unique: HashMap<Node, NodeId>,
+}
+
+impl Mtbdd {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn make(&mut self, node: Node) -> NodeId {
+        let nodes = &mut self.nodes;
+        *self.unique.entry(node).or_insert_with(|| {
+            nodes.push(node);
+            (nodes.len() - 1) as NodeId
+        })
+    }
+
+    #[inline]
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn leaf(&mut self, value: u32) -> NodeId {
+        self.make(Node::Leaf(value))
+    }
+
+    pub fn branch(&mut self, var: u32, low: NodeId, high: NodeId) -> NodeId {
+        if low == high {
+            low
+        } else {
+            debug_assert!(var < self.top_var(low) && var < self.top_var(high));
+            self.make(Node::Branch { var, low, high })
+        }
+    }
+
+    #[inline]
+    fn top_var(&self, node: NodeId) -> u32 {
+        match self.nodes[node as usize] {
+            Node::Leaf(_) => u32::MAX,
+            Node::Branch { var, .. } => var,
+        }
+    }
+
+    #[inline]
+    fn cofactors(&self, node: NodeId, var: u32) -> (NodeId, NodeId) {
+        match self.nodes[node as usize] {
+            Node::Branch { var: v, low, high } if v == var => (low, high),
+            _ => (node, node),
+        }
+    }
+
+    /* Diagram for row of an explicit transition table (row[symbol]) */
+    pub fn row(&mut self, n_vars: usize, row: &[u32]) -> NodeId {
+        assert_eq!(row.len(), 1 << n_vars);
+        self.row_helper(n_vars, row, 0, 0)
+    }
+
+    fn row_helper(&mut self, n_vars: usize, row: &[u32], var: usize, prefix: usize) -> NodeId {
+        if var == n_vars {
+            return self.leaf(row[prefix]);
+        }
+        let low = self.row_helper(n_vars, row, var + 1, prefix);
+        let high = self.row_helper(n_vars, row, var + 1, prefix | 1 << var);
+        self.branch(var as u32, low, high)
+    }
+
+    pub fn eval(&self, mut node: NodeId, symbol: usize) -> u32 {
+        loop {
+            match self.nodes[node as usize] {
+                Node::Leaf(value) => return value,
+                Node::Branch { var, low, high } => {
+                    node = if (symbol >> var) & 1 == 1 { high } else { low };
+                }
+            }
+        }
+    }
+
+    /* Distinct leaves reachable from node */
+    pub fn leaves(&self, node: NodeId) -> Vec<u32> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![node];
+        let mut result = Vec::new();
+        while let Some(n) = stack.pop() {
+            if !visited.insert(n) {
+                continue;
+            }
+            match self.nodes[n as usize] {
+                Node::Leaf(value) => result.push(value),
+                Node::Branch { low, high, .. } => {
+                    stack.push(high);
+                    stack.push(low);
+                }
+            }
+        }
+        result
+    }
+
+    /* Number of symbols over n_vars variables leading to each leaf; counts saturate at u128::MAX */
+    pub fn leaf_counts(&self, node: NodeId, n_vars: usize) -> Vec<(u32, u128)> {
+        let level = |n: NodeId| match self.nodes[n as usize] {
+            Node::Leaf(_) => n_vars as u32,
+            Node::Branch { var, .. } => var,
+        };
+        let scale = |count: u128, bits: u32| if count == 0 || count.leading_zeros() >= bits { count << bits } else { u128::MAX };
+        /* Weights are pushed down in the order of variables, children always test later variables */
+        let mut nodes = self.reachable(node);
+        nodes.sort_by_key(|n| level(*n));
+        let mut weights: HashMap<NodeId, u128> = HashMap::new();
+        weights.insert(node, scale(1, level(node)));
+        let mut result = Vec::new();
+        for n in nodes {
+            let weight = weights[&n];
+            match self.nodes[n as usize] {
+                Node::Leaf(value) => result.push((value, weight)),
+                Node::Branch { var, low, high } => {
+                    for child in [low, high] {
+                        let w = weights.entry(child).or_insert(0);
+                        *w = w.saturating_add(scale(weight, level(child) - var - 1));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn reachable(&self, node: NodeId) -> Vec<NodeId> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![node];
+        while let Some(n) = stack.pop() {
+            if visited.insert(n) {
+                if let Node::Branch { low, high, .. } = self.nodes[n as usize] {
+                    stack.push(low);
+                    stack.push(high);
+                }
+            }
+        }
+        visited.into_iter().collect()
+    }
+
+    /* Smallest symbol "a >= from" over n_vars variables such that the leaf of "a ^ xor" satisfies "filter",
+       together with the leaf */
+    pub fn next_symbol<F: Fn(u32) -> bool>(&self, node: NodeId, n_vars: usize, from: usize, xor: usize, filter: &F) -> Option<(usize, u32)> {
+        assert!(n_vars < usize::BITS as usize);
+        if from >> n_vars != 0 {
+            return None;
+        }
+        self.next_symbol_helper(node, 0, n_vars, true, from, xor, filter, &mut HashMap::new())
+    }
+
+    /* Bits below "var" are chosen, "ge" says whether they are at least the bits of "from";
+       the result holds the remaining bits. Comparing whole symbols is correct because both
+       candidates have the same bits below "var" */
+    #[allow(clippy::too_many_arguments)]
+    fn next_symbol_helper<F: Fn(u32) -> bool>(&self, node: NodeId, var: usize, n_vars: usize, ge: bool, from: usize, xor: usize,
+                                              filter: &F, memo: &mut SymbolMemo) -> Option<(usize, u32)> {
+        if var == n_vars {
+            return match self.nodes[node as usize] {
+                Node::Leaf(value) if ge && filter(value) => Some((0, value)),
+                _ => None,
+            };
+        }
+        if let Some(r) = memo.get(&(node, var, ge)) {
+            return *r;
+        }
+        let (low, high) = self.cofactors(node, var as u32);
+        let f = (from >> var) & 1;
+        let mut result = None;
+        for bit in 0..2 {
+            let child = if bit ^ ((xor >> var) & 1) == 1 { high } else { low };
+            let ge = bit > f || (bit == f && ge);
+            if let Some((rest, leaf)) = self.next_symbol_helper(child, var + 1, n_vars, ge, from, xor, filter, memo) {
+                let symbol = rest | bit << var;
+                if result.is_none_or(|(r, _)| symbol < r) {
+                    result = Some((symbol, leaf));
+                }
+            }
+        }
+        memo.insert((node, var, ge), result);
+        result
+    }
+
+    /* Pointwise combination of two diagrams; "f" has to be a function of its arguments
+       as long as the memo is reused */
+    pub fn apply2<F: FnMut(u32, u32) -> u32>(&mut self, a: NodeId, b: NodeId, f: &mut F, memo: &mut HashMap<(NodeId, NodeId), NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&(a, b)) {
+            return *r;
+        }
+        let result = match (self.nodes[a as usize], self.nodes[b as usize]) {
+            (Node::Leaf(x), Node::Leaf(y)) => {
+                let value = f(x, y);
+                self.leaf(value)
+            }
+            _ => {
+                let var = self.top_var(a).min(self.top_var(b));
+                let (a0, a1) = self.cofactors(a, var);
+                let (b0, b1) = self.cofactors(b, var);
+                let low = self.apply2(a0, b0, f, memo);
+                let high = self.apply2(a1, b1, f, memo);
+                self.branch(var, low, high)
+            }
+        };
+        memo.insert((a, b), result);
+        result
+    }
+
+    pub fn map_leaves<F: FnMut(u32) -> u32>(&mut self, node: NodeId, f: &mut F, memo: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match self.nodes[node as usize] {
+            Node::Leaf(x) => {
+                let value = f(x);
+                self.leaf(value)
+            }
+            Node::Branch { var, low, high } => {
+                let low = self.map_leaves(low, f, memo);
+                let high = self.map_leaves(high, f, memo);
+                self.branch(var, low, high)
+            }
+        };
+        memo.insert(node, result);
+        result
+    }
+
+    /* Removes variable "var" by combining both its branches by "f"; variables above are shifted down */
+    pub fn exists_var<F: FnMut(u32, u32) -> u32>(&mut self, node: NodeId, var: u32, f: &mut F,
+                                                 memo: &mut HashMap<NodeId, NodeId>,
+                                                 apply_memo: &mut HashMap<(NodeId, NodeId), NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match self.nodes[node as usize] {
+            Node::Leaf(_) => node,
+            Node::Branch { var: v, low, high } if v < var => {
+                let low = self.exists_var(low, var, f, memo, apply_memo);
+                let high = self.exists_var(high, var, f, memo, apply_memo);
+                self.branch(v, low, high)
+            }
+            _ => {
+                let (low, high) = self.cofactors(node, var);
+                let merged = self.apply2(low, high, f, apply_memo);
+                self.shift_vars(merged, var, &mut HashMap::new())
+            }
+        };
+        memo.insert(node, result);
+        result
+    }
+
+    /* Decrements all variables above "var" (there is no "var" in the diagram) */
+    fn shift_vars(&mut self, node: NodeId, var: u32, memo: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match self.nodes[node as usize] {
+            Node::Leaf(_) => node,
+            Node::Branch { var: v, low, high } => {
+                debug_assert!(v > var);
+                let low = self.shift_vars(low, var, memo);
+                let high = self.shift_vars(high, var, memo);
+                self.branch(v - 1, low, high)
+            }
+        };
+        memo.insert(node, result);
+        result
+    }
+
+    /* Diagram "if var then high else low" where low and high do not depend on var */
+    fn select(&mut self, var: u32, high: NodeId, low: NodeId, memo: &mut HashMap<(u32, NodeId, NodeId), NodeId>) -> NodeId {
+        let top = self.top_var(high).min(self.top_var(low));
+        if var < top {
+            return self.branch(var, low, high);
+        }
+        if let Some(r) = memo.get(&(var, high, low)) {
+            return *r;
+        }
+        let (h0, h1) = self.cofactors(high, top);
+        let (l0, l1) = self.cofactors(low, top);
+        let r0 = self.select(var, h0, l0, memo);
+        let r1 = self.select(var, h1, l1, memo);
+        let result = self.branch(top, r0, r1);
+        memo.insert((var, high, low), result);
+        result
+    }
+
+    /* Diagram of "g(node(a))(a)", i.e. every leaf is replaced by a diagram read by the same symbol */
+    pub fn compose<F: FnMut(&mut Self, u32) -> NodeId>(&mut self, node: NodeId, g: &mut F, memo: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match self.nodes[node as usize] {
+            Node::Leaf(x) => g(self, x),
+            Node::Branch { var, low, high } => {
+                let low = self.compose(low, g, memo);
+                let high = self.compose(high, g, memo);
+                let low = self.restrict(low, var, false, &mut HashMap::new());
+                let high = self.restrict(high, var, true, &mut HashMap::new());
+                self.select(var, high, low, &mut HashMap::new())
+            }
+        };
+        memo.insert(node, result);
+        result
+    }
+
+    /* Cofactor of the diagram by "var == value" */
+    fn restrict(&mut self, node: NodeId, var: u32, value: bool, memo: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match self.nodes[node as usize] {
+            Node::Branch { var: v, low, high } if v < var => {
+                let low = self.restrict(low, var, value, memo);
+                let high = self.restrict(high, var, value, memo);
+                self.branch(v, low, high)
+            }
+            Node::Branch { var: v, low, high } if v == var => if value { high } else { low },
+            _ => node,
+        };
+        memo.insert(node, result);
+        result
+    }
+
+    /* Renames variable v to permutation[v] */
+    pub fn rename_vars(&mut self, node: NodeId, permutation: &[u32], memo: &mut HashMap<NodeId, NodeId>,
+                       select_memo: &mut HashMap<(u32, NodeId, NodeId), NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match self.nodes[node as usize] {
+            Node::Leaf(_) => node,
+            Node::Branch { var, low, high } => {
+                let low = self.rename_vars(low, permutation, memo, select_memo);
+                let high = self.rename_vars(high, permutation, memo, select_memo);
+                self.select(permutation[var as usize], high, low, select_memo)
+            }
+        };
+        memo.insert(node, result);
+        result
+    }
+
+    /* Copies a diagram from another manager */
+    pub fn import(&mut self, other: &Mtbdd, node: NodeId, memo: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(r) = memo.get(&node) {
+            return *r;
+        }
+        let result = match other.nodes[node as usize] {
+            Node::Leaf(x) => self.leaf(x),
+            Node::Branch { var, low, high } => {
+                let low = self.import(other, low, memo);
+                let high = self.import(other, high, memo);
+                self.branch(var, low, high)
+            }
+        };
+        memo.insert(node, result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bdd_row() {
+        let mut bdd = Mtbdd::new();
+        let row = [3, 3, 3, 3, 5, 5, 5, 7];
+        let n = bdd.row(3, &row);
+        for (a, v) in row.iter().enumerate() {
+            assert_eq!(bdd.eval(n, a), *v);
+        }
+        let mut leaves = bdd.leaves(n);
+        leaves.sort();
+        assert_eq!(leaves, vec![3, 5, 7]);
+        /* Shared row gives the same node */
+        assert_eq!(bdd.row(3, &row), n);
+        assert_eq!(bdd.row(3, &[1; 8]), bdd.leaf(1));
+    }
+
+    #[test]
+    fn test_bdd_operations() {
+        let mut bdd = Mtbdd::new();
+        let row = [0, 1, 2, 3, 4, 5, 6, 7];
+        let n = bdd.row(3, &row);
+
+        let m = bdd.apply2(n, n, &mut |a, b| a + b, &mut HashMap::new());
+        for a in 0..8 {
+            assert_eq!(bdd.eval(m, a), 2 * a as u32);
+        }
+
+        /* Swap variables 0 and 2 */
+        let s = bdd.rename_vars(n, &[2, 1, 0], &mut HashMap::new(), &mut HashMap::new());
+        for a in 0..8 {
+            let swapped = (a & 2) | (a >> 2) | ((a & 1) << 2);
+            assert_eq!(bdd.eval(s, swapped), a as u32);
+        }
+
+        /* Remove variable 1 by max */
+        let e = bdd.exists_var(n, 1, &mut |a, b| a.max(b), &mut HashMap::new(), &mut HashMap::new());
+        for a in 0..4 {
+            let x = (a & 1) | ((a >> 1) << 2);
+            assert_eq!(bdd.eval(e, a), (x | 2) as u32);
+        }
+
+        let r = bdd.row(3, &[3, 3, 3, 3, 5, 5, 5, 7]);
+        let mut counts = bdd.leaf_counts(r, 4);
+        counts.sort();
+        assert_eq!(counts, vec![(3, 8), (5, 6), (7, 2)]);
+        let r = bdd.row(3, &[1, 4, 2, 4, 2, 4, 1, 4]);
+        assert_eq!(bdd.next_symbol(r, 3, 0, 0, &|v| v < 4), Some((0, 1)));
+        assert_eq!(bdd.next_symbol(r, 3, 1, 0, &|v| v < 4), Some((2, 2)));
+        assert_eq!(bdd.next_symbol(r, 3, 5, 0, &|v| v < 4), Some((6, 1)));
+        assert_eq!(bdd.next_symbol(r, 3, 7, 0, &|v| v < 4), None);
+        assert_eq!(bdd.next_symbol(r, 3, 0, 1, &|v| v < 4), Some((1, 1)));
+        assert_eq!(bdd.next_symbol(r, 3, 8, 0, &|_| true), None);
+
+        /* Leaf x is replaced by the row "10 * x + symbol" */
+        let c = bdd.compose(n, &mut |bdd, x| bdd.row(3, &row.map(|a| 10 * x + a)), &mut HashMap::new());
+        for a in 0..8 {
+            assert_eq!(bdd.eval(c, a), 11 * a as u32);
+        }
+    }
+}
@@ -1,9 +1,12 @@
+use std::convert::Infallible;
+
 use hashbrown;
 use hashbrown::{HashMap, HashSet};
 
 use crate::common::StateId;
 
-use super::{Nfa, Transition};
+use super::{Minimizer, Nfa, Transition};
+use super::minimize::{hopcroft_partitions, valmari_partitions};
 use super::TransitionTable;
 
 #[derive(Debug, Clone)]
@@ -36,6 +39,43 @@ impl Dfa {
         }
     }
 
+    /* Synchronized product restricted to pairs reachable from (0, 0) */
+    pub fn intersection(&self, other: &Dfa) -> Dfa {
+        self.intersection_with(other, |_| Ok::<(), Infallible>(())).unwrap()
+    }
+
+    /* Product that stops when "check" fails; it gets the number of created states */
+    pub fn intersection_with<E, F: FnMut(usize) -> Result<(), E>>(&self, other: &Dfa, mut check: F) -> Result<Dfa, E> {
+        assert_eq!(self.n_tracks(), other.n_tracks());
+        let asize = self.alphabet_size();
+        let mut map: HashMap<(StateId, StateId), StateId> = HashMap::new();
+        map.insert((0, 0), 0);
+        let mut pairs = vec![(0, 0)];
+        let mut transitions = Vec::new();
+        let mut i = 0;
+        while i < pairs.len() {
+            let (s1, s2) = pairs[i];
+            let (row1, row2) = (self.get_row(s1), other.get_row(s2));
+            for a in 0..asize {
+                let pair = (row1[a], row2[a]);
+                let id = match map.get(&pair) {
+                    Some(id) => *id,
+                    None => {
+                        let id = pairs.len() as StateId;
+                        map.insert(pair, id);
+                        pairs.push(pair);
+                        check(pairs.len())?;
+                        id
+                    }
+                };
+                transitions.push(id);
+            }
+            i += 1;
+        }
+        let accepting = pairs.iter().map(|(s1, s2)| self.is_accepting(*s1) && other.is_accepting(*s2)).collect();
+        Ok(Dfa::new(TransitionTable::new(self.n_tracks(), transitions), accepting))
+    }
+
     pub fn to_nfa(self) -> Nfa {
         Nfa::new(self.table.map_transitions(|s| Transition::simple(*s)), self.accepting, Nfa::simple_init())
     }
@@ -162,8 +202,43 @@ impl Dfa {
     }*/
 
     pub fn minimize(&self) -> Self {
+        self.minimize_with(Minimizer::Hopcroft)
+    }
+
+    pub fn minimize_with(&self, minimizer: Minimizer) -> Self {
+        self.minimize_checked(minimizer, |_| Ok::<(), Infallible>(())).unwrap()
+    }
+
+    /* Minimization that stops when "check" fails; it gets the number of blocks of the partition */
+    pub fn minimize_checked<E, F: FnMut(usize) -> Result<(), E>>(&self, minimizer: Minimizer, mut check: F) -> Result<Self, E> {
+        assert!(self.n_states() > 0);
+        let partitions = match minimizer {
+            Minimizer::Hopcroft => hopcroft_partitions(self, &mut check)?,
+            Minimizer::Valmari => valmari_partitions(self, &mut check)?,
+            Minimizer::Moore => self.moore_partitions(&mut check)?,
+        };
+        Ok(self.quotient(&partitions))
+    }
+
+    /* Automaton where states are merged by partitions; ids of partitions have to be
+       numbered by their first state, so the initial state remains 0 */
+    fn quotient(&self, partitions: &[StateId]) -> Self {
+        let asize = self.alphabet_size();
+        let n_partitions = partitions.iter().max().map(|p| *p as usize + 1).unwrap_or(0);
+        let mut transitions = vec![0; asize * n_partitions];
+        let mut accepting = vec![false; n_partitions];
+        for (s, row) in self.table.rows().enumerate() {
+            let p = partitions[s] as usize;
+            for (t, target) in transitions[p * asize..(p + 1) * asize].iter_mut().zip(row) {
+                *t = partitions[*target as usize];
+            }
+            accepting[p] = self.accepting[s];
+        }
+        Dfa::new(TransitionTable::new(self.n_tracks(), transitions), accepting)
+    }
+
+    fn moore_partitions<E, F: FnMut(usize) -> Result<(), E>>(&self, check: &mut F) -> Result<Vec<StateId>, E> {
         let n_states = self.accepting.len();
-        assert!(n_states > 0);
         let asize = self.alphabet_size();
         let mut partitions: Vec<StateId> = self.accepting.iter().map(|a| if *a { 0 } else { 1 }).collect();
 
@@ -172,6 +247,7 @@ impl Dfa {
 
         let mut prev_ids = 0;
         loop {
+            check(prev_ids as usize)?;
             self.table.fill_partitions(&partitions, &mut target_ids);
             let mut map = HashMap::new();
             let mut new_id = 0;
@@ -190,17 +266,7 @@ impl Dfa {
                 prev_ids = new_id;
             }
         }
-
-        let mut transitions = vec![0; asize * prev_ids as usize];
-        let mut accepting = vec![false; prev_ids as usize];
-
-        for s in 0..n_states {
-            let p = partitions[s] as usize;
-            let slice = &target_ids[s * asize..(s + 1) * asize];
-            transitions[p * asize..(p + 1) * asize].copy_from_slice(slice);
-            accepting[p] = self.accepting[s];
-        }
-        Dfa::new(TransitionTable::new(self.n_tracks(), transitions), accepting)
+        Ok(partitions)
     }
 }
 
@@ -213,6 +279,58 @@ mod tests {
         Dfa::new(TransitionTable::new(n_tracks, tr), acc)
     }
 
+    #[test]
+    fn test_intersection() {
+        /* Even number of 1s x ends with 1 */
+        let a = make_da(1, vec![0, 1, 1, 0], vec![true, false]);
+        let b = make_da(1, vec![0, 1, 0, 1], vec![false, true]);
+        let c = a.intersection(&b);
+        assert_eq!(c.n_states(), 4);
+        assert!(c.test_input([1, 0, 1].iter().copied()));
+        assert!(!c.test_input([1, 1, 0].iter().copied()));
+        assert!(!c.test_input([0, 1].iter().copied()));
+        assert_eq!(c.minimize().n_states(), 3);
+        assert_eq!(a.intersection(&a.clone().neg()).minimize().n_states(), 1);
+    }
+
+    /* Pseudo-random DFA (LCG), "accepting_ratio" of states are accepting */
+    fn random_dfa(seed: u64, n_tracks: usize, n_states: usize, accepting_ratio: u64) -> Dfa {
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            x >> 33
+        };
+        let tr = (0..n_states << n_tracks).map(|_| (next() % n_states as u64) as StateId).collect();
+        let acc = (0..n_states).map(|_| next() % 100 < accepting_ratio).collect();
+        make_da(n_tracks, tr, acc)
+    }
+
+    #[test]
+    fn test_minimizers_agree() {
+        for seed in 0..300 {
+            let a = random_dfa(seed, (seed % 3) as usize, 1 + (seed % 37) as usize, seed % 101);
+            let m = a.minimize_with(Minimizer::Moore);
+            for minimizer in &[Minimizer::Hopcroft, Minimizer::Valmari] {
+                let m2 = a.minimize_with(*minimizer);
+                assert_eq!(m.transitions(), m2.transitions(), "{:?}, seed {}", minimizer, seed);
+                assert_eq!(m.accepting(), m2.accepting(), "{:?}, seed {}", minimizer, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_checked_operations() {
+        let a = random_dfa(7, 2, 30, 50);
+        let b = random_dfa(8, 2, 30, 50);
+        let n = a.intersection(&b).n_states();
+        assert!(n > 10);
+        assert_eq!(a.intersection_with(&b, |s| if s > 10 { Err(s) } else { Ok(()) }).unwrap_err(), 11);
+        assert_eq!(a.intersection_with(&b, |s| if s > n { Err(s) } else { Ok(()) }).unwrap().n_states(), n);
+        for minimizer in &[Minimizer::Hopcroft, Minimizer::Valmari, Minimizer::Moore] {
+            assert!(a.minimize_checked(*minimizer, |_| Err(())).is_err());
+        }
+    }
+
     #[test]
     fn test_minimize_simple() {
         let tr = vec![0, 1, 1, 0];
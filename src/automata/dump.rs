@@ -0,0 +1,91 @@
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::{Dfa, Nfa, SymbolicDfa};
+
+/// Intermediate automata that can be written into the dump directory
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DumpStage {
+    /// Result of a subset construction, before minimization
+    Determinized,
+    Minimized,
+    /// Non-deterministic automaton after removing a track
+    Projected,
+    /// Automaton of elements smaller than a given one (see elements::cut)
+    Cut,
+}
+
+impl DumpStage {
+    pub const ALL: [DumpStage; 4] = [DumpStage::Determinized, DumpStage::Minimized, DumpStage::Projected, DumpStage::Cut];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Determinized => "determinized",
+            Self::Minimized => "minimized",
+            Self::Projected => "projected",
+            Self::Cut => "cut",
+        }
+    }
+}
+
+impl FromStr for DumpStage {
+    type Err = String;
+
+    fn from_str(stage: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter().find(|s| s.name() == stage).copied().ok_or_else(|| format!("Dump stage '{}' does not exist", stage))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DumpConfig {
+    pub dir: PathBuf,
+    pub stages: Vec<DumpStage>,
+}
+
+/// Configuration of dumps with the number of already written files; it is owned by the caller
+/// (e.g. solver::Context) and passed to operations that produce the dumped automata
+#[derive(Debug)]
+pub struct Dumper {
+    config: DumpConfig,
+    counter: Cell<usize>,
+}
+
+impl Dumper {
+    pub fn new(config: DumpConfig) -> Self {
+        Dumper { config, counter: Cell::new(0) }
+    }
+
+    pub fn is_dumped(&self, stage: DumpStage) -> bool {
+        self.config.stages.contains(&stage)
+    }
+
+    /* Files are numbered in the order of dumps, e.g. "0003-minimized.dot";
+       a failed write is reported but it does not stop the computation */
+    fn write(&self, stage: DumpStage, nfa: &Nfa) {
+        self.counter.set(self.counter.get() + 1);
+        let path = self.config.dir.join(format!("{:04}-{}.dot", self.counter.get(), stage.name()));
+        if let Err(e) = nfa.write_dot(&path, false) {
+            eprintln!("Cannot dump automaton into {}: {}", path.display(), e);
+        }
+    }
+}
+
+pub fn dump_nfa(dump: Option<&Dumper>, stage: DumpStage, nfa: &Nfa) {
+    if let Some(dumper) = dump.filter(|d| d.is_dumped(stage)) {
+        dumper.write(stage, nfa);
+    }
+}
+
+pub fn dump_dfa(dump: Option<&Dumper>, stage: DumpStage, dfa: &Dfa) {
+    if let Some(dumper) = dump.filter(|d| d.is_dumped(stage)) {
+        dumper.write(stage, &dfa.make_nfa());
+    }
+}
+
+/* Symbolic automata are expanded into explicit tables */
+pub fn dump_symbolic(dump: Option<&Dumper>, stage: DumpStage, sdfa: &SymbolicDfa) {
+    if let Some(dumper) = dump.filter(|d| d.is_dumped(stage)) {
+        dumper.write(stage, &sdfa.to_dfa().to_nfa());
+    }
+}
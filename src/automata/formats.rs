@@ -0,0 +1,617 @@
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::common::{PasError, PasResult, StateId};
+
+use super::{Nfa, Transition, TransitionTable};
+
+/// Textual formats of finite automata used by other tools.
+/// In Timbuk and BA/FA a symbol is written as "s" followed by its bits (track 0 first), e.g. "s01";
+/// in HOA tracks are atomic propositions. HOA describes automata over infinite words, so a convention
+/// for finite words is used: states in the acceptance set 0 are final. Exported files declare
+/// "Acceptance: 1 t", i.e. the set only marks final states and has no meaning for infinite runs;
+/// on import "1 t" and the Buchi condition "1 Inf(0)" are read in the same way and "0 t" makes
+/// every state final
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AutomatonFormat {
+    Timbuk,
+    Ba,
+    Hoa,
+}
+
+impl FromStr for AutomatonFormat {
+    type Err = PasError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "timbuk" => Ok(AutomatonFormat::Timbuk),
+            "ba" | "fa" => Ok(AutomatonFormat::Ba),
+            "hoa" => Ok(AutomatonFormat::Hoa),
+            _ => Err(PasError::InvalidArgument(format.to_string())),
+        }
+    }
+}
+
+fn invalid(message: String) -> PasError {
+    PasError::InvalidFormat(message)
+}
+
+fn symbol_label(symbol: usize, n_tracks: usize) -> String {
+    let bits: String = (0..n_tracks).map(|i| if (symbol >> i) & 1 == 1 { '1' } else { '0' }).collect();
+    format!("s{}", bits)
+}
+
+/* Returns (symbol, number of tracks) */
+fn parse_symbol_label(label: &str) -> PasResult<(usize, usize)> {
+    let bits = label.strip_prefix('s').filter(|b| b.chars().all(|c| c == '0' || c == '1'))
+        .ok_or_else(|| invalid(format!("invalid symbol '{}'", label)))?;
+    Ok((bits.chars().enumerate().filter(|(_, c)| *c == '1').map(|(i, _)| 1 << i).sum(), bits.len()))
+}
+
+/* Non-empty transitions (source, symbol, target) */
+fn transitions(nfa: &Nfa) -> Vec<(StateId, usize, StateId)> {
+    let mut result = Vec::new();
+    for s in 0..nfa.n_states() as StateId {
+        for a in 0..nfa.alphabet_size() {
+            result.extend(nfa.get_transition(s, a).states.iter().map(|t| (s, a, *t)));
+        }
+    }
+    result
+}
+
+fn sorted_initial_states(nfa: &Nfa) -> Vec<StateId> {
+    let mut init: Vec<StateId> = nfa.initial_states().iter().copied().collect();
+    init.sort_unstable();
+    init
+}
+
+/* Track names are used only by HOA */
+pub fn export_nfa(nfa: &Nfa, format: AutomatonFormat, track_names: &[String]) -> String {
+    match format {
+        AutomatonFormat::Timbuk => export_timbuk(nfa),
+        AutomatonFormat::Ba => export_ba(nfa),
+        AutomatonFormat::Hoa => export_hoa(nfa, track_names),
+    }
+}
+
+/* Imported automaton and names of tracks (only HOA has them) */
+pub fn import_nfa(text: &str, format: AutomatonFormat) -> PasResult<(Nfa, Vec<String>)> {
+    match format {
+        AutomatonFormat::Timbuk => import_timbuk(text).map(|nfa| (nfa, Vec::new())),
+        AutomatonFormat::Ba => import_ba(text).map(|nfa| (nfa, Vec::new())),
+        AutomatonFormat::Hoa => import_hoa(text),
+    }
+}
+
+/* Builds automaton from explicitly numbered states */
+struct NfaBuilder {
+    n_tracks: usize,
+    states: HashMap<String, StateId>,
+    transitions: Vec<(StateId, usize, StateId)>,
+    initial: HashSet<StateId>,
+    accepting: HashSet<StateId>,
+}
+
+impl NfaBuilder {
+    fn new(n_tracks: usize) -> Self {
+        NfaBuilder { n_tracks, states: HashMap::new(), transitions: Vec::new(), initial: HashSet::new(), accepting: HashSet::new() }
+    }
+
+    fn state(&mut self, name: &str) -> StateId {
+        let next = self.states.len() as StateId;
+        *self.states.entry(name.to_string()).or_insert(next)
+    }
+
+    fn build(self) -> Nfa {
+        let n_states = self.states.len().max(1);
+        let mut table = vec![Transition::empty(); n_states << self.n_tracks];
+        for (s, a, t) in &self.transitions {
+            let (s, t) = (*s, *t);
+            let states = &mut table[((s as usize) << self.n_tracks) + *a].states;
+            if !states.contains(&t) {
+                states.push(t);
+            }
+        }
+        let accepting = (0..n_states as StateId).map(|s| self.accepting.contains(&s)).collect();
+        Nfa::new(TransitionTable::new(self.n_tracks, table), accepting, self.initial)
+    }
+}
+
+fn export_timbuk(nfa: &Nfa) -> String {
+    let n_tracks = nfa.n_tracks();
+    let mut out = String::from("Ops x:0");
+    for a in 0..nfa.alphabet_size() {
+        write!(out, " {}:1", symbol_label(a, n_tracks)).unwrap();
+    }
+    out.push_str("\n\nAutomaton A\nStates");
+    for s in 0..nfa.n_states() {
+        write!(out, " q{}", s).unwrap();
+    }
+    out.push_str("\nFinal States");
+    for s in (0..nfa.n_states() as StateId).filter(|s| nfa.is_accepting(*s)) {
+        write!(out, " q{}", s).unwrap();
+    }
+    out.push_str("\nTransitions\n");
+    for s in sorted_initial_states(nfa) {
+        writeln!(out, "x -> q{}", s).unwrap();
+    }
+    for (s, a, t) in transitions(nfa) {
+        writeln!(out, "{}(q{}) -> q{}", symbol_label(a, n_tracks), s, t).unwrap();
+    }
+    out
+}
+
+/* States may be annotated by arity, e.g. "q:0" */
+fn timbuk_state(token: &str) -> &str {
+    token.split(':').next().unwrap()
+}
+
+fn import_timbuk(text: &str) -> PasResult<Nfa> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let position = |keyword: &str| words.iter().position(|w| *w == keyword).ok_or_else(|| invalid(format!("missing '{}'", keyword)));
+    let (ops, automaton, states, transitions) = (position("Ops")?, position("Automaton")?, position("States")?, position("Transitions")?);
+    let finals = words.iter().position(|w| *w == "Final").filter(|f| words.get(f + 1) == Some(&"States"))
+        .ok_or_else(|| invalid("missing 'Final States'".to_string()))?;
+    if !(ops < automaton && automaton < states && states < finals && finals < transitions) {
+        return Err(invalid("unexpected order of sections".to_string()));
+    }
+
+    let mut leaves = HashSet::new();
+    let mut n_tracks = None;
+    for op in &words[ops + 1..automaton] {
+        let (name, arity) = op.split_once(':').ok_or_else(|| invalid(format!("invalid symbol '{}'", op)))?;
+        match arity {
+            "0" => {
+                leaves.insert(name);
+            }
+            "1" => {
+                let (_, n) = parse_symbol_label(name)?;
+                if n_tracks.is_some_and(|m| m != n) {
+                    return Err(invalid(format!("symbol '{}' has a different number of tracks", name)));
+                }
+                n_tracks = Some(n);
+            }
+            _ => return Err(invalid(format!("symbol '{}' is not of arity 0 or 1", name))),
+        }
+    }
+
+    let mut builder = NfaBuilder::new(n_tracks.unwrap_or(0));
+    for s in &words[states + 1..finals] {
+        builder.state(timbuk_state(s));
+    }
+    for s in &words[finals + 2..transitions] {
+        let s = builder.state(timbuk_state(s));
+        builder.accepting.insert(s);
+    }
+    /* Transitions are "x -> q" or "a(q) -> p" */
+    let rest = &words[transitions + 1..];
+    if !rest.len().is_multiple_of(3) {
+        return Err(invalid("incomplete transition".to_string()));
+    }
+    for tr in rest.chunks(3) {
+        if tr[1] != "->" {
+            return Err(invalid(format!("invalid transition '{}'", tr.join(" "))));
+        }
+        let target = builder.state(timbuk_state(tr[2]));
+        if leaves.contains(tr[0]) {
+            builder.initial.insert(target);
+            continue;
+        }
+        let (symbol, source) = tr[0].strip_suffix(')').and_then(|t| t.split_once('('))
+            .ok_or_else(|| invalid(format!("invalid transition '{}'", tr.join(" "))))?;
+        let (a, n) = parse_symbol_label(symbol)?;
+        if n != builder.n_tracks {
+            return Err(invalid(format!("symbol '{}' is not declared", symbol)));
+        }
+        let source = builder.state(timbuk_state(source));
+        builder.transitions.push((source, a, target));
+    }
+    Ok(builder.build())
+}
+
+/* BA format allows a single initial state, a new one is added when needed */
+fn export_ba(nfa: &Nfa) -> String {
+    let n_tracks = nfa.n_tracks();
+    let init = sorted_initial_states(nfa);
+    let mut out = String::new();
+    let mut transitions = transitions(nfa);
+    let initial = if init.len() == 1 {
+        init[0]
+    } else {
+        let new = nfa.n_states() as StateId;
+        let mut from_init: Vec<_> = transitions.iter().filter(|(s, _, _)| init.contains(s)).map(|(_, a, t)| (new, *a, *t)).collect();
+        from_init.sort_unstable();
+        from_init.dedup();
+        transitions.extend(from_init);
+        new
+    };
+    writeln!(out, "[{}]", initial).unwrap();
+    for (s, a, t) in transitions {
+        writeln!(out, "{},[{}]->[{}]", symbol_label(a, n_tracks), s, t).unwrap();
+    }
+    for s in (0..nfa.n_states() as StateId).filter(|s| nfa.is_accepting(*s)) {
+        writeln!(out, "[{}]", s).unwrap();
+    }
+    if initial as usize == nfa.n_states() && init.iter().any(|s| nfa.is_accepting(*s)) {
+        writeln!(out, "[{}]", initial).unwrap();
+    }
+    out
+}
+
+fn ba_state(token: &str) -> &str {
+    let token = token.trim();
+    token.strip_prefix('[').and_then(|t| t.strip_suffix(']')).unwrap_or(token)
+}
+
+/* State lines before transitions are initial states, the lines after them are final states */
+fn import_ba(text: &str) -> PasResult<Nfa> {
+    let lines: Vec<&str> = text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+    let mut parsed = Vec::new();
+    let mut n_tracks = None;
+    for line in &lines {
+        match line.split_once("->") {
+            Some((lhs, target)) => {
+                let (symbol, source) = lhs.split_once(',').ok_or_else(|| invalid(format!("invalid transition '{}'", line)))?;
+                let (a, n) = parse_symbol_label(symbol.trim())?;
+                if n_tracks.is_some_and(|m| m != n) {
+                    return Err(invalid(format!("symbol '{}' has a different number of tracks", symbol)));
+                }
+                n_tracks = Some(n);
+                parsed.push(Some((ba_state(source), a, ba_state(target))));
+            }
+            None => parsed.push(None),
+        }
+    }
+    let mut builder = NfaBuilder::new(n_tracks.unwrap_or(0));
+    let mut after_transitions = false;
+    for (line, tr) in lines.iter().zip(parsed) {
+        match tr {
+            Some((source, a, target)) => {
+                after_transitions = true;
+                let (s, t) = (builder.state(source), builder.state(target));
+                builder.transitions.push((s, a, t));
+            }
+            None => {
+                let s = builder.state(ba_state(line));
+                if after_transitions {
+                    builder.accepting.insert(s);
+                } else {
+                    builder.initial.insert(s);
+                }
+            }
+        }
+    }
+    if builder.initial.is_empty() {
+        /* Rabit convention: the source of the first transition is initial */
+        if let Some((s, _, _)) = builder.transitions.first() {
+            builder.initial.insert(*s);
+        }
+    }
+    Ok(builder.build())
+}
+
+fn hoa_label(symbol: usize, n_tracks: usize) -> String {
+    if n_tracks == 0 {
+        return "t".to_string();
+    }
+    (0..n_tracks).map(|i| format!("{}{}", if (symbol >> i) & 1 == 1 { "" } else { "!" }, i)).collect::<Vec<_>>().join("&")
+}
+
+fn export_hoa(nfa: &Nfa, track_names: &[String]) -> String {
+    let n_tracks = nfa.n_tracks();
+    assert_eq!(track_names.len(), n_tracks);
+    let mut out = String::from("HOA: v1\n");
+    writeln!(out, "States: {}", nfa.n_states()).unwrap();
+    for s in sorted_initial_states(nfa) {
+        writeln!(out, "Start: {}", s).unwrap();
+    }
+    write!(out, "AP: {}", n_tracks).unwrap();
+    for name in track_names {
+        write!(out, " \"{}\"", name).unwrap();
+    }
+    out.push_str("\nAcceptance: 1 t\nproperties: state-acc explicit-labels\n--BODY--\n");
+    for s in 0..nfa.n_states() as StateId {
+        writeln!(out, "State: {}{}", s, if nfa.is_accepting(s) { " {0}" } else { "" }).unwrap();
+        for a in 0..nfa.alphabet_size() {
+            for t in &nfa.get_transition(s, a).states {
+                writeln!(out, "[{}] {}", hoa_label(a, n_tracks), t).unwrap();
+            }
+        }
+    }
+    out.push_str("--END--\n");
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum HoaToken {
+    Key(String),
+    Word(String),
+    Str(String),
+    Label(String),
+    AccSets(Vec<usize>),
+}
+
+fn hoa_tokens(text: &str) -> PasResult<Vec<HoaToken>> {
+    fn take_until(chars: &mut Peekable<Chars>, end: char) -> PasResult<String> {
+        let mut s = String::new();
+        loop {
+            match chars.next() {
+                Some(c) if c == end => return Ok(s),
+                Some(c) => s.push(c),
+                None => return Err(invalid(format!("missing '{}'", end))),
+            }
+        }
+    }
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => tokens.push(HoaToken::Str(take_until(&mut chars, '"')?)),
+            '[' => tokens.push(HoaToken::Label(take_until(&mut chars, ']')?)),
+            '{' => {
+                let sets = take_until(&mut chars, '}')?;
+                let sets = sets.split_whitespace().map(|s| s.parse().map_err(|_| invalid(format!("invalid acceptance set '{}'", s))));
+                tokens.push(HoaToken::AccSets(sets.collect::<PasResult<_>>()?));
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                while !(chars.next() == Some('*') && chars.peek() == Some(&'/')) {
+                    if chars.peek().is_none() {
+                        return Err(invalid("unterminated comment".to_string()));
+                    }
+                }
+                chars.next();
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek().filter(|c| !c.is_whitespace() && !"\"[{".contains(**c)) {
+                    word.push(*c);
+                    chars.next();
+                }
+                tokens.push(match word.strip_suffix(':') {
+                    Some(key) if !word.starts_with("--") => HoaToken::Key(key.to_string()),
+                    _ => HoaToken::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/* Boolean label over atomic propositions: t, f, numbers, !, &, |, parentheses */
+fn eval_label(label: &str, symbol: usize) -> PasResult<bool> {
+    fn expr(input: &[u8], pos: &mut usize, symbol: usize) -> PasResult<bool> {
+        let mut value = conj(input, pos, symbol)?;
+        while skip(input, pos) == Some(b'|') {
+            *pos += 1;
+            value |= conj(input, pos, symbol)?;
+        }
+        Ok(value)
+    }
+    fn conj(input: &[u8], pos: &mut usize, symbol: usize) -> PasResult<bool> {
+        let mut value = atom(input, pos, symbol)?;
+        while skip(input, pos) == Some(b'&') {
+            *pos += 1;
+            value &= atom(input, pos, symbol)?;
+        }
+        Ok(value)
+    }
+    fn atom(input: &[u8], pos: &mut usize, symbol: usize) -> PasResult<bool> {
+        let error = || invalid(format!("invalid label '{}'", String::from_utf8_lossy(input)));
+        match skip(input, pos) {
+            Some(b'!') => {
+                *pos += 1;
+                Ok(!atom(input, pos, symbol)?)
+            }
+            Some(b'(') => {
+                *pos += 1;
+                let value = expr(input, pos, symbol)?;
+                if skip(input, pos) != Some(b')') {
+                    return Err(error());
+                }
+                *pos += 1;
+                Ok(value)
+            }
+            Some(b't') | Some(b'f') => {
+                *pos += 1;
+                Ok(input[*pos - 1] == b't')
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let start = *pos;
+                while *pos < input.len() && input[*pos].is_ascii_digit() {
+                    *pos += 1;
+                }
+                let ap: usize = std::str::from_utf8(&input[start..*pos]).unwrap().parse().map_err(|_| error())?;
+                Ok(ap < usize::BITS as usize && (symbol >> ap) & 1 == 1)
+            }
+            _ => Err(error()),
+        }
+    }
+    fn skip(input: &[u8], pos: &mut usize) -> Option<u8> {
+        while *pos < input.len() && input[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        input.get(*pos).copied()
+    }
+    let input = label.as_bytes();
+    let mut pos = 0;
+    let value = expr(input, &mut pos, symbol)?;
+    if skip(input, &mut pos).is_some() {
+        return Err(invalid(format!("invalid label '{}'", label)));
+    }
+    Ok(value)
+}
+
+/* Supported subset: explicit labels, state-based acceptance "Inf(0)" or "t" */
+fn import_hoa(text: &str) -> PasResult<(Nfa, Vec<String>)> {
+    let tokens = hoa_tokens(text)?;
+    let body = tokens.iter().position(|t| *t == HoaToken::Word("--BODY--".to_string()))
+        .ok_or_else(|| invalid("missing '--BODY--'".to_string()))?;
+    let mut headers: Vec<(&str, Vec<&HoaToken>)> = Vec::new();
+    for token in &tokens[..body] {
+        match token {
+            HoaToken::Key(key) => headers.push((key, Vec::new())),
+            token => headers.last_mut().ok_or_else(|| invalid("missing 'HOA' header".to_string()))?.1.push(token),
+        }
+    }
+    if headers.first().map(|h| h.0) != Some("HOA") {
+        return Err(invalid("missing 'HOA' header".to_string()));
+    }
+    let word = |token: &HoaToken| -> PasResult<String> {
+        match token {
+            HoaToken::Word(w) => Ok(w.clone()),
+            t => Err(invalid(format!("unexpected {:?}", t))),
+        }
+    };
+    let number = |token: &HoaToken| -> PasResult<usize> {
+        word(token)?.parse().map_err(|_| invalid(format!("number expected, got {:?}", token)))
+    };
+
+    let mut names = Vec::new();
+    let mut start = Vec::new();
+    let mut all_final = false;
+    for (key, values) in &headers {
+        match *key {
+            "AP" => {
+                names = values.iter().skip(1).map(|v| match v {
+                    HoaToken::Str(s) => Ok(s.clone()),
+                    t => Err(invalid(format!("AP name expected, got {:?}", t))),
+                }).collect::<PasResult<_>>()?;
+                if values.is_empty() || number(values[0])? != names.len() {
+                    return Err(invalid("number of APs does not match".to_string()));
+                }
+            }
+            "Start" => {
+                if values.len() != 1 {
+                    return Err(invalid("conjunction of initial states is not supported".to_string()));
+                }
+                start.push(number(values[0])?);
+            }
+            "Acceptance" => {
+                let condition: Vec<String> = values.iter().map(|v| word(v)).collect::<PasResult<_>>()?;
+                match condition.iter().map(|s| s.as_str()).collect::<Vec<_>>().as_slice() {
+                    ["1", "Inf(0)"] | ["1", "t"] => all_final = false,
+                    ["0", "t"] => all_final = true,
+                    _ => return Err(invalid(format!("acceptance '{}' is not supported", condition.join(" ")))),
+                }
+            }
+            "Alias" => return Err(invalid("aliases are not supported".to_string())),
+            _ => { /* Other headers do not change the language */ }
+        }
+    }
+
+    let mut builder = NfaBuilder::new(names.len());
+    for s in start {
+        let s = builder.state(&s.to_string());
+        builder.initial.insert(s);
+    }
+    let mut current = None;
+    let mut i = body + 1;
+    while i < tokens.len() {
+        match &tokens[i] {
+            HoaToken::Key(key) if key == "State" => {
+                let label = tokens.get(i + 1).map(number).unwrap_or_else(|| Err(invalid("missing state".to_string())))?;
+                let s = builder.state(&label.to_string());
+                current = Some(s);
+                i += 2;
+                if let Some(HoaToken::Str(_)) = tokens.get(i) {
+                    i += 1;
+                }
+                if let Some(HoaToken::AccSets(sets)) = tokens.get(i) {
+                    if sets.contains(&0) {
+                        builder.accepting.insert(s);
+                    }
+                    i += 1;
+                }
+                if all_final {
+                    builder.accepting.insert(s);
+                }
+            }
+            HoaToken::Label(label) => {
+                let source = current.ok_or_else(|| invalid("edge outside of a state".to_string()))?;
+                let target = tokens.get(i + 1).map(number).unwrap_or_else(|| Err(invalid("missing target".to_string())))?;
+                let target = builder.state(&target.to_string());
+                i += 2;
+                if let Some(HoaToken::AccSets(sets)) = tokens.get(i) {
+                    if !sets.is_empty() {
+                        return Err(invalid("transition-based acceptance is not supported".to_string()));
+                    }
+                    i += 1;
+                }
+                for a in 0..1 << builder.n_tracks {
+                    if eval_label(label, a)? {
+                        builder.transitions.push((source, a, target));
+                    }
+                }
+            }
+            HoaToken::Word(w) if w == "--END--" => return Ok((builder.build(), names)),
+            t => return Err(invalid(format!("unexpected {:?} in body (implicit labels are not supported)", t))),
+        }
+    }
+    Err(invalid("missing '--END--'".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlevel::parser::parse_setdef;
+    use crate::solver::commands::build_set;
+
+    use super::*;
+
+    fn nfa(setdef: &str) -> Nfa {
+        build_set(&parse_setdef(setdef).unwrap()).unwrap().into_nfa()
+    }
+
+    fn equivalent(a: &Nfa, b: &Nfa) -> bool {
+        a.n_tracks() == b.n_tracks() && a.is_included(b) && b.is_included(a)
+    }
+
+    #[test]
+    fn test_formats_roundtrip() {
+        let mut union = nfa("{ x, y | x + 2 == y }");
+        union.join(&nfa("{ x, y | x == 3 * y }"));
+        let automata = [nfa("{ x, y | x < y and x % 3 == 1 }"), nfa("{ | exists(x)(x > 3) }"), nfa("{ | x < 0 }"), union];
+        for a in &automata {
+            for format in &[AutomatonFormat::Timbuk, AutomatonFormat::Ba, AutomatonFormat::Hoa] {
+                let names: Vec<String> = (0..a.n_tracks()).map(|i| format!("t{}", i)).collect();
+                let text = export_nfa(a, *format, &names);
+                let (b, imported_names) = import_nfa(&text, *format).unwrap();
+                assert!(equivalent(a, &b), "{:?}\n{}", format, text);
+                if *format == AutomatonFormat::Hoa {
+                    assert_eq!(imported_names, names);
+                    assert!(text.contains("Acceptance: 1 t\n") && !text.contains("Inf"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_import_external() {
+        /* x is even (bits are read MSB-first) */
+        let expected = nfa("{ x | exists(y)(x == 2 * y) }");
+        let timbuk = "Ops a:0 s0:1 s1:1\nAutomaton even\nStates p:0 q:0\nFinal States p:0\nTransitions\na -> p\ns0(p) -> p s1(p) -> q\ns0(q) -> p\ns1(q) -> q\n";
+        assert!(equivalent(&import_nfa(timbuk, AutomatonFormat::Timbuk).unwrap().0, &expected));
+
+        let ba = "[p]\ns0,[p]->[p]\ns1,[p]->[q]\ns0,[q]->[p]\ns1,[q]->[q]\n[p]\n";
+        assert!(equivalent(&import_nfa(ba, AutomatonFormat::Ba).unwrap().0, &expected));
+
+        let hoa = "HOA: v1 /* from another tool */\nname: \"even\"\nStates: 2\nStart: 0\nAP: 1 \"x\"\n\
+                   acc-name: Buchi\nAcceptance: 1 Inf(0)\nproperties: trans-labels explicit-labels state-acc\n\
+                   --BODY--\nState: 0 \"zero\" {0}\n[!0] 0\n[0] 1\nState: 1\n[t & (0 | f)] 1\n[!(0)] 0\n--END--\n";
+        let (a, names) = import_nfa(hoa, AutomatonFormat::Hoa).unwrap();
+        assert_eq!(names, vec!["x".to_string()]);
+        assert!(equivalent(&a, &expected));
+
+        for (text, format) in &[("Ops s0:1\nAutomaton A\nStates q\nTransitions\n", AutomatonFormat::Timbuk),
+                                ("[p]\ns01,[p]->[p]\ns1,[p]->[q]\n", AutomatonFormat::Ba),
+                                ("HOA: v1\nStates: 1\nStart: 0\nAP: 0\nAcceptance: 2 Inf(0)&Inf(1)\n--BODY--\n--END--\n", AutomatonFormat::Hoa),
+                                ("HOA: v1\nStart: 0\nAP: 1 \"x\"\nAcceptance: 1 Inf(0)\n--BODY--\nState: 0\n[0 &] 0\n--END--\n", AutomatonFormat::Hoa),
+                                ("HOA: v1\nStart: 0\nAP: 0\nAcceptance: 1 Inf(0)\n--BODY--\nState: 0\n[t] 0 {0}\n--END--\n", AutomatonFormat::Hoa)] {
+            assert!(matches!(import_nfa(text, *format), Err(PasError::InvalidFormat(_))), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::common::StateId;
+
+use super::Dfa;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Minimizer {
+    /// Hopcroft's partition refinement, O(n·k·log n)
+    Hopcroft,
+    /// Valmari-Lehtinen refinement of blocks and transitions; transitions into
+    /// states that cannot reach an accepting state are ignored (partial DFA)
+    Valmari,
+    /// Moore-style iterated refinement, kept for cross-checking
+    Moore,
+}
+
+/* Refinable partition of 0..n; sets are contiguous ranges in "elements",
+   marked elements are moved to the beginning of their set */
+struct Partition {
+    elements: Vec<usize>,
+    location: Vec<usize>,
+    set_of: Vec<usize>,
+    first: Vec<usize>,
+    past: Vec<usize>,
+    marked: Vec<usize>,
+    touched: Vec<usize>,
+}
+
+impl Partition {
+    fn new(n: usize) -> Self {
+        Partition {
+            elements: (0..n).collect(),
+            location: (0..n).collect(),
+            set_of: vec![0; n],
+            first: if n > 0 { vec![0] } else { Vec::new() },
+            past: if n > 0 { vec![n] } else { Vec::new() },
+            marked: if n > 0 { vec![0] } else { Vec::new() },
+            touched: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn n_sets(&self) -> usize {
+        self.first.len()
+    }
+
+    #[inline]
+    fn set_elements(&self, set: usize) -> &[usize] {
+        &self.elements[self.first[set]..self.past[set]]
+    }
+
+    fn mark(&mut self, e: usize) {
+        let s = self.set_of[e];
+        let i = self.location[e];
+        let j = self.first[s] + self.marked[s];
+        if i < j {
+            /* Already marked */
+            return;
+        }
+        self.elements[i] = self.elements[j];
+        self.location[self.elements[i]] = i;
+        self.elements[j] = e;
+        self.location[e] = j;
+        if self.marked[s] == 0 {
+            self.touched.push(s);
+        }
+        self.marked[s] += 1;
+    }
+
+    /* Splits touched sets into marked and unmarked parts; the smaller part gets a new id.
+       Calls "on_split(old, new)" for each split */
+    fn split<F: FnMut(usize, usize)>(&mut self, mut on_split: F) {
+        while let Some(s) = self.touched.pop() {
+            let j = self.first[s] + self.marked[s];
+            self.marked[s] = 0;
+            if j == self.past[s] {
+                continue;
+            }
+            let z = self.first.len();
+            if j - self.first[s] <= self.past[s] - j {
+                self.first.push(self.first[s]);
+                self.past.push(j);
+                self.first[s] = j;
+            } else {
+                self.first.push(j);
+                self.past.push(self.past[s]);
+                self.past[s] = j;
+            }
+            self.marked.push(0);
+            for i in self.first[z]..self.past[z] {
+                self.set_of[self.elements[i]] = z;
+            }
+            on_split(s, z);
+        }
+    }
+}
+
+/* States grouped by (target state, symbol): sources[start[t * asize + a]..start[t * asize + a + 1]] */
+fn inverse_transitions(dfa: &Dfa) -> (Vec<usize>, Vec<StateId>) {
+    let asize = dfa.alphabet_size();
+    let n = dfa.n_states();
+    let mut start = vec![0; n * asize + 1];
+    for row in dfa.rows() {
+        for (a, t) in row.iter().enumerate() {
+            start[*t as usize * asize + a + 1] += 1;
+        }
+    }
+    for i in 1..start.len() {
+        start[i] += start[i - 1];
+    }
+    let mut fill = start.clone();
+    let mut sources = vec![0; n * asize];
+    for (s, row) in dfa.rows().enumerate() {
+        for (a, t) in row.iter().enumerate() {
+            let idx = *t as usize * asize + a;
+            sources[fill[idx]] = s as StateId;
+            fill[idx] += 1;
+        }
+    }
+    (start, sources)
+}
+
+fn initial_partition(dfa: &Dfa) -> Partition {
+    let mut partition = Partition::new(dfa.n_states());
+    for (s, acc) in dfa.accepting().iter().enumerate() {
+        if *acc {
+            partition.mark(s);
+        }
+    }
+    partition.split(|_, _| {});
+    partition
+}
+
+/* Block id of each state, blocks are numbered by their first state */
+fn block_ids(partition: &Partition) -> Vec<StateId> {
+    let mut ids = vec![StateId::MAX; partition.n_sets()];
+    let mut next = 0;
+    partition.set_of.iter().map(|s| {
+        if ids[*s] == StateId::MAX {
+            ids[*s] = next;
+            next += 1;
+        }
+        ids[*s]
+    }).collect()
+}
+
+/* Partition refinements call "check" with the number of blocks once per round (see Dfa::minimize_checked) */
+pub(super) fn hopcroft_partitions<E, F: FnMut(usize) -> Result<(), E>>(dfa: &Dfa, check: &mut F) -> Result<Vec<StateId>, E> {
+    let asize = dfa.alphabet_size();
+    let (start, sources) = inverse_transitions(dfa);
+    let mut partition = initial_partition(dfa);
+
+    /* Waiting splitters (block, symbol) */
+    let mut waiting = Vec::new();
+    if partition.n_sets() == 2 {
+        /* The new block is the smaller one */
+        for a in 0..asize {
+            waiting.push((1, a));
+        }
+    }
+
+    let mut splitter = Vec::new();
+    while let Some((block, a)) = waiting.pop() {
+        check(partition.n_sets())?;
+        splitter.clear();
+        splitter.extend_from_slice(partition.set_elements(block));
+        for t in &splitter {
+            let idx = t * asize + a;
+            for s in &sources[start[idx]..start[idx + 1]] {
+                partition.mark(*s as usize);
+            }
+        }
+        /* If (old, c) waits, both parts have to wait; otherwise the smaller one is enough.
+           The new block is always the smaller one, so it is added in both cases */
+        partition.split(|_old, new| {
+            for c in 0..asize {
+                waiting.push((new, c));
+            }
+        });
+    }
+    Ok(block_ids(&partition))
+}
+
+pub(super) fn valmari_partitions<E, F: FnMut(usize) -> Result<(), E>>(dfa: &Dfa, check: &mut F) -> Result<Vec<StateId>, E> {
+    let asize = dfa.alphabet_size();
+    let n = dfa.n_states();
+
+    /* States that can reach an accepting state; other transitions are ignored */
+    let mut live = dfa.accepting().clone();
+    let reverse = dfa.reverse_reachability();
+    let mut stack: Vec<usize> = (0..n).filter(|s| live[*s]).collect();
+    while let Some(s) = stack.pop() {
+        for p in &reverse[s] {
+            if !live[*p as usize] {
+                live[*p as usize] = true;
+                stack.push(*p as usize);
+            }
+        }
+    }
+
+    /* Transitions (tail, label, head) sorted by label */
+    let mut transitions: Vec<(usize, usize, usize)> = Vec::new();
+    for a in 0..asize {
+        for (s, row) in dfa.rows().enumerate() {
+            if live[row[a] as usize] {
+                transitions.push((s, a, row[a] as usize));
+            }
+        }
+    }
+
+    /* Incoming transitions of each state */
+    let mut in_start = vec![0; n + 1];
+    for (_, _, h) in &transitions {
+        in_start[h + 1] += 1;
+    }
+    for i in 1..in_start.len() {
+        in_start[i] += in_start[i - 1];
+    }
+    let mut fill = in_start.clone();
+    let mut incoming = vec![0; transitions.len()];
+    for (i, (_, _, h)) in transitions.iter().enumerate() {
+        incoming[fill[*h]] = i;
+        fill[*h] += 1;
+    }
+
+    let mut blocks = initial_partition(dfa);
+
+    /* Cords: transitions with the same label */
+    let mut cords = Partition::new(transitions.len());
+    if !transitions.is_empty() {
+        cords.first.clear();
+        cords.past.clear();
+        cords.marked.clear();
+        for (i, (_, a, _)) in transitions.iter().enumerate() {
+            if i == 0 || transitions[i - 1].1 != *a {
+                if i > 0 {
+                    cords.past.push(i);
+                }
+                cords.first.push(i);
+                cords.marked.push(0);
+            }
+            cords.set_of[i] = cords.first.len() - 1;
+        }
+        cords.past.push(transitions.len());
+    }
+
+    let mut b = 0;
+    let mut c = 0;
+    let mut tmp = Vec::new();
+    while c < cords.n_sets() {
+        check(blocks.n_sets())?;
+        tmp.clear();
+        tmp.extend(cords.set_elements(c).iter().map(|t| transitions[*t].0));
+        for s in &tmp {
+            blocks.mark(*s);
+        }
+        blocks.split(|_, _| {});
+        c += 1;
+        while b < blocks.n_sets() {
+            tmp.clear();
+            for s in blocks.set_elements(b) {
+                tmp.extend_from_slice(&incoming[in_start[*s]..in_start[*s + 1]]);
+            }
+            for t in &tmp {
+                cords.mark(*t);
+            }
+            cords.split(|_, _| {});
+            b += 1;
+        }
+    }
+    Ok(block_ids(&blocks))
+}
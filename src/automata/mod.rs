@@ -1,12 +1,23 @@
+pub use antichain::Subsumption;
 pub use automaton::Automaton;
 pub use dfa::Dfa;
+pub use formats::{AutomatonFormat, export_nfa, import_nfa};
+pub use dump::{dump_dfa, dump_nfa, dump_symbolic, DumpConfig, Dumper, DumpStage};
+pub use minimize::Minimizer;
 pub use nfa::Nfa;
 pub use nfa::Transition;
+pub use symbolic::SymbolicDfa;
 pub use table::TransitionTable;
 pub use words::{Bound, longest_words, number_of_words, number_of_words_next_length, number_of_words_zero_length, shortest_words};
 
 mod table;
 mod dfa;
+mod minimize;
 mod nfa;
 mod automaton;
 mod words;
+mod bdd;
+mod symbolic;
+mod antichain;
+mod dump;
+mod formats;
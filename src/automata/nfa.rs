@@ -1,3 +1,4 @@
+use std::convert::Infallible;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -7,7 +8,7 @@ use itertools::Itertools;
 
 use smallvec::{smallvec, SmallVec};
 
-use crate::common::{StateId, StateSet};
+use crate::common::{StateId, StateSet, StateSetBuilder};
 
 use super::Dfa;
 use super::TransitionTable;
@@ -82,6 +83,22 @@ impl Nfa {
         &self.initial_states
     }
 
+    #[inline]
+    pub fn is_accepting(&self, state: StateId) -> bool {
+        self.accepting[state as usize]
+    }
+
+    #[inline]
+    pub fn get_transition(&self, state: StateId, symbol: usize) -> &Transition {
+        self.table.get_transition(state, symbol)
+    }
+
+    /* Accepts all words */
+    pub fn universal(n_tracks: usize) -> Self {
+        let table = TransitionTable::new(n_tracks, vec![Transition::simple(0); 1 << n_tracks]);
+        Nfa::new(table, vec![true], Nfa::simple_init())
+    }
+
     pub fn join(&mut self, other: &Nfa) {
         let shift = self.n_states() as StateId;
         self.table.join(&other.table);
@@ -94,39 +111,47 @@ impl Nfa {
     }
 
 
+    /* Subset construction; subsets are interned into ids in the order of discovery */
     pub fn determinize(&self) -> Dfa {
-        let asize = self.alphabet_size();
-        let mut map = HashMap::new();
-        let init = self.initial_states.clone();
-        map.insert(StateSet::new(init.clone()), 0);
+        self.determinize_with(|_| Ok::<(), Infallible>(())).unwrap()
+    }
 
-        let mut transitions = vec![0; asize];
+    /* Subset construction that stops when "check" fails; it gets the number of created states */
+    pub fn determinize_with<E, F: FnMut(usize) -> Result<(), E>>(&self, mut check: F) -> Result<Dfa, E> {
+        let asize = self.alphabet_size();
+        let mut builder = StateSetBuilder::new(self.n_states());
+        builder.extend(self.initial_states.iter().copied());
+        let init = builder.take();
+
+        let mut map: HashMap<StateSet, StateId> = HashMap::new();
+        map.insert(init.clone(), 0);
+        let mut sets = vec![init];
+        let mut transitions = Vec::new();
         let mut accepting = Vec::new();
-        accepting.push(init.iter().any(|s| self.accepting[*s as usize]));
 
-        let mut stack = vec![(0, init)];
-        let mut new_id = 0;
-
-        while let Some((s_id, state)) = stack.pop() {
+        let mut i = 0;
+        while i < sets.len() {
+            accepting.push(sets[i].as_slice().iter().any(|s| self.accepting[*s as usize]));
             for a in 0..asize {
-                let mut new_state = HashSet::new();
-                for s in &state {
-                    new_state.extend(self.table.get_transition(*s, a).states.iter());
+                for s in sets[i].as_slice() {
+                    builder.extend(self.table.get_transition(*s, a).states.iter().copied());
                 }
-                let fs = StateSet::new(new_state);
-                let id = map.get(&fs).map(|x| *x).unwrap_or_else(|| {
-                    let new_state: HashSet<StateId> = fs.inner().clone();
-                    new_id += 1;
-                    map.insert(fs, new_id);
-                    accepting.push(new_state.iter().any(|s| self.accepting[*s as usize]));
-                    transitions.resize(transitions.len() + asize, 0 as StateId);
-                    stack.push((new_id, new_state));
-                    new_id
-                });
-                transitions[s_id * asize + a] = id as StateId;
+                let set = builder.take();
+                let id = match map.get(&set) {
+                    Some(id) => *id,
+                    None => {
+                        let id = sets.len() as StateId;
+                        map.insert(set.clone(), id);
+                        sets.push(set);
+                        check(sets.len())?;
+                        id
+                    }
+                };
+                transitions.push(id);
             }
+            i += 1;
         }
-        Dfa::new(TransitionTable::new(self.n_tracks(), transitions), accepting)
+        Ok(Dfa::new(TransitionTable::new(self.n_tracks(), transitions), accepting))
     }
 
     #[inline]
@@ -200,6 +225,59 @@ impl Nfa {
         }
     }
 
+    /* Accepts also words prefixed by any number of zero symbols; together with zero_prefix_fix
+       the language does not depend on padding */
+    pub fn zero_prefix_closure(&mut self) {
+        if self.n_tracks() == 0 {
+            return;
+        }
+        let new = self.n_states() as StateId;
+        let mut row = vec![Transition::empty(); self.alphabet_size()];
+        for s in &self.initial_states {
+            for (a, t) in self.table.get_row(*s).iter().enumerate() {
+                for t in &t.states {
+                    if !row[a].states.contains(t) {
+                        row[a].states.push(*t);
+                    }
+                }
+            }
+        }
+        row[0].states.push(new);
+        self.accepting.push(self.initial_states.iter().any(|s| self.accepting[*s as usize]));
+        self.table.push_row(&row);
+        self.initial_states.clear();
+        self.initial_states.insert(new);
+    }
+
+    /* Two's complement counterpart of zero_prefix_fix: "aaw" and "aw" encode the same
+       values, so a new initial state skips any number of repeated leading symbols */
+    pub fn sign_extension_fix(&mut self) {
+        let asize = self.alphabet_size();
+        let mut row = Vec::with_capacity(asize);
+        for a in 0..asize {
+            let mut reached = HashSet::new();
+            let mut current = self.initial_states.clone();
+            while !current.is_empty() {
+                let mut next = HashSet::new();
+                for s in &current {
+                    for s2 in &self.table.get_transition(*s, a).states {
+                        if reached.insert(*s2) {
+                            next.insert(*s2);
+                        }
+                    }
+                }
+                current = next;
+            }
+            row.push(Transition::new(reached.into_iter().collect()));
+        }
+        let accepting = self.initial_states.iter().chain(row[0].states.iter()).any(|s| self.accepting[*s as usize]);
+        let new_id = self.n_states() as StateId;
+        self.table.push_row(&row);
+        self.accepting.push(accepting);
+        self.initial_states.clear();
+        self.initial_states.insert(new_id);
+    }
+
     pub fn write_dot(&self, path: &Path, remove_sink: bool) -> std::io::Result<()> {
         let sink: Option<StateId> = if remove_sink {
             self.accepting.iter().enumerate().find(|(i, a)| {
@@ -276,4 +354,27 @@ mod tests {
         assert_eq!(m.n_states(), 3);
         assert_eq!(*m.accepting(), vec![false, false, true]);
     }
+
+    /* Run by "cargo test --release bench_determinize -- --ignored --nocapture" */
+    #[test]
+    #[ignore]
+    fn bench_determinize() {
+        use crate::highlevel::parser::parse_setdef;
+        use crate::solver::commands::build_set;
+
+        let setdefs = [
+            "{ x, y, z | 12345 * x + 45678 * y == 99991 * z + 7 }",
+            "{ x | exists(y)(exists(z)(x == 123 * y + 234 * z)) }",
+            "{ x, y | exists(z)(x == 373 * z + y and y < 373) }",
+            "{ x | not exists(y)(x == 60 * y + 1 or x == 101 * y + 3 or x == 151 * y + 7) }",
+            "{ x | forall(y)(y > x or exists(z)(y == 33 * z + x)) }",
+            "{ x, y | forall(z)(z < x or exists(w)(w + z == y and w % 51 == 2)) }",
+        ];
+        println!("{:<90} {:>10} {:>10}", "set", "time [ms]", "states");
+        for setdef in &setdefs {
+            let start = std::time::Instant::now();
+            let a = build_set(&parse_setdef(setdef).unwrap()).unwrap();
+            println!("{:<90} {:>10.3} {:>10}", setdef, start.elapsed().as_secs_f64() * 1000.0, a.n_states());
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,495 @@
+use hashbrown::HashMap;
+
+use crate::common::StateId;
+
+use super::{Bound, Dfa, dump_symbolic, Dumper, DumpStage, TransitionTable};
+use super::bdd::{Mtbdd, NodeId};
+
+/// DFA whose rows are decision diagrams over track bits (leaves are target states),
+/// so the size does not depend on 2^n_tracks. State 0 is initial.
+#[derive(Debug, Clone)]
+pub struct SymbolicDfa {
+    n_tracks: usize,
+    bdd: Mtbdd,
+    roots: Vec<NodeId>,
+    accepting: Vec<bool>,
+}
+
+/* Interning of sorted state sets */
+#[derive(Default)]
+struct SetTable {
+    sets: Vec<Vec<StateId>>,
+    ids: HashMap<Vec<StateId>, u32>,
+}
+
+impl SetTable {
+    fn intern(&mut self, set: Vec<StateId>) -> u32 {
+        let sets = &mut self.sets;
+        *self.ids.entry(set).or_insert_with_key(|set| {
+            sets.push(set.clone());
+            (sets.len() - 1) as u32
+        })
+    }
+
+    fn union(&mut self, a: u32, b: u32) -> u32 {
+        if a == b {
+            return a;
+        }
+        let mut set = self.sets[a as usize].clone();
+        set.extend_from_slice(&self.sets[b as usize]);
+        set.sort_unstable();
+        set.dedup();
+        self.intern(set)
+    }
+}
+
+impl SymbolicDfa {
+    pub fn from_dfa(dfa: &Dfa) -> Self {
+        let mut bdd = Mtbdd::new();
+        let roots = dfa.rows().map(|row| bdd.row(dfa.n_tracks(), row)).collect();
+        SymbolicDfa {
+            n_tracks: dfa.n_tracks(),
+            bdd,
+            roots,
+            accepting: dfa.accepting().clone(),
+        }
+    }
+
+    /* Explicit automaton; the table has 2^n_tracks columns */
+    pub fn to_dfa(&self) -> Dfa {
+        let asize = 1 << self.n_tracks;
+        let mut transitions = Vec::with_capacity(asize * self.n_states());
+        for root in &self.roots {
+            transitions.extend((0..asize).map(|a| self.bdd.eval(*root, a)));
+        }
+        Dfa::new(TransitionTable::new(self.n_tracks, transitions), self.accepting.clone())
+    }
+
+    #[inline]
+    pub fn n_states(&self) -> usize {
+        self.accepting.len()
+    }
+
+    #[inline]
+    pub fn n_tracks(&self) -> usize {
+        self.n_tracks
+    }
+
+    #[inline]
+    pub fn n_nodes(&self) -> usize {
+        self.bdd.n_nodes()
+    }
+
+    #[inline]
+    pub fn is_accepting(&self, state: StateId) -> bool {
+        self.accepting[state as usize]
+    }
+
+    pub fn target(&self, state: StateId, symbol: usize) -> StateId {
+        self.bdd.eval(self.roots[state as usize], symbol)
+    }
+
+    /* Distinct targets of a state with the number of symbols leading to them */
+    pub fn successors(&self, state: StateId) -> Vec<(StateId, u128)> {
+        self.bdd.leaf_counts(self.roots[state as usize], self.n_tracks)
+    }
+
+    /* Targets of reading the same symbol twice from a state with the number of such symbols */
+    pub fn diagonal_successors(&self, state: StateId) -> Vec<(StateId, u128)> {
+        let mut bdd = self.bdd.clone();
+        let root = bdd.compose(self.roots[state as usize], &mut |_, t| self.roots[t as usize], &mut HashMap::new());
+        bdd.leaf_counts(root, self.n_tracks)
+    }
+
+    /* Smallest symbol "a >= from" such that "filter" holds for the target of "a ^ xor", with the target */
+    pub fn next_symbol<F: Fn(StateId) -> bool>(&self, state: StateId, from: usize, xor: usize, filter: &F) -> Option<(usize, StateId)> {
+        self.bdd.next_symbol(self.roots[state as usize], self.n_tracks, from, xor, filter)
+    }
+
+    /* Distinct predecessors of each state */
+    fn reverse(&self) -> Vec<Vec<StateId>> {
+        let mut reverse = vec![Vec::new(); self.n_states()];
+        for (s, root) in self.roots.iter().enumerate() {
+            for t in self.bdd.leaves(*root) {
+                reverse[t as usize].push(s as StateId);
+            }
+        }
+        reverse
+    }
+
+    /* Length of the shortest accepted word from each state, see shortest_words */
+    pub fn shortest_words(&self) -> Vec<Option<usize>> {
+        let reverse = self.reverse();
+        let mut output = vec![None; self.n_states()];
+        let mut current: Vec<StateId> = (0..self.n_states() as StateId).filter(|s| self.is_accepting(*s)).collect();
+        let mut step = 0;
+        while !current.is_empty() {
+            let mut next = Vec::new();
+            for s in current {
+                if output[s as usize].is_none() {
+                    output[s as usize] = Some(step);
+                    next.extend(reverse[s as usize].iter().filter(|p| output[**p as usize].is_none()));
+                }
+            }
+            current = next;
+            step += 1;
+        }
+        output
+    }
+
+    /* Length of the longest accepted word and the number of accepted words from each state
+       (see longest_words and number_of_words); None is an infinite number, numbers saturate at u128::MAX */
+    pub fn longest_and_number_of_words(&self) -> (Vec<Bound>, Vec<Option<u128>>) {
+        let reverse = self.reverse();
+        let live: Vec<bool> = self.shortest_words().iter().map(|d| d.is_some()).collect();
+        let successors: Vec<Vec<(StateId, u128)>> = (0..self.n_states() as StateId).map(|s| {
+            self.successors(s).into_iter().filter(|(t, _)| live[*t as usize]).collect()
+        }).collect();
+
+        /* Live states are processed after all their live successors, states on cycles stay infinite */
+        let mut longest: Vec<Bound> = live.iter().map(|l| if *l { Bound::Infinite } else { Bound::None }).collect();
+        let mut words: Vec<Option<u128>> = live.iter().map(|l| if *l { None } else { Some(0) }).collect();
+        let mut pending: Vec<usize> = successors.iter().map(|s| s.len()).collect();
+        let mut stack: Vec<StateId> = (0..self.n_states() as StateId).filter(|s| live[*s as usize] && pending[*s as usize] == 0).collect();
+        while let Some(s) = stack.pop() {
+            let accepting = self.is_accepting(s);
+            let mut length = if accepting { Bound::Finite(0) } else { Bound::None };
+            let mut count = accepting as u128;
+            for (t, c) in &successors[s as usize] {
+                length = length.max(longest[*t as usize].increase());
+                count = count.saturating_add(c.saturating_mul(words[*t as usize].unwrap()));
+            }
+            longest[s as usize] = length;
+            words[s as usize] = Some(count);
+            for p in &reverse[s as usize] {
+                if live[*p as usize] && *p != s {
+                    pending[*p as usize] -= 1;
+                    if pending[*p as usize] == 0 {
+                        stack.push(*p);
+                    }
+                }
+            }
+        }
+        (longest, words)
+    }
+
+    pub fn test_input<I: Iterator<Item=usize>>(&self, word: I) -> bool {
+        let mut state = 0;
+        for a in word {
+            state = self.bdd.eval(self.roots[state as usize], a);
+        }
+        self.accepting[state as usize]
+    }
+
+    pub fn complement(mut self) -> Self {
+        for a in self.accepting.iter_mut() {
+            *a = !*a;
+        }
+        self
+    }
+
+    /* New track is the last variable, no diagram tests it */
+    pub fn add_track(&mut self) {
+        self.n_tracks += 1;
+    }
+
+    pub fn swap_tracks(&mut self, index1: usize, index2: usize) {
+        if index1 == index2 {
+            return;
+        }
+        let mut permutation: Vec<u32> = (0..self.n_tracks as u32).collect();
+        permutation.swap(index1, index2);
+        let mut memo = HashMap::new();
+        let mut select_memo = HashMap::new();
+        for root in self.roots.iter_mut() {
+            *root = self.bdd.rename_vars(*root, &permutation, &mut memo, &mut select_memo);
+        }
+        self.compact();
+    }
+
+    fn reachable_states(&self) -> Vec<StateId> {
+        let mut visited = vec![false; self.n_states()];
+        visited[0] = true;
+        let mut result = vec![0];
+        let mut i = 0;
+        while i < result.len() {
+            for t in self.bdd.leaves(self.roots[result[i] as usize]) {
+                if !visited[t as usize] {
+                    visited[t as usize] = true;
+                    result.push(t);
+                }
+            }
+            i += 1;
+        }
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.reachable_states().iter().any(|s| self.accepting[*s as usize])
+    }
+
+    /* Drops nodes that are not used by roots */
+    fn compact(&mut self) {
+        let mut bdd = Mtbdd::new();
+        let mut memo = HashMap::new();
+        for root in self.roots.iter_mut() {
+            *root = bdd.import(&self.bdd, *root, &mut memo);
+        }
+        self.bdd = bdd;
+    }
+
+    /* Minimal automaton; unreachable states are removed. Refinement is symbolic: a signature
+       of a state is its diagram with leaves replaced by classes, i.e. a single node id */
+    pub fn minimize(&self) -> SymbolicDfa {
+        let reachable = self.reachable_states();
+        let mut bdd = self.bdd.clone();
+        let mut class: Vec<u32> = vec![0; self.n_states()];
+        for s in &reachable {
+            class[*s as usize] = if self.accepting[*s as usize] { 0 } else { 1 };
+        }
+        let mut n_classes = 0;
+        loop {
+            let mut memo = HashMap::new();
+            let mut ids: HashMap<(bool, NodeId), u32> = HashMap::new();
+            let mut new_class = class.clone();
+            for s in &reachable {
+                let signature = bdd.map_leaves(self.roots[*s as usize], &mut |t| class[t as usize], &mut memo);
+                let next = ids.len() as u32;
+                new_class[*s as usize] = *ids.entry((self.accepting[*s as usize], signature)).or_insert(next);
+            }
+            class = new_class;
+            if ids.len() == n_classes {
+                break;
+            }
+            n_classes = ids.len();
+        }
+
+        /* Classes are numbered by reachability order, so state 0 stays initial */
+        let mut roots = vec![0; n_classes];
+        let mut accepting = vec![false; n_classes];
+        let mut memo = HashMap::new();
+        for s in &reachable {
+            let c = class[*s as usize] as usize;
+            roots[c] = bdd.map_leaves(self.roots[*s as usize], &mut |t| class[t as usize], &mut memo);
+            accepting[c] = self.accepting[*s as usize];
+        }
+        let mut result = SymbolicDfa { n_tracks: self.n_tracks, bdd, roots, accepting };
+        result.compact();
+        result
+    }
+
+    /* Synchronized product; "accept" combines acceptance of both components */
+    pub fn product<F: Fn(bool, bool) -> bool>(&self, other: &SymbolicDfa, accept: F) -> SymbolicDfa {
+        assert_eq!(self.n_tracks, other.n_tracks);
+        let mut bdd = Mtbdd::new();
+        let mut memo = HashMap::new();
+        let roots1: Vec<NodeId> = self.roots.iter().map(|r| bdd.import(&self.bdd, *r, &mut memo)).collect();
+        let mut memo = HashMap::new();
+        let roots2: Vec<NodeId> = other.roots.iter().map(|r| bdd.import(&other.bdd, *r, &mut memo)).collect();
+
+        let mut pairs: Vec<(u32, u32)> = vec![(0, 0)];
+        let mut pair_ids: HashMap<(u32, u32), u32> = HashMap::new();
+        pair_ids.insert((0, 0), 0);
+        let mut apply_memo = HashMap::new();
+        let mut roots = Vec::new();
+        let mut i = 0;
+        while i < pairs.len() {
+            let (s1, s2) = pairs[i];
+            let root = bdd.apply2(roots1[s1 as usize], roots2[s2 as usize], &mut |t1, t2| {
+                *pair_ids.entry((t1, t2)).or_insert_with(|| {
+                    pairs.push((t1, t2));
+                    (pairs.len() - 1) as u32
+                })
+            }, &mut apply_memo);
+            roots.push(root);
+            i += 1;
+        }
+        let accepting = pairs.iter().map(|(s1, s2)| accept(self.accepting[*s1 as usize], other.accepting[*s2 as usize])).collect();
+        let result = SymbolicDfa { n_tracks: self.n_tracks, bdd, roots, accepting };
+        result.minimize()
+    }
+
+    /* Existential projection of a track with zero-prefix fix (natural numbers) or with
+       sign-extension fix (integers, see Nfa::sign_extension_fix);
+       the result is determinized by a symbolic subset construction, it is dumped before
+       and after minimization */
+    pub fn project(&self, track: usize, sign_extension: bool, dump: Option<&Dumper>) -> SymbolicDfa {
+        assert!(track < self.n_tracks);
+        let mut bdd = self.bdd.clone();
+        let mut sets = SetTable::default();
+
+        /* Non-deterministic rows: leaves are ids of state sets */
+        let mut singleton_memo = HashMap::new();
+        let mut exists_memo = HashMap::new();
+        let mut union_memo = HashMap::new();
+        let mut nfa_roots: Vec<NodeId> = self.roots.iter().map(|root| {
+            let r = bdd.map_leaves(*root, &mut |t| sets.intern(vec![t]), &mut singleton_memo);
+            bdd.exists_var(r, track as u32, &mut |a, b| sets.union(a, b), &mut exists_memo, &mut union_memo)
+        }).collect();
+        let mut accepting = self.accepting.clone();
+        let n_tracks = self.n_tracks - 1;
+
+        let init = if sign_extension {
+            /* New initial state reads a symbol "a" into states reachable from 0 by a+ */
+            let mut row = nfa_roots[0];
+            let mut successors = HashMap::new();
+            loop {
+                let next = bdd.compose(row, &mut |bdd, set| *successors.entry(set).or_insert_with(|| {
+                    let members = sets.sets[set as usize].clone();
+                    let mut root = nfa_roots[members[0] as usize];
+                    for s in &members[1..] {
+                        root = bdd.apply2(root, nfa_roots[*s as usize], &mut |a, b| sets.union(a, b), &mut union_memo);
+                    }
+                    root
+                }), &mut HashMap::new());
+                let next = bdd.apply2(row, next, &mut |a, b| sets.union(a, b), &mut union_memo);
+                if next == row {
+                    break;
+                }
+                row = next;
+            }
+            let zero = bdd.eval(row, 0);
+            accepting.push(accepting[0] || sets.sets[zero as usize].iter().any(|s| accepting[*s as usize]));
+            nfa_roots.push(row);
+            vec![(nfa_roots.len() - 1) as StateId]
+        } else {
+            /* Initial states are states reachable by zero symbols */
+            let mut init = vec![0];
+            if n_tracks > 0 {
+                let mut i = 0;
+                while i < init.len() {
+                    let set = bdd.eval(nfa_roots[init[i] as usize], 0);
+                    for s in &sets.sets[set as usize] {
+                        if !init.contains(s) {
+                            init.push(*s);
+                        }
+                    }
+                    i += 1;
+                }
+                init.sort_unstable();
+            }
+            init
+        };
+
+        let init = sets.intern(init);
+        let mut det_ids: HashMap<u32, u32> = HashMap::new();
+        det_ids.insert(init, 0);
+        let mut det_sets = vec![init];
+        let mut roots = Vec::new();
+        let mut i = 0;
+        while i < det_sets.len() {
+            let members = sets.sets[det_sets[i] as usize].clone();
+            let mut root = nfa_roots[members[0] as usize];
+            for s in &members[1..] {
+                root = bdd.apply2(root, nfa_roots[*s as usize], &mut |a, b| sets.union(a, b), &mut union_memo);
+            }
+            for set in bdd.leaves(root) {
+                det_ids.entry(set).or_insert_with(|| {
+                    det_sets.push(set);
+                    (det_sets.len() - 1) as u32
+                });
+            }
+            roots.push(root);
+            i += 1;
+        }
+
+        let mut memo = HashMap::new();
+        let roots = roots.into_iter().map(|r| bdd.map_leaves(r, &mut |set| det_ids[&set], &mut memo)).collect();
+        let accepting = det_sets.iter().map(|set| sets.sets[*set as usize].iter().any(|s| accepting[*s as usize])).collect();
+        let projected = SymbolicDfa { n_tracks, bdd, roots, accepting };
+        dump_symbolic(dump, DumpStage::Projected, &projected);
+        let result = projected.minimize();
+        dump_symbolic(dump, DumpStage::Minimized, &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::automata::{longest_words, number_of_words, shortest_words};
+    use crate::highlevel::parser::parse_setdef;
+    use crate::solver::commands::build_set;
+
+    use super::*;
+
+    fn dfa(setdef: &str) -> Dfa {
+        build_set(&parse_setdef(setdef).unwrap()).unwrap().into_dfa()
+    }
+
+    #[test]
+    fn test_symbolic_conversion() {
+        let a = dfa("{ x, y, z | x + y == z and x < 100 }");
+        let s = SymbolicDfa::from_dfa(&a);
+        assert_eq!(s.n_states(), a.n_states());
+        let b = s.to_dfa();
+        assert_eq!(a.transitions(), b.transitions());
+        assert_eq!(s.minimize().n_states(), a.n_states());
+        assert!(!s.is_empty());
+        assert!(SymbolicDfa::from_dfa(&dfa("{ x | x < 0 }")).is_empty());
+
+        for a in &[a, dfa("{ x, y | x < y and y < 5 }")] {
+            let s = SymbolicDfa::from_dfa(a);
+            assert_eq!(s.shortest_words(), shortest_words(a));
+            let (longest, words) = s.longest_and_number_of_words();
+            assert_eq!(longest, longest_words(a));
+            assert_eq!(words.iter().map(|w| w.map(|w| w as usize)).collect::<Vec<_>>(), number_of_words(a));
+        }
+    }
+
+    fn equivalent(a: &SymbolicDfa, b: &SymbolicDfa) -> bool {
+        a.product(b, |x, y| x != y).is_empty()
+    }
+
+    #[test]
+    fn test_symbolic_product() {
+        let a = dfa("{ x, y | x < y }");
+        let b = dfa("{ x, y | x + 3 == 2 * y }");
+        let p = SymbolicDfa::from_dfa(&a).product(&SymbolicDfa::from_dfa(&b), |x, y| x && y);
+        assert_eq!(p.n_states(), a.intersection(&b).minimize().n_states());
+        assert!(equivalent(&p, &SymbolicDfa::from_dfa(&a.intersection(&b))));
+        let u = SymbolicDfa::from_dfa(&a).product(&SymbolicDfa::from_dfa(&b), |x, y| x || y);
+        let expected = a.clone().neg().intersection(&b.clone().neg()).neg();
+        assert!(equivalent(&u, &SymbolicDfa::from_dfa(&expected)));
+        assert!(!equivalent(&u, &p));
+    }
+
+    #[test]
+    fn test_symbolic_project_and_swap() {
+        let a = dfa("{ x, y, z | x + y == z and y < 7 and 3 * x < z }");
+        for track in 0..3 {
+            let p = SymbolicDfa::from_dfa(&a).project(track, false, None);
+            let mut nfa = a.make_nfa();
+            nfa.swap_tracks(0, track);
+            nfa.merge_first_track();
+            nfa.zero_prefix_fix();
+            let mut expected = nfa.make_dfa();
+            if track == 2 {
+                /* Explicit merge leaves the remaining tracks as (y, x) */
+                expected.swap_tracks(0, 1);
+            }
+            assert_eq!(p.n_states(), expected.minimize().n_states());
+            assert!(equivalent(&p, &SymbolicDfa::from_dfa(&expected)), "track {}", track);
+        }
+
+        let a = dfa("int { x, y, z | x + y == z and y < 7 and 3 * x < z + 5 }");
+        for track in 0..3 {
+            let p = SymbolicDfa::from_dfa(&a).project(track, true, None);
+            let mut nfa = a.make_nfa();
+            nfa.swap_tracks(0, track);
+            nfa.merge_first_track();
+            nfa.sign_extension_fix();
+            let mut expected = nfa.make_dfa();
+            if track == 2 {
+                expected.swap_tracks(0, 1);
+            }
+            assert_eq!(p.n_states(), expected.minimize().n_states());
+            assert!(equivalent(&p, &SymbolicDfa::from_dfa(&expected)), "track {}", track);
+        }
+
+        let mut s = SymbolicDfa::from_dfa(&a);
+        s.swap_tracks(0, 2);
+        let mut b = a.clone();
+        b.swap_tracks(0, 2);
+        assert_eq!(s.to_dfa().transitions(), b.transitions());
+    }
+}
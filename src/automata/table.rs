@@ -98,6 +98,11 @@ impl<T: Default + Clone> TransitionTable<T> {
         }
     }
 
+    pub fn push_row(&mut self, row: &[T]) {
+        assert_eq!(row.len(), self.alphabet_size());
+        self.transitions.extend_from_slice(row);
+    }
+
     pub fn add_track(&self) -> TransitionTable<T> {
         let mut new_transitions = Vec::with_capacity(self.n_states() * self.alphabet_size() * 2);
         for state in self.rows() {
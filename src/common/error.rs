@@ -0,0 +1,82 @@
+use std::fmt;
+
+use super::Resource;
+
+#[derive(Debug)]
+pub enum PasError {
+    Parse { line: usize, column: usize, message: String },
+    UndefinedSet(String),
+    UnknownCommand(String),
+    ArityMismatch { command: String, expected: usize, got: usize },
+    DuplicateVariable(String),
+    UndefinedVariable(String),
+    InvalidArgument(String),
+    AssertionFailed(String),
+    Io(std::io::Error),
+    Unsupported(String),
+    ResourceExhausted { resource: Resource, formula: String },
+    InvalidFormat(String),
+}
+
+pub type PasResult<T> = Result<T, PasError>;
+
+impl PasError {
+    /* Coefficients and constants of atoms are kept in i64 */
+    pub fn overflow() -> Self {
+        PasError::Unsupported("coefficient or constant does not fit into a 64-bit integer".to_string())
+    }
+
+    pub fn parse_error(input: &str, rest: &str, message: String) -> Self {
+        let offset = input.len() - rest.len();
+        let prefix = &input[..offset];
+        let line = prefix.matches('\n').count() + 1;
+        let column = prefix.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        PasError::Parse { line, column, message }
+    }
+}
+
+impl fmt::Display for PasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { line, column, message } => write!(f, "Parse error at line {}, column {}:\n{}", line, column, message),
+            Self::UndefinedSet(name) => write!(f, "Set '{}' not defined", name),
+            Self::UnknownCommand(name) => write!(f, "Unknown command '{}'", name),
+            Self::ArityMismatch { command, expected, got } => {
+                write!(f, "'{}' expects {} argument(s), got {}", command, expected, got)
+            }
+            Self::DuplicateVariable(name) => write!(f, "Variable '{}' is declared more than once", name),
+            Self::UndefinedVariable(name) => write!(f, "Variable '{}' not defined", name),
+            Self::InvalidArgument(arg) => write!(f, "Invalid argument '{}'", arg),
+            Self::AssertionFailed(msg) => write!(f, "Assertion failed: {}", msg),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+            Self::ResourceExhausted { resource, formula } => write!(f, "Resource exhausted ({}) in '{}'", resource, formula),
+            Self::InvalidFormat(msg) => write!(f, "Invalid file format: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PasError {}
+
+impl From<std::io::Error> for PasError {
+    fn from(e: std::io::Error) -> Self {
+        PasError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_position() {
+        let input = "a = { x | x < 1 };\nb = { y | y ? 2 }";
+        match PasError::parse_error(input, &input[30..], String::new()) {
+            PasError::Parse { line, column, .. } => {
+                assert_eq!(line, 2);
+                assert_eq!(column, 12);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Resource {
+    States(usize),
+    Time,
+    Cancelled,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::States(max) => write!(f, "more than {} states", max),
+            Self::Time => write!(f, "time limit"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// Limits of a set construction: maximal number of states of an intermediate automaton,
+/// wall-clock deadline and a flag for cooperative cancellation
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_states: Option<usize>,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Limits {
+    pub fn none() -> Self {
+        Default::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /* Called with the number of states of an automaton under construction */
+    pub fn check(&self, n_states: usize) -> Result<(), Resource> {
+        if let Some(max) = self.max_states.filter(|max| n_states > *max) {
+            return Err(Resource::States(max));
+        }
+        if self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+            return Err(Resource::Cancelled);
+        }
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(Resource::Time);
+        }
+        Ok(())
+    }
+}
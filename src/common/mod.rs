@@ -1,7 +1,11 @@
 pub use self::bits::iterate_bits_no_lz;
+pub use self::error::{PasError, PasResult};
+pub use self::limits::{Limits, Resource};
 pub use self::name::Name;
-pub use self::states::{StateId, StateSet};
+pub use self::states::{StateId, StateSet, StateSetBuilder};
 
 mod states;
 mod name;
 mod bits;
+mod error;
+mod limits;
@@ -1,44 +1,73 @@
-use std::hash::{Hash, Hasher};
-
-use hashbrown::HashSet;
+use smallvec::SmallVec;
 
 pub type StateId = u32;
 
-#[derive(Debug)]
+/// Sorted set of states; the key of subset constructions.
+/// Hash and equality are the ones of the sorted sequence, so different sets do not collide
+/// more than different sequences
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
 pub struct StateSet {
-    inner: HashSet<StateId>
+    states: SmallVec<[StateId; 4]>,
 }
 
 impl StateSet {
-    pub fn new(set: HashSet<StateId>) -> Self {
-        StateSet { inner: set }
+    pub fn new<I: IntoIterator<Item=StateId>>(states: I) -> Self {
+        let mut states: SmallVec<[StateId; 4]> = states.into_iter().collect();
+        states.sort_unstable();
+        states.dedup();
+        StateSet { states }
     }
 
     #[inline]
-    pub fn inner(&self) -> &HashSet<StateId> {
-        &self.inner
+    pub fn as_slice(&self) -> &[StateId] {
+        &self.states
     }
-}
 
-impl PartialEq for StateSet
-{
-    fn eq(&self, other: &StateSet) -> bool {
-        self.inner == other.inner
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
     }
 }
 
-impl Eq for StateSet {}
+/// Collects a subset of 0..n_states in a dense bitmap, so unions of successor sets
+/// do not need hashing; "take" returns the sorted set and resets the builder
+pub struct StateSetBuilder {
+    marked: Vec<bool>,
+    members: Vec<StateId>,
+}
 
-impl Hash for StateSet {
-    fn hash<H>(&self, state: &mut H)
-        where
-            H: Hasher,
-    {
-        let mut h: StateId = 0;
-        for elm in &self.inner {
-            h ^= *elm;
+impl StateSetBuilder {
+    pub fn new(n_states: usize) -> Self {
+        StateSetBuilder { marked: vec![false; n_states], members: Vec::new() }
+    }
+
+    #[inline]
+    pub fn insert(&mut self, state: StateId) {
+        if !self.marked[state as usize] {
+            self.marked[state as usize] = true;
+            self.members.push(state);
+        }
+    }
+
+    pub fn extend<I: IntoIterator<Item=StateId>>(&mut self, states: I) {
+        for s in states {
+            self.insert(s);
+        }
+    }
+
+    pub fn take(&mut self) -> StateSet {
+        for s in &self.members {
+            self.marked[*s as usize] = false;
         }
-        state.write_u64(h as u64 * self.inner.len() as u64);
+        self.members.sort_unstable();
+        let states = SmallVec::from_slice(&self.members);
+        self.members.clear();
+        StateSet { states }
     }
 }
 
@@ -100,4 +129,26 @@ impl Value {
             Self::Infinite => None
         }
     }
-}*/
\ No newline at end of file
+}*/
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_state_set() {
+        assert_eq!(StateSet::new(vec![3, 1, 2, 1]).as_slice(), &[1, 2, 3]);
+        let mut builder = StateSetBuilder::new(10);
+        builder.extend(vec![7, 0, 7, 3]);
+        assert_eq!(builder.take(), StateSet::new(vec![0, 3, 7]));
+        builder.insert(5);
+        assert_eq!(builder.take().as_slice(), &[5]);
+        assert!(builder.take().is_empty());
+
+        /* All of these had the same XOR hash */
+        let sets: HashSet<StateSet> = vec![vec![1, 2, 3], vec![0], vec![], vec![0, 1, 2, 3]].into_iter().map(StateSet::new).collect();
+        assert_eq!(sets.len(), 4);
+    }
+}
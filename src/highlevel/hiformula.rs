@@ -1,11 +1,14 @@
-use crate::common::Name;
-use crate::solver::{LoFormula, LoPredicate};
+use std::convert::TryFrom;
+
+use crate::common::{Name, PasError, PasResult};
+use crate::solver::{LoFormula, LoPredicate, Relation};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Expression {
     Variable(Name),
     Constant(u64),
     Add(Vec<Expression>),
+    Neg(Box<Expression>),
     Mul(Box<Expression>, u64),
     Mod(Box<Expression>, u64),
 }
@@ -22,146 +25,179 @@ impl Expression {
         }
         Expression::Add(exprs)
     }
+
+    pub fn new_neg(expr: Expression) -> Self {
+        match expr {
+            Expression::Neg(e) => *e,
+            e => Expression::Neg(Box::new(e)),
+        }
+    }
+
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum BinOp {
     Eq,
     Lt,
     Lte,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum HiPredicate {
     BinOp(BinOp, Expression, Expression),
+    Congruence(Expression, Expression, u64),
+    SetRef(Name, Vec<Expression>),
     True,
     False,
 }
 
-impl HiPredicate {
-    fn eq_optimize(lhs: &Expression, rhs: &Expression) -> Option<LoFormula> {
-        match (lhs, rhs) {
-            /*([Expression::Variable(name1), Expression::Variable(name2)], [Expression::Variable(name3)]) => {
-                Some(LoPredicate::safe_add(name1.clone(), name2.clone(), name3.clone()).to_formula())
-            },*/
-            /*([Expression::Variable(Variable{name: name1, scale: 2, modulo: 1})], [Expression::Variable(Variable{name: name2, scale: 1, modulo: 1})]) => {
-                Some(LoPredicate::safe_double(name1.clone(), name2.clone()).to_formula())
-            },*/
-            (Expression::Variable(name), Expression::Constant(c)) => {
-                Some(LoPredicate::EqConst(name.clone(), *c).to_formula())
+/* Linear combination of variables; each "E % M" is replaced by a fresh variable */
+#[derive(Default)]
+struct LinearTerms {
+    terms: Vec<(Name, i64)>,
+    constant: i64,
+    mods: Vec<(Name, Expression, u64)>,
+}
+
+impl LinearTerms {
+    fn add(&mut self, expression: &Expression, scale: i64) -> PasResult<()> {
+        match expression {
+            Expression::Constant(v) => {
+                self.constant = i64::try_from(*v).ok().and_then(|v| v.checked_mul(scale))
+                    .and_then(|v| v.checked_add(self.constant)).ok_or_else(PasError::overflow)?;
+            }
+            Expression::Variable(name) => self.terms.push((name.clone(), scale)),
+            Expression::Add(es) => {
+                for e in es {
+                    self.add(e, scale)?;
+                }
+            }
+            Expression::Neg(e) => self.add(e, scale.checked_neg().ok_or_else(PasError::overflow)?)?,
+            Expression::Mul(e, x) => {
+                let scale = i64::try_from(*x).ok().and_then(|x| x.checked_mul(scale)).ok_or_else(PasError::overflow)?;
+                self.add(e, scale)?;
+            }
+            Expression::Mod(e, x) => {
+                let fresh = Name::new_tmp();
+                self.terms.push((fresh.clone(), scale));
+                self.mods.push((fresh, *e.clone(), *x));
             }
-            _ => None,
         }
+        Ok(())
     }
 
-    pub fn make_lo_formula(&self) -> LoFormula {
-        match self {
-            HiPredicate::BinOp(op, lhs, rhs) => {
-                if let BinOp::Eq = op {
-                    if let Some(f) = Self::eq_optimize(lhs, rhs) {
-                        return f;
-                    }
-                    if let Some(f) = Self::eq_optimize(rhs, lhs) {
-                        return f;
-                    }
+    fn from_difference(lhs: &Expression, rhs: &Expression) -> PasResult<Self> {
+        let mut lt = LinearTerms::default();
+        lt.add(lhs, 1)?;
+        lt.add(rhs, -1)?;
+        Ok(lt)
+    }
+
+    /* Constant moved to the other side of the relation */
+    fn neg_constant(&self) -> PasResult<i64> {
+        self.constant.checked_neg().ok_or_else(PasError::overflow)
+    }
+
+    /* Adds definitions of variables introduced for "E % M" */
+    fn close(self, mut formula: LoFormula) -> PasResult<LoFormula> {
+        for (name, expr, modulus) in self.mods {
+            formula = formula.and(Self::mod_definition(&name, &expr, modulus)?).close_if_tmp(&name);
+        }
+        Ok(formula)
+    }
+
+    // E % M == OUT ~~> OUT == E (mod M) and 0 <= OUT and OUT < M
+    fn mod_definition(out: &Name, expr: &Expression, modulus: u64) -> PasResult<LoFormula> {
+        if modulus == 0 {
+            return Ok(LoPredicate::False.to_formula());
+        }
+        let max = i64::try_from(modulus - 1).map_err(|_| PasError::overflow())?;
+        let mut lt = LinearTerms::default();
+        lt.add(expr, -1)?;
+        lt.terms.push((out.clone(), 1));
+        let congruence = LoPredicate::congruence(std::mem::take(&mut lt.terms), modulus, lt.neg_constant()?)?.to_formula();
+        let range = LoPredicate::linear(vec![(out.clone(), 1)], Relation::Lte, max)?.to_formula()
+            .and(LoPredicate::linear(vec![(out.clone(), -1)], Relation::Lte, 0)?.to_formula());
+        lt.close(congruence.and(range))
+    }
+
+    /* "E % M == C" is directly a congruence without a variable for E % M */
+    fn mod_optimize(&self, op: &BinOp) -> PasResult<Option<LoFormula>> {
+        match (op, self.terms.as_slice(), self.mods.as_slice()) {
+            (BinOp::Eq, [(name, c)], [(out, expr, modulus)]) if name == out && *modulus > 0 => {
+                if self.constant.checked_rem(*c).ok_or_else(PasError::overflow)? != 0 {
+                    return Ok(Some(LoPredicate::False.to_formula()));
                 }
-                let (lf, name1) = Self::expression_to_lo_formula(lhs);
-                let (rf, name2) = Self::expression_to_lo_formula(rhs);
-                if name1 == name2 {
-                    match op {
-                        BinOp::Lt => LoPredicate::False.to_formula(),
-                        BinOp::Eq | BinOp::Lte => LoPredicate::True.to_formula(),
-                    }
-                } else {
-                    let f = match op {
-                        BinOp::Eq => LoPredicate::safe_eq(name1.clone(), name2.clone()).to_formula(),
-                        BinOp::Lte => {
-                            let fresh = Name::new_tmp();
-                            LoPredicate::safe_add(name1.clone(), fresh.clone(), name2.clone()).to_formula().exists(fresh)
-                        }
-                        BinOp::Lt => {
-                            let fresh = Name::new_tmp();
-                            LoPredicate::safe_add(name2.clone(), fresh.clone(), name1.clone()).to_formula().exists(fresh).neg()
-                        }
-                    };
-                    f.and(lf).close_if_tmp(&name1).and(rf).close_if_tmp(&name2)
+                let value = self.neg_constant()?.checked_div(*c).ok_or_else(PasError::overflow)?;
+                if value < 0 || value as u64 >= *modulus {
+                    return Ok(Some(LoPredicate::False.to_formula()));
                 }
+                let mut lt = LinearTerms::default();
+                lt.add(expr, 1)?;
+                let residue = value.checked_sub(lt.constant).ok_or_else(PasError::overflow)?;
+                let f = LoPredicate::congruence(std::mem::take(&mut lt.terms), *modulus, residue)?.to_formula();
+                Ok(Some(lt.close(f)?))
             }
-            HiPredicate::True => LoFormula::Predicate(LoPredicate::True),
-            HiPredicate::False => LoFormula::Predicate(LoPredicate::False),
+            _ => Ok(None),
         }
     }
+}
 
-    fn expression_to_lo_formula(expression: &Expression) -> (LoFormula, Name) {
-        match expression {
-            Expression::Constant(v) => {
-                let fresh = Name::new_tmp();
-                (LoPredicate::EqConst(fresh.clone(), *v).to_formula(), fresh)
-            }
-            Expression::Variable(v) => (LoPredicate::True.to_formula(), v.clone()),
-            Expression::Add(es) => {
-                assert!(es.len() >= 2);
-                let (mut f1, mut name1) = Self::expression_to_lo_formula(&es[0]);
-                for e in &es[1..] {
-                    let (f2, name2) = Self::expression_to_lo_formula(e);
-                    let fresh = Name::new_tmp();
-                    let f3 = LoFormula::Predicate(LoPredicate::safe_add(name1.clone(), name2.clone(), fresh.clone()));
-                    f1 = f3.and(f1).close_if_tmp(&name1).and(f2).close_if_tmp(&name2);
-                    name1 = fresh
+impl HiPredicate {
+    /* Fails with Unsupported when a coefficient or a constant does not fit into i64 */
+    pub fn make_lo_formula(&self) -> PasResult<LoFormula> {
+        match self {
+            HiPredicate::BinOp(op, lhs, rhs) => {
+                /* lhs - rhs OP 0; expressions are evaluated as integers even in Nat domain,
+                   i.e. "x - y < 3" is "x < 3 + y", only variables range over the domain */
+                let mut lt = LinearTerms::from_difference(lhs, rhs)?;
+                if let Some(f) = lt.mod_optimize(op)? {
+                    return Ok(f);
                 }
-                (f1, name1)
+                let constant = lt.neg_constant()?;
+                let (rel, constant) = match op {
+                    BinOp::Eq => (Relation::Eq, constant),
+                    BinOp::Lte => (Relation::Lte, constant),
+                    BinOp::Lt => (Relation::Lte, constant.checked_sub(1).ok_or_else(PasError::overflow)?),
+                };
+                let f = LoPredicate::linear(std::mem::take(&mut lt.terms), rel, constant)?.to_formula();
+                lt.close(f)
             }
-            Expression::Mod(expr, x) => {
-                // E % x == OUT ~~> exists(T)(T * x + OUT == E) and OUT < x
-                let var_t = Name::new_unnamed();
-                let var_out = Name::new_unnamed();
-                let tmp_e1 = Expression::Add(vec![Expression::Mul(Box::new(Expression::Variable(var_t.clone())), *x), Expression::Variable(var_out.clone())]);
-                let formula1 = HiFormula::Exists(var_t.clone(), Box::new(HiFormula::Predicate(HiPredicate::BinOp(BinOp::Eq, *expr.clone(), tmp_e1))));
-                let formula2 = HiFormula::Predicate(HiPredicate::BinOp(BinOp::Lt, Expression::Variable(var_out.clone()), Expression::Constant(*x)));
-                let formula = HiFormula::And(Box::new(formula1), Box::new(formula2));
-                let fresh_tmp = Name::new_tmp();
-                (formula.make_lo_formula().rename_free_var(&var_out, &fresh_tmp), fresh_tmp)
+            HiPredicate::Congruence(lhs, rhs, modulus) => {
+                let mut lt = LinearTerms::from_difference(lhs, rhs)?;
+                let f = LoPredicate::congruence(std::mem::take(&mut lt.terms), *modulus, lt.neg_constant()?)?.to_formula();
+                lt.close(f)
             }
-            Expression::Mul(e, mut x) => {
-                if x == 0 {
-                    todo!();
-                }
-                let (mut formula, mut exp_var) = Self::expression_to_lo_formula(e);
-                let mut out_var = None;
-                //let mut formula = LoPredicate::True.to_formula();
-                loop {
-                    let mut close = true;
-                    if x & 1 == 1 {
-                        if out_var.is_none() {
-                            out_var = Some(exp_var.clone());
-                            close = false;
-                        } else {
-                            let fresh2 = Name::new_tmp();
-                            let var = out_var.take().unwrap();
-                            formula = formula.and(LoPredicate::safe_add(var.clone(), exp_var.clone(), fresh2.clone()).to_formula()).close_if_tmp(&var);
-                            out_var = Some(fresh2);
-                        }
-                    }
-                    x >>= 1;
-                    if x == 0 {
-                        if close {
-                            formula = formula.close_if_tmp(&exp_var);
+            HiPredicate::SetRef(set, args) => {
+                /* Arguments that are not distinct variables get a fresh variable
+                   ~~> (x, E) in S ~~> exists(T)((x, T) in S and T == E) */
+                let mut vars: Vec<Name> = Vec::with_capacity(args.len());
+                let mut defs = Vec::new();
+                for arg in args {
+                    match arg {
+                        Expression::Variable(name) if !vars.contains(name) => vars.push(name.clone()),
+                        e => {
+                            let fresh = Name::new_tmp();
+                            let mut lt = LinearTerms::default();
+                            lt.add(e, -1)?;
+                            lt.terms.push((fresh.clone(), 1));
+                            let f = LoPredicate::linear(std::mem::take(&mut lt.terms), Relation::Eq, lt.neg_constant()?)?.to_formula();
+                            defs.push((fresh.clone(), lt.close(f)?));
+                            vars.push(fresh);
                         }
-                        return (formula, out_var.unwrap());
-                    }
-                    let fresh = Name::new_tmp();
-                    formula = formula.and(LoPredicate::safe_double(exp_var.clone(), fresh.clone()).to_formula());
-                    if close {
-                        formula = formula.close_if_tmp(&exp_var);
                     }
-                    exp_var = fresh;
                 }
+                Ok(defs.into_iter().fold(LoPredicate::SetRef(set.clone(), vars).to_formula(), |f, (name, def)| {
+                    f.and(def).close_if_tmp(&name)
+                }))
             }
+            HiPredicate::True => Ok(LoFormula::Predicate(LoPredicate::True)),
+            HiPredicate::False => Ok(LoFormula::Predicate(LoPredicate::False)),
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum HiFormula {
     Predicate(HiPredicate),
     Neg(Box<HiFormula>),
@@ -172,15 +208,15 @@ pub enum HiFormula {
 }
 
 impl HiFormula {
-    pub fn make_lo_formula(&self) -> LoFormula {
-        match self {
-            HiFormula::Predicate(p) => p.make_lo_formula(),
-            HiFormula::Neg(f) => f.make_lo_formula().neg(),
-            HiFormula::And(f1, f2) => f1.make_lo_formula().and(f2.make_lo_formula()),
-            HiFormula::Or(f1, f2) => f1.make_lo_formula().or(f2.make_lo_formula()),
-            HiFormula::Exists(name, f) => f.make_lo_formula().exists(name.clone()),
-            HiFormula::ForAll(name, f) => f.make_lo_formula().for_all(name.clone()),
-        }
+    pub fn make_lo_formula(&self) -> PasResult<LoFormula> {
+        Ok(match self {
+            HiFormula::Predicate(p) => p.make_lo_formula()?,
+            HiFormula::Neg(f) => f.make_lo_formula()?.neg(),
+            HiFormula::And(f1, f2) => f1.make_lo_formula()?.and(f2.make_lo_formula()?),
+            HiFormula::Or(f1, f2) => f1.make_lo_formula()?.or(f2.make_lo_formula()?),
+            HiFormula::Exists(name, f) => f.make_lo_formula()?.exists(name.clone()),
+            HiFormula::ForAll(name, f) => f.make_lo_formula()?.for_all(name.clone()),
+        })
     }
 
     pub fn and(self, other: HiFormula) -> HiFormula {
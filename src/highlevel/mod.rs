@@ -1,2 +1,3 @@
 pub mod hiformula;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod smtlib;
@@ -1,20 +1,21 @@
 use nom::{InputTakeAtPosition, IResult};
 use nom::branch::alt;
-use nom::bytes::complete::tag;
+use nom::bytes::complete::{is_not, tag, take_while_m_n};
 use nom::character::complete::{digit1, multispace0};
-use nom::combinator::{all_consuming, map, map_res, opt};
+use nom::combinator::{all_consuming, cut, map, map_res, not, opt, recognize};
 use nom::error::{convert_error, ErrorKind, VerboseError};
-use nom::multi::{fold_many0, separated_list};
+use nom::multi::{fold_many0, many0, separated_list};
 use nom::sequence::{delimited, pair, preceded, terminated, tuple};
 
-use crate::common::Name;
+use crate::common::{Name, PasError, PasResult};
 use crate::highlevel::hiformula::{Expression, HiFormula};
 use crate::highlevel::hiformula::{BinOp, HiPredicate};
-use crate::solver::commands::{Command, SetDef};
+use crate::solver::commands::{Command, SetDef, SetExpr};
+use crate::solver::Domain;
 
 pub type NomResult<'a, Ret> = IResult<&'a str, Ret, VerboseError<&'a str>>;
 
-fn integer(input: &str) -> NomResult<u64>
+fn integer(input: &str) -> NomResult<'_, u64>
 {
     map_res(digit1, |digit_str: &str| {
         digit_str.parse::<u64>()
@@ -28,12 +29,17 @@ pub fn is_id_char(c: char) -> bool {
     }
 }
 
-fn identifier(input: &str) -> NomResult<String>
+/* Keyword that is not a prefix of an identifier, e.g. "in" does not match "ineven" */
+fn keyword<'a>(kw: &'static str) -> impl Fn(&'a str) -> NomResult<'a, &'a str> {
+    terminated(tag(kw), not(take_while_m_n(1, 1, is_id_char)))
+}
+
+fn identifier(input: &str) -> NomResult<'_, String>
 {
     input.split_at_position1_complete(|item| !is_id_char(item), ErrorKind::Alpha).map(|(x, y)| (x, y.to_string()))
 }
 
-fn atom(input: &str) -> NomResult<Expression> {
+fn atom(input: &str) -> NomResult<'_, Expression> {
     alt((
         map(tuple((integer, opt(preceded(tuple((multispace0, tag("*"), multispace0)), identifier)))), |r| {
             match r {
@@ -50,15 +56,56 @@ fn atom(input: &str) -> NomResult<Expression> {
     ))(input)
 }
 
-fn expr(input: &str) -> NomResult<Vec<Expression>> {
-    separated_list(tuple((multispace0, tag("+"), multispace0)), atom)(input)
+fn term(input: &str) -> NomResult<'_, Expression> {
+    alt((
+        map(preceded(pair(tag("-"), multispace0), term), Expression::new_neg),
+        atom
+    ))(input)
 }
 
-fn operator(input: &str) -> NomResult<&str> {
+fn expr(input: &str) -> NomResult<'_, Vec<Expression>> {
+    map(pair(term, many0(pair(delimited(multispace0, alt((tag("+"), tag("-"))), multispace0), term))), |(first, rest)| {
+        let mut terms = vec![first];
+        terms.extend(rest.into_iter().map(|(op, e)| if op == "-" { Expression::new_neg(e) } else { e }));
+        terms
+    })(input)
+}
+
+fn operator(input: &str) -> NomResult<'_, &str> {
     alt((tag("=="), tag("<="), tag(">="), tag("<"), tag(">")))(input)
 }
 
-fn predicate(input: &str) -> NomResult<HiPredicate> {
+fn congruence(input: &str) -> NomResult<'_, HiPredicate> {
+    map(tuple((expr,
+               delimited(multispace0, tag("≡"), multispace0),
+               expr,
+               delimited(tuple((multispace0, tag("("), multispace0, tag("mod"), multispace0)), integer, pair(multispace0, tag(")"))))),
+        |(lhs, _, rhs, modulus)| {
+            HiPredicate::Congruence(Expression::new_add(lhs), Expression::new_add(rhs), modulus)
+        })(input)
+}
+
+fn expr_list(input: &str) -> NomResult<'_, Vec<Expression>> {
+    separated_list(tuple((multispace0, tag(","), multispace0)), map(expr, Expression::new_add))(input)
+}
+
+fn set_ref(input: &str) -> NomResult<'_, HiPredicate> {
+    let args = delimited(pair(tag("("), multispace0), expr_list, pair(multispace0, tag(")")));
+    alt((
+        map(tuple((alt((args, map(expr, |e| vec![Expression::new_add(e)]))),
+                   delimited(multispace0, keyword("in"), multispace0),
+                   identifier)),
+            |(args, _, name)| HiPredicate::SetRef(Name::new(name), args)),
+        map(pair(identifier, preceded(multispace0, delimited(pair(tag("("), multispace0), expr_list, pair(multispace0, tag(")"))))),
+            |(name, args)| HiPredicate::SetRef(Name::new(name), args)),
+    ))(input)
+}
+
+fn predicate(input: &str) -> NomResult<'_, HiPredicate> {
+    alt((set_ref, congruence, binop_predicate))(input)
+}
+
+fn binop_predicate(input: &str) -> NomResult<'_, HiPredicate> {
     map(tuple((expr, delimited(multispace0, operator, multispace0), expr)), |(lhs, op, rhs)| {
         let lhs = Expression::new_add(lhs);
         let rhs = Expression::new_add(rhs);
@@ -83,7 +130,7 @@ enum Quantifier {
     ForAll(Name),
 }
 
-fn quantifier(input: &str) -> NomResult<Quantifier> {
+fn quantifier(input: &str) -> NomResult<'_, Quantifier> {
     map(pair(
         terminated(alt((tag("exists"), tag("forall"))), multispace0),
         delimited(
@@ -101,14 +148,14 @@ fn quantifier(input: &str) -> NomResult<Quantifier> {
     })(input)
 }
 
-fn quantifiers(input: &str) -> NomResult<Vec<Quantifier>> {
+fn quantifiers(input: &str) -> NomResult<'_, Vec<Quantifier>> {
     fold_many0(quantifier, Vec::new(), |mut acc: Vec<_>, item| {
         acc.push(item);
         acc
     })(input)
 }
 
-fn formula_inner(input: &str) -> NomResult<HiFormula> {
+fn formula_inner(input: &str) -> NomResult<'_, HiFormula> {
     alt((
         map(tuple((quantifiers, delimited(pair(tag("("), multispace0), formula, pair(tag(")"), multispace0)))), |r| {
             r.0.into_iter().rev().fold(r.1, |acc, item| match item {
@@ -116,13 +163,13 @@ fn formula_inner(input: &str) -> NomResult<HiFormula> {
                 Quantifier::ForAll(name) => HiFormula::ForAll(name, Box::new(acc))
             })
         }),
-        map(preceded(tuple((tag("not"), multispace0)), formula_inner), |f| f.neg()),
+        map(preceded(tuple((keyword("not"), multispace0)), formula_inner), |f| f.neg()),
         map(terminated(predicate, multispace0), HiFormula::Predicate)
     ))(input)
 }
 
-fn formula_and(input: &str) -> NomResult<HiFormula> {
-    map(tuple((formula_inner, opt(preceded(tuple((tag("and"), multispace0)), formula_and)))), |r| {
+fn formula_and(input: &str) -> NomResult<'_, HiFormula> {
+    map(tuple((formula_inner, opt(preceded(tuple((keyword("and"), multispace0)), formula_and)))), |r| {
         match r {
             (f, None) => f,
             (f, Some(g)) => f.and(g),
@@ -130,8 +177,8 @@ fn formula_and(input: &str) -> NomResult<HiFormula> {
     })(input)
 }
 
-fn formula_or(input: &str) -> NomResult<HiFormula> {
-    map(tuple((formula_and, opt(preceded(tuple((tag("or"), multispace0)), formula_or)))), |r| {
+fn formula_or(input: &str) -> NomResult<'_, HiFormula> {
+    map(tuple((formula_and, opt(preceded(tuple((keyword("or"), multispace0)), formula_or)))), |r| {
         match r {
             (f, None) => f,
             (f, Some(g)) => f.or(g),
@@ -145,65 +192,151 @@ fn formula(input: &str) -> IResult<&str, HiFormula, VerboseError<&str>> {
     formula_or(input)
 }
 
-fn id_list(input: &str) -> NomResult<Vec<String>> {
+fn id_list(input: &str) -> NomResult<'_, Vec<String>> {
     terminated(separated_list(tuple((multispace0, tag(","), multispace0)), identifier), multispace0)(input)
 }
 
-fn var_list(input: &str) -> NomResult<Vec<Name>> {
+fn var_list(input: &str) -> NomResult<'_, Vec<Name>> {
     map(id_list, |r| r.iter().map(|x| Name::from_str(x)).collect())(input)
 }
 
-fn setout(input: &str) -> NomResult<Vec<Name>> {
+fn setout(input: &str) -> NomResult<'_, Vec<Name>> {
     terminated(var_list, tuple((multispace0, tag("|"), multispace0)))(input)
 }
 
-pub fn setdef(input: &str) -> NomResult<SetDef> {
-    map(delimited(tuple((tag("{"), multispace0)),
-                  tuple((setout, formula)),
-                  tuple((tag("}"), multispace0))), |(vars, formula)| {
+fn domain(input: &str) -> NomResult<'_, Domain> {
+    terminated(alt((
+        map(tag("nat"), |_| Domain::Nat),
+        map(tag("int"), |_| Domain::Int),
+    )), multispace0)(input)
+}
+
+/* Errors after "{" are not backtracked, so they point into the formula instead of before the set */
+pub fn setdef(input: &str) -> NomResult<'_, SetDef> {
+    map(tuple((opt(domain), preceded(tuple((tag("{"), multispace0)),
+                                     cut(terminated(tuple((setout, formula)),
+                                                    tuple((tag("}"), multispace0))))))), |(domain, (vars, formula))| {
         SetDef {
             vars,
             formula,
+            domain: domain.unwrap_or(Domain::Nat),
         }
     })(input)
 }
 
-pub fn command(input: &str) -> NomResult<Command> {
+fn set_atom(input: &str) -> NomResult<'_, SetExpr> {
+    terminated(alt((
+        map(preceded(pair(tag("~"), multispace0), set_atom), |e| SetExpr::Complement(Box::new(e))),
+        map(preceded(pair(tag("project"), multispace0),
+                     delimited(pair(tag("("), multispace0),
+                               pair(set_expr, preceded(pair(tag(","), multispace0), var_list)),
+                               tag(")"))),
+            |(e, names)| SetExpr::Project(Box::new(e), names)),
+        map(preceded(pair(tag("import"), multispace0),
+                     delimited(pair(tag("("), multispace0),
+                               tuple((terminated(string, multispace0),
+                                      preceded(pair(tag(","), multispace0), terminated(string, multispace0)),
+                                      opt(preceded(pair(tag(","), multispace0), var_list)))),
+                               tag(")"))),
+            |(path, format, names)| SetExpr::Import(path, format, names.unwrap_or_default())),
+        map(preceded(pair(tag("load"), multispace0),
+                     delimited(pair(tag("("), multispace0), terminated(string, multispace0), tag(")"))),
+            SetExpr::Load),
+        delimited(pair(tag("("), multispace0), set_expr, tag(")")),
+        map(identifier, |name| SetExpr::Ref(Name::new(name))),
+    )), multispace0)(input)
+}
+
+/* "&" and "\" bind tighter than "|", all operators are left-associative */
+fn set_product(input: &str) -> NomResult<'_, SetExpr> {
+    map(pair(set_atom, many0(pair(terminated(alt((tag("&"), tag("\\"))), multispace0), set_atom))), |(first, rest)| {
+        rest.into_iter().fold(first, |acc, (op, e)| match op {
+            "&" => SetExpr::Intersection(Box::new(acc), Box::new(e)),
+            _ => SetExpr::Difference(Box::new(acc), Box::new(e)),
+        })
+    })(input)
+}
+
+pub fn set_expr(input: &str) -> NomResult<'_, SetExpr> {
+    map(pair(set_product, many0(preceded(pair(tag("|"), multispace0), set_product))), |(first, rest)| {
+        rest.into_iter().fold(first, |acc, e| SetExpr::Union(Box::new(acc), Box::new(e)))
+    })(input)
+}
+
+/* Quoted string without escapes, quotes are removed */
+fn string(input: &str) -> NomResult<'_, String> {
+    map(delimited(tag("\""), opt(is_not("\"")), tag("\"")), |s: Option<&str>| s.unwrap_or("").to_string())(input)
+}
+
+/* Argument of a call: identifier, integer or quoted string (without quotes) */
+fn arg(input: &str) -> NomResult<'_, String> {
+    alt((
+        identifier,
+        map(recognize(pair(opt(tag("-")), digit1)), |s: &str| s.to_string()),
+        string,
+    ))(input)
+}
+
+fn arg_list(input: &str) -> NomResult<'_, Vec<String>> {
+    terminated(separated_list(tuple((multispace0, tag(","), multispace0)), arg), multispace0)(input)
+}
+
+pub fn command(input: &str) -> NomResult<'_, Command> {
     alt((
         map(tuple((identifier, delimited(multispace0, tag("="), multispace0), setdef)), |(name, _, sd)| Command::SetDef(name, sd)),
-        map(tuple((identifier, delimited(delimited(multispace0, tag("("), multispace0), id_list, delimited(multispace0, tag(")"), multispace0)))), |(name, args)| Command::Call(name, args)),
+        /* Not a set definition, so the rest is committed to a set expression */
+        map(tuple((identifier, delimited(multispace0, tag("="), multispace0), cut(set_expr))), |(name, _, e)| Command::SetExpr(name, e)),
+        map(tuple((identifier, delimited(delimited(multispace0, tag("("), multispace0), arg_list, delimited(multispace0, tag(")"), multispace0)))), |(name, args)| Command::Call(name, args)),
     ))(input)
 }
 
-pub fn commands(input: &str) -> NomResult<Vec<Command>> {
+pub fn commands(input: &str) -> NomResult<'_, Vec<Command>> {
     terminated(separated_list(tuple((multispace0, tag(";"), multispace0)), command), multispace0)(input)
 }
 
-pub fn parse_formula(input: &str) -> HiFormula {
-    formula(input).unwrap().1
+pub fn parse_formula(input: &str) -> PasResult<HiFormula> {
+    nom_to_result(input, all_consuming(preceded(multispace0, formula))(input)).map(|r| r.1)
 }
 
-pub fn parse_setdef(input: &str) -> SetDef {
-    setdef(input).unwrap().1
+pub fn parse_setdef(input: &str) -> PasResult<SetDef> {
+    nom_to_result(input, all_consuming(preceded(multispace0, setdef))(input)).map(|r| r.1)
 }
 
+pub fn parse_commands(input: &str) -> PasResult<Vec<Command>> {
+    /* Commands are parsed one by one, so an error points into the failing command */
+    let mut cmds = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let (r, cmd) = nom_to_result(input, command(rest))?;
+        cmds.push(cmd);
+        rest = r;
+        if !rest.is_empty() {
+            rest = nom_to_result(input, pair(tag(";"), multispace0)(rest))?.0;
+        }
+    }
+    Ok(cmds)
+}
 
-/*fn named_defset(input: &str) -> NomResult<SetDef> {
+
+/*fn named_defset(input: &str) -> NomResult<'_, SetDef> {
     map(tuple((variable, tuple((multispace0, tag("="), multispace0)), formula)), |(name, _, f)| {
 
     })(input)
 }*/
 
-pub fn parse_exact<Ret, Parser: Fn(&str) -> NomResult<Ret>>(parser: Parser, input: &str) -> NomResult<Ret> {
+pub fn parse_exact<Ret, Parser: Fn(&str) -> NomResult<'_, Ret>>(parser: Parser, input: &str) -> NomResult<'_, Ret> {
     all_consuming(parser)(input)
 }
 
-pub fn unwrap_nom<'a, Ret>(input: &'a str, result: NomResult<'a, Ret>) -> (&'a str, Ret) {
+pub fn nom_to_result<'a, Ret>(input: &'a str, result: NomResult<'a, Ret>) -> PasResult<(&'a str, Ret)> {
     match result {
-        Ok(data) => data,
+        Ok(data) => Ok(data),
         Err(e) => match e {
-            nom::Err::Incomplete(_needed) => panic!("Incomplete input"),
-            nom::Err::Error(e) | nom::Err::Failure(e) => panic!(convert_error(input, e))
+            nom::Err::Incomplete(_needed) => Err(PasError::parse_error(input, "", "Incomplete input".to_string())),
+            nom::Err::Error(e) | nom::Err::Failure(e) => {
+                let rest = e.errors.first().map(|(rest, _)| *rest).unwrap_or("");
+                Err(PasError::parse_error(input, rest, convert_error(input, e)))
+            }
         }
     }
 }
@@ -238,6 +371,17 @@ mod test {
         assert_eq!(Ok(("", vec![Expression::Mul(Box::new(xx2), 2), Expression::Mul(Box::new(yy2), 3)])), expr("2 * xx + 3 * yy"));
     }
 
+    #[test]
+    fn parse_expr_sub() {
+        let x = Expression::Variable(Name::from_str("x"));
+        let y = Expression::Variable(Name::from_str("y"));
+        let neg = |e: &Expression| Expression::Neg(Box::new(e.clone()));
+        assert_eq!(Ok(("", vec![x.clone(), neg(&y)])), expr("x - y"));
+        assert_eq!(Ok(("", vec![neg(&x), y.clone(), neg(&Expression::Constant(2))])), expr("-x + y - 2"));
+        assert_eq!(Ok(("", vec![x.clone(), y.clone()])), expr("x - -y"));
+        assert_eq!(Ok(("", vec![neg(&Expression::Mul(Box::new(x), 3))])), expr("- 3 * x"));
+    }
+
     #[test]
     fn test_parse_predicate() {
         let x1 = Expression::Variable(Name::from_str("x"));
@@ -246,6 +390,28 @@ mod test {
         assert_eq!(Ok(("", HiPredicate::BinOp(BinOp::Eq, Expression::Add(vec![x1, c2]), y3))), predicate("x + 2 == 3 * y"));
     }
 
+    #[test]
+    fn test_parse_congruence() {
+        let x = Expression::Variable(Name::from_str("x"));
+        let y = Expression::Variable(Name::from_str("y"));
+        assert_eq!(Ok(("", HiPredicate::Congruence(x.clone(), Expression::Add(vec![y, Expression::Constant(1)]), 7))), predicate("x ≡ y + 1 (mod 7)"));
+        assert_eq!(Ok(("", HiPredicate::Congruence(x, Expression::Constant(2), 3))), predicate("x≡2(mod 3)"));
+    }
+
+    #[test]
+    fn test_parse_set_ref() {
+        let x = Expression::Variable(Name::from_str("x"));
+        let y = Expression::Variable(Name::from_str("y"));
+        let even = Name::from_str("even");
+        assert_eq!(Ok(("", HiPredicate::SetRef(even.clone(), vec![x.clone()]))), predicate("x in even"));
+        assert_eq!(Ok(("", HiPredicate::SetRef(even.clone(), vec![x.clone(), y]))), predicate("(x, y) in even"));
+        assert_eq!(Ok(("", HiPredicate::SetRef(even, vec![x]))), predicate("even(x)"));
+        assert!(parse_formula("x ineven").is_err());
+        assert!(parse_formula("x < 1 andy < 2").is_err());
+        assert_eq!(parse_formula("notx in even").unwrap(), HiFormula::Predicate(
+            HiPredicate::SetRef(Name::from_str("even"), vec![Expression::Variable(Name::from_str("notx"))])));
+    }
+
     #[test]
     fn test_parse_formula() {
         let p = |s: &str| {
@@ -278,11 +444,72 @@ mod test {
         assert_eq!(r2.formula, f);
     }
 
+    #[test]
+    fn test_parser_setdef_domain() {
+        let (_, r) = setdef("{ x | x < 10 }").unwrap();
+        assert_eq!(r.domain, Domain::Nat);
+        let (_, r) = setdef("nat { x | x < 10 }").unwrap();
+        assert_eq!(r.domain, Domain::Nat);
+        let (_, r) = setdef("int {x | x + 3 == 1}").unwrap();
+        assert_eq!(r.domain, Domain::Int);
+        assert_eq!(r.vars, vec![Name::from_str("x")]);
+    }
+
     #[test]
     fn test_parser_exact() {
         assert!(parse_exact(setdef, "{ x, y | x <= 10 and 2 < x } + 1").is_err());
     }
 
+    #[test]
+    fn test_parser_error() {
+        let position = |r: PasResult<Vec<Command>>| match r {
+            Err(PasError::Parse { line, column, .. }) => (line, column),
+            r => panic!("Parse error expected, got {:?}", r),
+        };
+        assert_eq!(position(parse_commands("a = { x | x < 1 };\n  b = { y | y ? 2 }\n")), (2, 15));
+        assert_eq!(position(parse_commands("s = { x | x <  }")), (1, 16));
+        assert_eq!(position(parse_commands("s = { x | x < 1 ) }")), (1, 17));
+        assert_eq!(position(parse_commands("a = b;\ns = ")), (2, 5));
+        assert!(parse_formula("x < 1 and").is_err());
+        assert!(parse_setdef("{ x | x < 1").is_err());
+        assert_eq!(parse_commands(" a = { x | x < 1 }; stats(a) ").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_parser_call_args() {
+        match &parse_commands("list(a, 10, csv); assert_member(a, -3); save(a, \"out dir/a.pas\")").unwrap()[..] {
+            [Command::Call(n1, a1), Command::Call(_, a3), Command::Call(n2, a2)] => {
+                assert_eq!(n1, "list");
+                assert_eq!(a1, &["a", "10", "csv"]);
+                assert_eq!(a3, &["a", "-3"]);
+                assert_eq!(n2, "save");
+                assert_eq!(a2, &["a", "out dir/a.pas"]);
+            }
+            r => panic!("Unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parser_set_expr() {
+        let (_, e) = parse_exact(set_expr, "a | ~b & (c | d) \\ project(e, x, y) ").unwrap();
+        match e {
+            SetExpr::Union(a, e) => {
+                assert!(matches!(*a, SetExpr::Ref(_)));
+                match *e {
+                    SetExpr::Difference(e, p) => {
+                        assert!(matches!(*p, SetExpr::Project(_, ref names) if names.len() == 2));
+                        assert!(matches!(*e, SetExpr::Intersection(ref b, ref u) if matches!(**b, SetExpr::Complement(_)) && matches!(**u, SetExpr::Union(_, _))));
+                    }
+                    e => panic!("Unexpected {:?}", e),
+                }
+            }
+            e => panic!("Unexpected {:?}", e),
+        }
+        assert!(matches!(parse_commands("x = project(a, y)").unwrap()[0], Command::SetExpr(_, SetExpr::Project(_, _))));
+        assert!(matches!(parse_commands("x = { y | y < 2 }").unwrap()[0], Command::SetDef(_, _)));
+        assert!(matches!(&parse_commands("x = load(\"a.set\")").unwrap()[0], Command::SetExpr(_, SetExpr::Load(p)) if p == "a.set"));
+    }
+
     #[test]
     fn test_parser_commands() {
         let (_, cs) = parse_exact(commands, "aa = { x | x == x }").unwrap();
@@ -0,0 +1,705 @@
+use std::convert::TryFrom;
+use std::io::Write;
+
+use crate::common::{Name, PasError, PasResult};
+use crate::highlevel::hiformula::{BinOp, Expression, HiFormula, HiPredicate};
+use crate::solver::{AutomaticSet, Domain};
+use crate::solver::commands::{Context, SetDef};
+
+/* SMT-LIB2 front-end for (quantified) linear integer arithmetic.
+   Declared constants are tracks of a set whose formula is the conjunction of assertions;
+   check-sat tests its emptiness and the model is its minimal element.
+   Scripts where all constants and quantified variables have sort Nat (non-standard) are solved
+   in Nat domain, otherwise in Int domain where Nat variables are restricted to non-negative values */
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SExpr {
+    Symbol(String),
+    Numeral(u64),
+    String(String),
+    List(Vec<SExpr>),
+}
+
+impl SExpr {
+    fn symbol(&self) -> Option<&str> {
+        match self {
+            SExpr::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[SExpr]> {
+        match self {
+            SExpr::List(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/:".contains(c)
+}
+
+pub fn parse_sexprs(input: &str) -> PasResult<Vec<SExpr>> {
+    let error = |rest: &str, message: &str| PasError::parse_error(input, rest, message.to_string());
+    let mut stack: Vec<(Vec<SExpr>, &str)> = Vec::new();
+    let mut result = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with(';') {
+            rest = rest.find('\n').map(|i| &rest[i..]).unwrap_or("");
+            continue;
+        }
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+        let (item, length) = match c {
+            '(' => {
+                stack.push((Vec::new(), rest));
+                rest = &rest[1..];
+                continue;
+            }
+            ')' => match stack.pop() {
+                Some((items, _)) => (SExpr::List(items), 1),
+                None => return Err(error(rest, "Unexpected ')'")),
+            },
+            '|' => match rest[1..].find('|') {
+                Some(end) => (SExpr::Symbol(rest[1..end + 1].to_string()), end + 2),
+                None => return Err(error(rest, "Unterminated quoted symbol")),
+            },
+            '"' => {
+                /* "" is an escaped quote */
+                let mut value = String::new();
+                let mut chars = rest.char_indices().skip(1).peekable();
+                let mut end = None;
+                while let Some((i, c)) = chars.next() {
+                    if c == '"' {
+                        if let Some((_, '"')) = chars.peek() {
+                            chars.next();
+                        } else {
+                            end = Some(i + 1);
+                            break;
+                        }
+                    }
+                    value.push(c);
+                }
+                match end {
+                    Some(end) => (SExpr::String(value), end),
+                    None => return Err(error(rest, "Unterminated string")),
+                }
+            }
+            c if is_symbol_char(c) => {
+                let length = rest.find(|c| !is_symbol_char(c)).unwrap_or(rest.len());
+                let token = &rest[..length];
+                if token.starts_with(|c: char| c.is_ascii_digit()) {
+                    match token.parse::<u64>() {
+                        Ok(value) => (SExpr::Numeral(value), length),
+                        Err(_) => return Err(error(rest, "Invalid numeral")),
+                    }
+                } else {
+                    (SExpr::Symbol(token.to_string()), length)
+                }
+            }
+            _ => return Err(error(rest, "Unexpected character")),
+        };
+        rest = &rest[length..];
+        match stack.last_mut() {
+            Some((items, _)) => items.push(item),
+            None => result.push(item),
+        }
+    }
+    if let Some((_, start)) = stack.pop() {
+        return Err(error(start, "Unclosed '('"));
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Sort {
+    Int,
+    Nat,
+}
+
+impl Sort {
+    fn parse(sort: &SExpr) -> PasResult<Sort> {
+        match sort.symbol() {
+            Some("Int") => Ok(Sort::Int),
+            Some("Nat") => Ok(Sort::Nat),
+            _ => Err(PasError::Unsupported(format!("sort {}", show(sort)))),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Sort::Int => "Int",
+            Sort::Nat => "Nat",
+        }
+    }
+}
+
+fn show(expr: &SExpr) -> String {
+    match expr {
+        SExpr::Symbol(s) => s.clone(),
+        SExpr::Numeral(v) => v.to_string(),
+        SExpr::String(s) => format!("\"{}\"", s.replace('"', "\"\"")),
+        SExpr::List(items) => format!("({})", items.iter().map(show).collect::<Vec<_>>().join(" ")),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Term(Expression),
+    Formula(HiFormula),
+}
+
+fn nat_guard(name: &Name) -> HiFormula {
+    HiFormula::Predicate(HiPredicate::BinOp(BinOp::Lte, Expression::Constant(0), Expression::Variable(name.clone())))
+}
+
+fn constant_expr(value: i64) -> Expression {
+    if value < 0 {
+        Expression::new_neg(Expression::Constant(value.unsigned_abs()))
+    } else {
+        Expression::Constant(value as u64)
+    }
+}
+
+/* None for terms with variables; constants that do not fit into i64 are refused */
+fn constant_value(expr: &Expression) -> PasResult<Option<i64>> {
+    /* Outer None for terms with variables, inner None on overflow */
+    fn value(expr: &Expression) -> Option<Option<i128>> {
+        Some(match expr {
+            Expression::Constant(v) => Some(*v as i128),
+            Expression::Neg(e) => value(e)?.and_then(|v| v.checked_neg()),
+            Expression::Add(es) => {
+                let values = es.iter().map(value).collect::<Option<Vec<_>>>()?;
+                values.into_iter().try_fold(0i128, |sum, v| sum.checked_add(v?))
+            }
+            Expression::Mul(e, x) => value(e)?.and_then(|v| v.checked_mul(*x as i128)),
+            Expression::Variable(_) | Expression::Mod(_, _) => return None,
+        })
+    }
+    match value(expr) {
+        Some(v) => v.and_then(|v| i64::try_from(v).ok()).map(Some).ok_or_else(PasError::overflow),
+        None => Ok(None),
+    }
+}
+
+fn scale(expr: Expression, factor: i64) -> Expression {
+    match factor {
+        0 => Expression::Constant(0),
+        1 => expr,
+        -1 => Expression::new_neg(expr),
+        f if f < 0 => Expression::new_neg(Expression::Mul(Box::new(expr), f.unsigned_abs())),
+        f => Expression::Mul(Box::new(expr), f as u64),
+    }
+}
+
+fn fold_formulas<I: Iterator<Item=HiFormula>>(formulas: I, empty: HiFormula, op: fn(HiFormula, HiFormula) -> HiFormula) -> HiFormula {
+    formulas.reduce(op).unwrap_or(empty)
+}
+
+fn implies(lhs: HiFormula, rhs: HiFormula) -> HiFormula {
+    lhs.neg().or(rhs)
+}
+
+fn iff(lhs: HiFormula, rhs: HiFormula) -> HiFormula {
+    lhs.clone().and(rhs.clone()).or(lhs.neg().and(rhs.neg()))
+}
+
+#[derive(Debug, Default)]
+struct Frame {
+    constants: Vec<(Name, Sort)>,
+    /* Nullary define-fun; the flag is set when the body uses Int sort */
+    definitions: Vec<(String, Value, bool)>,
+    assertions: Vec<(HiFormula, bool)>,
+}
+
+/* Translation of terms of one command; "scope" holds let bindings and quantified variables */
+struct Translator<'a> {
+    frames: &'a [Frame],
+    scope: Vec<(String, Value)>,
+    uses_int: bool,
+}
+
+impl<'a> Translator<'a> {
+    fn new(frames: &'a [Frame]) -> Self {
+        Translator { frames, scope: Vec::new(), uses_int: false }
+    }
+
+    fn lookup(&mut self, symbol: &str) -> PasResult<Value> {
+        if let Some((_, value)) = self.scope.iter().rev().find(|(s, _)| s == symbol) {
+            return Ok(value.clone());
+        }
+        for frame in self.frames.iter().rev() {
+            if let Some((_, value, uses_int)) = frame.definitions.iter().rev().find(|(s, _, _)| s == symbol) {
+                self.uses_int |= *uses_int;
+                return Ok(value.clone());
+            }
+            if let Some((name, _)) = frame.constants.iter().find(|(n, _)| *n == Name::from_str(symbol)) {
+                return Ok(Value::Term(Expression::Variable(name.clone())));
+            }
+        }
+        match symbol {
+            "true" => Ok(Value::Formula(HiFormula::Predicate(HiPredicate::True))),
+            "false" => Ok(Value::Formula(HiFormula::Predicate(HiPredicate::False))),
+            _ => Err(PasError::UndefinedVariable(symbol.to_string())),
+        }
+    }
+
+    fn term(&mut self, expr: &SExpr) -> PasResult<Expression> {
+        match self.translate(expr)? {
+            Value::Term(e) => Ok(e),
+            Value::Formula(_) => Err(PasError::InvalidArgument(format!("{} is not a term", show(expr)))),
+        }
+    }
+
+    fn formula(&mut self, expr: &SExpr) -> PasResult<HiFormula> {
+        match self.translate(expr)? {
+            Value::Formula(f) => Ok(f),
+            Value::Term(_) => Err(PasError::InvalidArgument(format!("{} is not a formula", show(expr)))),
+        }
+    }
+
+    fn terms(&mut self, args: &[SExpr]) -> PasResult<Vec<Expression>> {
+        args.iter().map(|a| self.term(a)).collect()
+    }
+
+    fn formulas(&mut self, args: &[SExpr]) -> PasResult<Vec<HiFormula>> {
+        args.iter().map(|a| self.formula(a)).collect()
+    }
+
+    fn constant(&mut self, expr: &SExpr) -> PasResult<i64> {
+        let term = self.term(expr)?;
+        constant_value(&term)?.ok_or_else(|| PasError::Unsupported(format!("non-constant {} (only linear arithmetic is supported)", show(expr))))
+    }
+
+    /* (< a b c) ~~> a < b and b < c */
+    fn chain(&mut self, args: &[SExpr], compare: fn(Expression, Expression) -> HiFormula) -> PasResult<HiFormula> {
+        let terms = self.terms(args)?;
+        Ok(fold_formulas(terms.windows(2).map(|w| compare(w[0].clone(), w[1].clone())), HiFormula::Predicate(HiPredicate::True), HiFormula::and))
+    }
+
+    fn quantifier(&mut self, vars: &SExpr, body: &SExpr, exists: bool) -> PasResult<HiFormula> {
+        let mut bound = Vec::new();
+        for var in vars.list().ok_or_else(|| PasError::InvalidArgument(show(vars)))? {
+            match var.list() {
+                Some([SExpr::Symbol(symbol), sort]) => {
+                    let sort = Sort::parse(sort)?;
+                    self.uses_int |= sort == Sort::Int;
+                    /* Fresh names avoid capturing variables of let bindings */
+                    let name = Name::new_unnamed();
+                    self.scope.push((symbol.clone(), Value::Term(Expression::Variable(name.clone()))));
+                    bound.push((name, sort));
+                }
+                _ => return Err(PasError::InvalidArgument(show(var))),
+            }
+        }
+        let formula = self.formula(body);
+        self.scope.truncate(self.scope.len() - bound.len());
+        Ok(bound.into_iter().rev().fold(formula?, |f, (name, sort)| {
+            match (exists, sort) {
+                (true, Sort::Int) => HiFormula::Exists(name, Box::new(f)),
+                (true, Sort::Nat) => HiFormula::Exists(name.clone(), Box::new(nat_guard(&name).and(f))),
+                (false, Sort::Int) => HiFormula::ForAll(name, Box::new(f)),
+                (false, Sort::Nat) => HiFormula::ForAll(name.clone(), Box::new(implies(nat_guard(&name), f))),
+            }
+        }))
+    }
+
+    fn translate(&mut self, expr: &SExpr) -> PasResult<Value> {
+        let items = match expr {
+            SExpr::Numeral(v) => return Ok(Value::Term(Expression::Constant(*v))),
+            SExpr::Symbol(s) => return self.lookup(s),
+            SExpr::String(_) => return Err(PasError::Unsupported(format!("string {}", show(expr)))),
+            SExpr::List(items) => items,
+        };
+        let (head, args) = match items.split_first() {
+            Some((SExpr::Symbol(head), args)) => (head.as_str(), args),
+            _ => return Err(PasError::Unsupported(format!("term {}", show(expr)))),
+        };
+        let arity = |expected: usize| {
+            if args.len() != expected {
+                return Err(PasError::ArityMismatch { command: head.to_string(), expected, got: args.len() });
+            }
+            Ok(())
+        };
+        let formula = match head {
+            "+" => return Ok(Value::Term(Expression::new_add(self.terms(args)?))),
+            "-" => {
+                let mut terms = self.terms(args)?;
+                if terms.is_empty() {
+                    return Err(PasError::ArityMismatch { command: head.to_string(), expected: 1, got: 0 });
+                }
+                if terms.len() == 1 {
+                    return Ok(Value::Term(Expression::new_neg(terms.pop().unwrap())));
+                }
+                let first = terms.remove(0);
+                return Ok(Value::Term(Expression::new_add(std::iter::once(first).chain(terms.into_iter().map(Expression::new_neg)).collect())));
+            }
+            "*" => {
+                let mut factor = 1i64;
+                let mut variable = None;
+                for term in self.terms(args)? {
+                    match (constant_value(&term)?, &variable) {
+                        (Some(v), _) => factor = factor.checked_mul(v).ok_or_else(PasError::overflow)?,
+                        (None, None) => variable = Some(term),
+                        (None, Some(_)) => return Err(PasError::Unsupported(format!("non-linear term {}", show(expr)))),
+                    }
+                }
+                return Ok(Value::Term(match variable {
+                    Some(term) => scale(term, factor),
+                    None => constant_expr(factor),
+                }));
+            }
+            "mod" => {
+                arity(2)?;
+                let term = self.term(&args[0])?;
+                match self.constant(&args[1])? {
+                    0 => return Err(PasError::Unsupported(format!("division by zero in {}", show(expr)))),
+                    m => return Ok(Value::Term(Expression::Mod(Box::new(term), m.unsigned_abs()))),
+                }
+            }
+            "not" => {
+                arity(1)?;
+                self.formula(&args[0])?.neg()
+            }
+            "and" => fold_formulas(self.formulas(args)?.into_iter(), HiFormula::Predicate(HiPredicate::True), HiFormula::and),
+            "or" => fold_formulas(self.formulas(args)?.into_iter(), HiFormula::Predicate(HiPredicate::False), HiFormula::or),
+            "=>" => {
+                let mut formulas = self.formulas(args)?;
+                let last = formulas.pop().ok_or_else(|| PasError::ArityMismatch { command: head.to_string(), expected: 2, got: 0 })?;
+                formulas.into_iter().rev().fold(last, |f, lhs| implies(lhs, f))
+            }
+            "xor" => {
+                let formulas = self.formulas(args)?;
+                fold_formulas(formulas.into_iter(), HiFormula::Predicate(HiPredicate::False), |a, b| iff(a, b).neg())
+            }
+            "ite" => {
+                arity(3)?;
+                let condition = self.formula(&args[0])?;
+                match (self.translate(&args[1])?, self.translate(&args[2])?) {
+                    (Value::Formula(a), Value::Formula(b)) => condition.clone().and(a).or(condition.neg().and(b)),
+                    _ => return Err(PasError::Unsupported(format!("ite over terms in {}", show(expr)))),
+                }
+            }
+            "=" | "distinct" => {
+                let values = args.iter().map(|a| self.translate(a)).collect::<PasResult<Vec<_>>>()?;
+                let mut pairs = Vec::new();
+                for (i, a) in values.iter().enumerate() {
+                    let others = if head == "=" { &values[i + 1..values.len().min(i + 2)] } else { &values[i + 1..] };
+                    for b in others {
+                        pairs.push(match (a, b) {
+                            (Value::Term(a), Value::Term(b)) => HiFormula::Predicate(HiPredicate::BinOp(BinOp::Eq, a.clone(), b.clone())),
+                            (Value::Formula(a), Value::Formula(b)) => iff(a.clone(), b.clone()),
+                            _ => return Err(PasError::InvalidArgument(format!("mixed terms and formulas in {}", show(expr)))),
+                        });
+                    }
+                }
+                let pairs = pairs.into_iter().map(|f| if head == "=" { f } else { f.neg() });
+                fold_formulas(pairs, HiFormula::Predicate(HiPredicate::True), HiFormula::and)
+            }
+            "<" => self.chain(args, |a, b| HiFormula::Predicate(HiPredicate::BinOp(BinOp::Lt, a, b)))?,
+            "<=" => self.chain(args, |a, b| HiFormula::Predicate(HiPredicate::BinOp(BinOp::Lte, a, b)))?,
+            ">" => self.chain(args, |a, b| HiFormula::Predicate(HiPredicate::BinOp(BinOp::Lt, b, a)))?,
+            ">=" => self.chain(args, |a, b| HiFormula::Predicate(HiPredicate::BinOp(BinOp::Lte, b, a)))?,
+            "exists" | "forall" => {
+                arity(2)?;
+                self.quantifier(&args[0], &args[1], head == "exists")?
+            }
+            "let" => {
+                arity(2)?;
+                /* Bindings are parallel, all of them are translated in the outer scope */
+                let mut bindings = Vec::new();
+                for binding in args[0].list().ok_or_else(|| PasError::InvalidArgument(show(&args[0])))? {
+                    match binding.list() {
+                        Some([SExpr::Symbol(symbol), value]) => bindings.push((symbol.clone(), self.translate(value)?)),
+                        _ => return Err(PasError::InvalidArgument(show(binding))),
+                    }
+                }
+                let n_bindings = bindings.len();
+                self.scope.extend(bindings);
+                let value = self.translate(&args[1]);
+                self.scope.truncate(self.scope.len() - n_bindings);
+                return value;
+            }
+            /* Annotations (e.g. :named) are ignored */
+            "!" if !args.is_empty() => return self.translate(&args[0]),
+            _ => return Err(PasError::Unsupported(format!("operator '{}'", head))),
+        };
+        Ok(Value::Formula(formula))
+    }
+}
+
+pub struct SmtSession {
+    context: Context,
+    frames: Vec<Frame>,
+    model: Option<AutomaticSet>,
+}
+
+impl SmtSession {
+    pub fn new(context: Context) -> Self {
+        SmtSession { context, frames: vec![Frame::default()], model: None }
+    }
+
+    fn sort_of(&self, name: &Name) -> Sort {
+        self.frames.iter().flat_map(|f| f.constants.iter()).find(|(n, _)| n == name).map(|(_, s)| *s).unwrap()
+    }
+
+    fn declare(&mut self, symbol: &str, sort: Sort) -> PasResult<()> {
+        let name = Name::from_str(symbol);
+        let defined = self.frames.iter().any(|f| f.constants.iter().any(|(n, _)| *n == name) || f.definitions.iter().any(|(s, _, _)| s == symbol));
+        if defined {
+            return Err(PasError::DuplicateVariable(symbol.to_string()));
+        }
+        self.frames.last_mut().unwrap().constants.push((name, sort));
+        Ok(())
+    }
+
+    fn set_def(&self) -> SetDef {
+        let constants: Vec<&(Name, Sort)> = self.frames.iter().flat_map(|f| f.constants.iter()).collect();
+        let assertions: Vec<&(HiFormula, bool)> = self.frames.iter().flat_map(|f| f.assertions.iter()).collect();
+        let uses_int = constants.iter().any(|(_, s)| *s == Sort::Int) || assertions.iter().any(|(_, i)| *i);
+        let guards = constants.iter().filter(|(_, s)| uses_int && *s == Sort::Nat).map(|(n, _)| nat_guard(n));
+        let formula = fold_formulas(guards.chain(assertions.iter().map(|(f, _)| f.clone())), HiFormula::Predicate(HiPredicate::True), HiFormula::and);
+        SetDef {
+            vars: constants.iter().map(|(n, _)| n.clone()).collect(),
+            formula,
+            domain: if uses_int { Domain::Int } else { Domain::Nat },
+        }
+    }
+
+    fn check_sat(&mut self) -> PasResult<&'static str> {
+        self.model = None;
+        match self.context.build_set(&self.set_def()) {
+            Ok(mut aset) => {
+                if aset.is_empty() {
+                    return Ok("unsat");
+                }
+                self.model = Some(aset);
+                Ok("sat")
+            }
+            Err(PasError::ResourceExhausted { .. }) => Ok("unknown"),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn format_model(&self) -> PasResult<String> {
+        let aset = self.model.as_ref().ok_or_else(|| PasError::Unsupported("model is not available".to_string()))?;
+        let mut out = String::from("(\n");
+        let values = aset.witness()?.ok_or_else(|| PasError::Unsupported("model is not available".to_string()))?;
+        for (name, value) in values {
+            let value = if value < 0 { format!("(- {})", value.unsigned_abs()) } else { value.to_string() };
+            out.push_str(&format!("  (define-fun {:?} () {} {})\n", name, self.sort_of(&name).name(), value));
+        }
+        out.push(')');
+        Ok(out)
+    }
+
+    /* Returns false for (exit) */
+    pub fn eval<W: Write>(&mut self, command: &SExpr, out: &mut W) -> PasResult<bool> {
+        let items = command.list().unwrap_or(&[]);
+        let (name, args) = match items.split_first() {
+            Some((SExpr::Symbol(name), args)) => (name.as_str(), args),
+            _ => return Err(PasError::UnknownCommand(show(command))),
+        };
+        let arity = |expected: usize| {
+            if args.len() != expected {
+                return Err(PasError::ArityMismatch { command: name.to_string(), expected, got: args.len() });
+            }
+            Ok(())
+        };
+        let levels = || match args {
+            [] => Ok(1),
+            [SExpr::Numeral(n)] => Ok(*n as usize),
+            _ => Err(PasError::InvalidArgument(show(command))),
+        };
+        if !matches!(name, "get-model" | "echo" | "set-info" | "set-option" | "set-logic" | "exit") {
+            self.model = None;
+        }
+        match name {
+            "set-logic" | "set-info" | "set-option" => {}
+            "exit" => return Ok(false),
+            "echo" => match args {
+                [SExpr::String(s)] => writeln!(out, "{}", s)?,
+                _ => return Err(PasError::InvalidArgument(show(command))),
+            },
+            "declare-const" => match args {
+                [SExpr::Symbol(symbol), sort] => self.declare(symbol, Sort::parse(sort)?)?,
+                _ => return Err(PasError::InvalidArgument(show(command))),
+            },
+            "declare-fun" => match args {
+                [SExpr::Symbol(symbol), SExpr::List(params), sort] if params.is_empty() => self.declare(symbol, Sort::parse(sort)?)?,
+                [SExpr::Symbol(_), SExpr::List(_), _] => return Err(PasError::Unsupported(format!("function with arguments in {}", show(command)))),
+                _ => return Err(PasError::InvalidArgument(show(command))),
+            },
+            "define-fun" => match args {
+                [SExpr::Symbol(symbol), SExpr::List(params), sort, body] if params.is_empty() => {
+                    let mut translator = Translator::new(&self.frames);
+                    let value = match (sort.symbol(), translator.translate(body)?) {
+                        (Some("Bool"), Value::Formula(f)) => Value::Formula(f),
+                        (Some("Int"), Value::Term(e)) => Value::Term(e),
+                        _ => return Err(PasError::InvalidArgument(show(command))),
+                    };
+                    let uses_int = translator.uses_int;
+                    if self.frames.iter().any(|f| f.constants.iter().any(|(n, _)| *n == Name::from_str(symbol))) {
+                        return Err(PasError::DuplicateVariable(symbol.clone()));
+                    }
+                    self.frames.last_mut().unwrap().definitions.push((symbol.clone(), value, uses_int));
+                }
+                [SExpr::Symbol(_), SExpr::List(_), _, _] => return Err(PasError::Unsupported(format!("function with arguments in {}", show(command)))),
+                _ => return Err(PasError::InvalidArgument(show(command))),
+            },
+            "assert" => {
+                arity(1)?;
+                let mut translator = Translator::new(&self.frames);
+                let formula = translator.formula(&args[0])?;
+                let uses_int = translator.uses_int;
+                self.frames.last_mut().unwrap().assertions.push((formula, uses_int));
+            }
+            "check-sat" => {
+                arity(0)?;
+                let result = self.check_sat()?;
+                writeln!(out, "{}", result)?;
+            }
+            "get-model" => {
+                arity(0)?;
+                let model = self.format_model()?;
+                writeln!(out, "{}", model)?;
+            }
+            "push" => {
+                for _ in 0..levels()? {
+                    self.frames.push(Frame::default());
+                }
+            }
+            "pop" => {
+                let n = levels()?;
+                if n >= self.frames.len() {
+                    return Err(PasError::InvalidArgument(show(command)));
+                }
+                self.frames.truncate(self.frames.len() - n);
+            }
+            _ => return Err(PasError::UnknownCommand(name.to_string())),
+        }
+        Ok(true)
+    }
+}
+
+pub fn run_smtlib<W: Write>(context: Context, input: &str, out: &mut W) -> PasResult<()> {
+    let mut session = SmtSession::new(context);
+    for command in parse_sexprs(input)? {
+        if !session.eval(&command, out)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> PasResult<String> {
+        let mut out = Vec::new();
+        run_smtlib(Context::new(), input, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_parse_sexprs() {
+        let exprs = parse_sexprs("(assert (< |a b| 10)) ; comment\n(echo \"x\"\"y\")").unwrap();
+        assert_eq!(exprs.len(), 2);
+        assert_eq!(show(&exprs[0]), "(assert (< a b 10))");
+        assert_eq!(exprs[1], SExpr::List(vec![SExpr::Symbol("echo".to_string()), SExpr::String("x\"y".to_string())]));
+        assert!(matches!(parse_sexprs("(assert\n (< x 1)"), Err(PasError::Parse { line: 1, column: 1, .. })));
+        assert!(matches!(parse_sexprs("(a))"), Err(PasError::Parse { line: 1, column: 4, .. })));
+    }
+
+    #[test]
+    fn test_smtlib_sat() {
+        let output = run("(set-logic LIA)
+                          (declare-fun x () Int)
+                          (declare-const y Int)
+                          (assert (and (> x 3) (= (+ x y) 10) (distinct y 2)))
+                          (check-sat)
+                          (get-model)").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "sat");
+        let value = |name: &str| -> i64 {
+            let line = lines.iter().find(|l| l.contains(&format!("define-fun {} ", name))).unwrap();
+            let value = line.trim().trim_start_matches(&format!("(define-fun {} () Int ", name)).trim_end_matches(')');
+            if let Some(v) = value.strip_prefix("(- ") { -v.parse::<i64>().unwrap() } else { value.parse().unwrap() }
+        };
+        let (x, y) = (value("x"), value("y"));
+        assert!(x > 3 && x + y == 10 && y != 2);
+
+        let output = run("(declare-fun x () Int)
+                          (assert (< x (- 5)))
+                          (check-sat)
+                          (get-model)").unwrap();
+        assert_eq!(output, "sat\n(\n  (define-fun x () Int (- 8))\n)\n");
+
+        let output = run("(declare-const x Nat)
+                          (declare-const y Nat)
+                          (assert (= (* 3 x) (+ y 7)))
+                          (assert (= (mod y 2) 1))
+                          (check-sat)
+                          (get-model)").unwrap();
+        assert_eq!(output, "sat\n(\n  (define-fun x () Nat 4)\n  (define-fun y () Nat 5)\n)\n");
+
+        /* The shortest model has x below i64::MIN */
+        let output = run("(declare-const x Int)
+                          (declare-const y Int)
+                          (assert (< x y (- 9223372036854775807)))
+                          (check-sat)
+                          (get-model)").unwrap();
+        assert_eq!(output, "sat\n(\n  (define-fun x () Int (- 18446744073709551616))\n  (define-fun y () Int (- 18446744073709551615))\n)\n");
+    }
+
+    #[test]
+    fn test_smtlib_quantifiers() {
+        /* Every integer is even or odd; x is even without being a double of an integer */
+        let output = run("(assert (forall ((x Int)) (exists ((y Int)) (or (= x (* 2 y)) (= x (+ (* 2 y) 1))))))
+                          (check-sat)
+                          (declare-const x Int)
+                          (assert (and (= (mod x 2) 0) (not (exists ((y Int)) (= x (* y 2))))))
+                          (check-sat)").unwrap();
+        assert_eq!(output, "sat\nunsat\n");
+
+        /* Nat bounded quantifier vs Int */
+        let output = run("(assert (exists ((x Nat)) (forall ((y Nat)) (<= x y))))
+                          (check-sat)
+                          (push 1)
+                          (assert (exists ((x Int)) (forall ((y Int)) (<= x y))))
+                          (check-sat)
+                          (pop 1)
+                          (check-sat)").unwrap();
+        assert_eq!(output, "sat\nunsat\nsat\n");
+
+        let output = run("(declare-const x Int)
+                          (define-fun big () Bool (forall ((z Int)) (=> (> z x) (> z 10))))
+                          (assert (let ((b big) (y (+ x 1))) (and b (=> (> y 0) (>= x 10)))))
+                          (check-sat)
+                          (get-model)
+                          (exit)
+                          (check-sat)").unwrap();
+        assert_eq!(output, "sat\n(\n  (define-fun x () Int 10)\n)\n");
+    }
+
+    #[test]
+    fn test_smtlib_errors() {
+        assert!(matches!(run("(declare-const x Int) (declare-const x Int)"), Err(PasError::DuplicateVariable(_))));
+        assert!(matches!(run("(assert (< y 1))"), Err(PasError::UndefinedVariable(_))));
+        assert!(matches!(run("(declare-const x Int) (assert (< (* x x) 1))"), Err(PasError::Unsupported(_))));
+        assert!(matches!(run("(declare-const x Real)"), Err(PasError::Unsupported(_))));
+        assert!(matches!(run("(declare-const x Int) (assert (< x 1)) (check-sat) (assert true) (get-model)"), Err(PasError::Unsupported(_))));
+        assert!(matches!(run("(push 1) (pop 2)"), Err(PasError::InvalidArgument(_))));
+        assert!(matches!(run("(check-sat-foo)"), Err(PasError::UnknownCommand(_))));
+        assert!(matches!(run("(declare-const x Int) (assert (< x (-)))"), Err(PasError::ArityMismatch { expected: 1, got: 0, .. })));
+        assert!(matches!(run("(declare-const x Int) (assert (< x (* 10000000000 10000000000)))"), Err(PasError::Unsupported(_))));
+        assert!(matches!(run("(declare-const x Int) (assert (< (* 10000000000000000000 x) 1))"), Err(PasError::Unsupported(_))));
+        assert!(matches!(run("(push 1) (declare-const x Int) (pop 1) (assert (< x 1))"), Err(PasError::UndefinedVariable(_))));
+    }
+}
@@ -1,17 +1,24 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use structopt::StructOpt;
 
-use crate::highlevel::parser::{commands, parse_exact, unwrap_nom};
+use crate::automata::{DumpConfig, DumpStage};
+use crate::common::PasResult;
+use crate::highlevel::parser::parse_commands;
+use crate::highlevel::smtlib::run_smtlib;
+use crate::repl::Repl;
 use crate::solver::commands::{Command, Context};
+use crate::solver::TraceFormat;
 
 pub mod common;
 pub mod solver;
 pub mod automata;
 pub mod highlevel;
 pub mod render;
+pub mod repl;
 
 
 #[derive(Debug)]
@@ -31,24 +38,88 @@ impl FromStr for RenderFormat {
     }
 }
 
+#[derive(Debug, StructOpt)]
+enum SubCommand {
+    /// Interactive mode
+    Repl,
+}
+
 #[derive(Debug, StructOpt)]
 struct Opts {
-    file: String,
+    file: Option<String>,
+    /// Read the file as an SMT-LIB2 script (default for .smt2 files)
+    #[structopt(long)]
+    smtlib: bool,
+    /// Print size and depth of formulas before and after simplification
+    #[structopt(long)]
+    explain: bool,
+    /// Reorder tracks of intermediate automata to reduce their size
+    #[structopt(long)]
+    reorder: bool,
+    /// Maximal number of states of an intermediate automaton
+    #[structopt(long)]
+    max_states: Option<usize>,
+    /// Time limit of a set construction in seconds
+    #[structopt(long)]
+    timeout: Option<f64>,
+    /// Print evaluation trace of each defined set
+    #[structopt(long)]
+    trace: bool,
+    /// Format of traces: tree or json
+    #[structopt(long, default_value = "tree")]
+    trace_format: TraceFormat,
+    /// Directory for dot files of intermediate automata
+    #[structopt(long, parse(from_os_str))]
+    dump_dir: Option<PathBuf>,
+    /// Dumped stages: determinized, minimized, projected, cut (all by default)
+    #[structopt(long, use_delimiter = true)]
+    dump_stages: Vec<DumpStage>,
+    #[structopt(subcommand)]
+    cmd: Option<SubCommand>,
 }
 
-fn read_file(path: &Path) -> Vec<Command> {
-    let content = fs::read_to_string(path).unwrap();
-    let input = content.trim();
-    let (_, cmds) = unwrap_nom(input, parse_exact(commands, input));
-    cmds
+fn read_file(path: &Path) -> PasResult<Vec<Command>> {
+    let content = fs::read_to_string(path)?;
+    parse_commands(&content)
 }
 
-fn main() {
-    let opts = Opts::from_args();
-    let cmds = read_file(Path::new(&opts.file));
+fn run(opts: &Opts) -> PasResult<()> {
     let mut context = Context::new();
+    context.set_explain(opts.explain);
+    context.set_reorder(opts.reorder);
+    context.set_max_states(opts.max_states);
+    context.set_timeout(opts.timeout.map(Duration::from_secs_f64));
+    context.set_trace(if opts.trace { Some(opts.trace_format) } else { None });
+    context.set_debug_dump(opts.dump_dir.as_ref().map(|dir| DumpConfig {
+        dir: dir.clone(),
+        stages: if opts.dump_stages.is_empty() { DumpStage::ALL.to_vec() } else { opts.dump_stages.clone() },
+    }));
+    if let Some(file) = opts.file.as_ref().filter(|f| opts.smtlib || f.ends_with(".smt2")) {
+        return run_smtlib(context, &fs::read_to_string(file)?, &mut std::io::stdout());
+    }
+    if let Some(file) = &opts.file {
+        let cmds = read_file(Path::new(file))?;
+        for cmd in cmds {
+            context.eval(cmd)?;
+        }
+    }
+    if let Some(SubCommand::Repl) = opts.cmd {
+        let stdin = std::io::stdin();
+        let mut repl = Repl::new(context);
+        repl.run(stdin.lock(), &mut std::io::stdout())?;
+    }
+    Ok(())
+}
 
-    for cmd in cmds {
-        context.eval(cmd);
+fn main() {
+    let opts = Opts::from_args();
+    if opts.file.is_none() && opts.cmd.is_none() {
+        Opts::clap().print_help().unwrap();
+        println!();
+        std::process::exit(1);
+    }
+    if let Err(e) = run(&opts) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 }
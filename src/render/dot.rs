@@ -150,6 +150,6 @@ mod tests {
     }
 
     fn make_dfa(setdef: &str) -> Dfa {
-        evaluate_formula(&parse_setdef(setdef).formula().make_lo_formula()).into_dfa()
+        evaluate_formula(&parse_setdef(setdef).unwrap().formula().make_lo_formula().unwrap()).into_dfa()
     }
 }
@@ -3,7 +3,7 @@ use std::io::Write;
 use crate::automata::Dfa;
 use crate::solver::{get_max_value, iterate_elements};
 
-pub fn render_set_png<W: Write>(dfas: &[&Dfa], colors: &[[u8; 3]], writer: &mut W) {
+pub fn render_set_png<W: Write>(dfas: &[&Dfa], colors: &[[u8; 3]], writer: &mut W) -> std::io::Result<()> {
     assert!(dfas.iter().all(|x| x.n_tracks() == 2));
     let nfas: Vec<_> = dfas.iter().map(|dfa| dfa.make_nfa()).collect();
     let size_x: usize = nfas.iter().map(|nfa| get_max_value(nfa, 0).to_limit().unwrap()).max().unwrap();
@@ -24,6 +24,7 @@ pub fn render_set_png<W: Write>(dfas: &[&Dfa], colors: &[[u8; 3]], writer: &mut
     let mut encoder = png::Encoder::new(writer, size_x as u32, size_y as u32);
     encoder.set_color(png::ColorType::RGB);
     encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
-    writer.write_image_data(&data).unwrap();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
 }
@@ -0,0 +1,166 @@
+use std::io::{BufRead, Write};
+
+use crate::common::{Name, PasError, PasResult};
+use crate::highlevel::parser::parse_commands;
+use crate::solver::commands::Context;
+
+const HELP: &str = "Commands are the same as in input files, input continues while brackets are not closed.
+Directives:
+  :sets      List defined sets
+  :show X    Show details of set X
+  :undef X   Remove set X
+  :history   Show previous inputs
+  :help      Show this help
+  :quit      Exit";
+
+pub struct Repl {
+    context: Context,
+    history: Vec<String>,
+}
+
+/* Input is complete when all brackets are closed */
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+    for c in input.chars() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            _ => { /* Do nothing */ }
+        }
+    }
+    depth <= 0
+}
+
+impl Repl {
+    pub fn new(context: Context) -> Self {
+        Repl {
+            context,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> std::io::Result<()> {
+        let mut buffer = String::new();
+        write!(out, "pas> ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                let directive = line.trim();
+                self.history.push(directive.to_string());
+                if directive == ":quit" {
+                    return Ok(());
+                }
+                if let Err(e) = self.directive(directive, out) {
+                    writeln!(out, "Error: {}", e)?;
+                }
+            } else {
+                buffer.push_str(&line);
+                buffer.push('\n');
+                /* Empty line forces evaluation of an unfinished input */
+                if !is_complete(&buffer) && !line.trim().is_empty() {
+                    write!(out, "...> ")?;
+                    out.flush()?;
+                    continue;
+                }
+                let input = std::mem::take(&mut buffer);
+                if !input.trim().is_empty() {
+                    self.history.push(input.trim_end().to_string());
+                    if let Err(e) = self.eval(&input) {
+                        writeln!(out, "Error: {}", e)?;
+                    }
+                }
+            }
+            write!(out, "pas> ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    pub fn eval(&mut self, input: &str) -> PasResult<()> {
+        for cmd in parse_commands(input)? {
+            self.context.eval(cmd)?;
+        }
+        Ok(())
+    }
+
+    fn directive<W: Write>(&mut self, directive: &str, out: &mut W) -> PasResult<()> {
+        let mut parts = directive.split_whitespace();
+        let name = parts.next().unwrap();
+        let args: Vec<&str> = parts.collect();
+        match (name, args.as_slice()) {
+            (":sets", []) => {
+                let mut sets: Vec<_> = self.context.sets().collect();
+                sets.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, aset) in sets {
+                    writeln!(out, "{:?}({}) {:?}, DFA size: {}", name, format_tracks(aset.track_names()), aset.domain(), aset.dfa_size())?;
+                }
+            }
+            (":show", [set_name]) => {
+                let aset = self.context.get_set(&Name::from_str(set_name))?;
+                writeln!(out, "Tracks: {}", format_tracks(aset.track_names()))?;
+                writeln!(out, "Domain: {:?}", aset.domain())?;
+                writeln!(out, "DFA size: {}", aset.dfa_size())?;
+                match aset.size() {
+                    Some(size) => writeln!(out, "Elements: {}", size)?,
+                    None => writeln!(out, "Elements: infinite")?,
+                }
+            }
+            (":undef", [set_name]) => {
+                self.context.remove_set(&Name::from_str(set_name))?;
+            }
+            (":history", []) => {
+                for (i, entry) in self.history.iter().enumerate() {
+                    writeln!(out, "{:>4}  {}", i + 1, entry)?;
+                }
+            }
+            (":help", []) => writeln!(out, "{}", HELP)?,
+            (":sets", _) | (":show", _) | (":undef", _) | (":history", _) | (":help", _) => {
+                let expected = if name == ":show" || name == ":undef" { 1 } else { 0 };
+                return Err(PasError::ArityMismatch { command: name.to_string(), expected, got: args.len() });
+            }
+            _ => return Err(PasError::UnknownCommand(name.to_string())),
+        }
+        Ok(())
+    }
+}
+
+fn format_tracks(names: &[Name]) -> String {
+    names.iter().map(|n| format!("{:?}", n)).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, input: &str) -> String {
+        let mut out = Vec::new();
+        repl.run(input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut repl = Repl::new(Context::new());
+        let out = run(&mut repl, "a = { x | x < 10 }\nb = { x, y |\n  x < y and\n  y < 3 }\n:sets\n:show a\nc = { x | x ? 1 }\nstats(z)\n:undef a\n:undef a\n:show\n:history\n");
+        assert!(out.contains("...> "));
+        assert!(out.contains("a(x) Nat, DFA size:"));
+        assert!(out.contains("b(x, y) Nat, DFA size:"));
+        assert!(out.contains("Elements: 10"));
+        assert!(out.contains("Error: Parse error"));
+        assert!(out.contains("Error: Set 'z' not defined"));
+        assert!(out.contains("Error: Set 'a' not defined"));
+        assert!(out.contains("Error: ':show' expects 1 argument(s), got 0"));
+        assert!(out.contains("   2  b = { x, y |\n  x < y and\n  y < 3 }"));
+        assert!(repl.context().get_set(&Name::from_str("a")).is_err());
+        assert!(repl.context().get_set(&Name::from_str("b")).is_ok());
+
+        let out = run(&mut repl, "d = { x | x < 2 }; :quit\n:quit\ne = { x | x < 3 }\n");
+        assert!(out.contains("Error: Parse error"));
+        assert!(repl.context().get_set(&Name::from_str("e")).is_err());
+    }
+}
@@ -1,17 +1,222 @@
+use std::convert::TryFrom;
+
 use hashbrown::HashMap;
 
-use crate::automata::{Automaton, Dfa, Nfa, Transition, TransitionTable};
-use crate::common::{iterate_bits_no_lz, Name};
+use crate::automata::{Automaton, Dfa, dump_dfa, dump_nfa, dump_symbolic, Dumper, DumpStage, Minimizer, Nfa, SymbolicDfa, Transition, TransitionTable};
+use crate::common::{iterate_bits_no_lz, Limits, Name, PasError, PasResult, Resource, StateId};
+
+use super::{cut, decode_sign_class, decode_word, Element, get_nth_element, get_symbolic_witness, get_witness, iterate_elements, number_of_elements, number_of_symbolic_elements, SymbolicElements};
+
+/// Domain of values stored in tracks.
+/// Nat: MSB-first binary encoding padded by leading zeros
+/// Int: MSB-first two's complement encoding padded by repeating the sign symbol
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Domain {
+    Nat,
+    Int,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Relation {
+    Eq,
+    Lte,
+}
+
+impl Relation {
+    #[inline]
+    pub fn holds(&self, lhs: i128, rhs: i128) -> bool {
+        match self {
+            Relation::Eq => lhs == rhs,
+            Relation::Lte => lhs <= rhs,
+        }
+    }
+}
+
+/// Sets with at least this number of tracks are stored as symbolic automata, explicit
+/// transition tables have 2^n_tracks columns
+pub const SYMBOLIC_MIN_TRACKS: usize = 10;
 
-use super::{cut, get_nth_element, number_of_elements};
+/// Linear atoms and congruences are built as explicit tables (2^n columns for n variables),
+/// atoms with more variables are refused when they are created
+pub const MAX_ATOM_TRACKS: usize = 16;
 
 #[derive(Debug, Clone)]
 pub struct AutomaticSet {
     automaton: Automaton,
     track_names: Vec<Name>,
+    domain: Domain,
+}
+
+/// Builds DFA for "coeffs[0] * x0 + ... + coeffs[n] * xn REL constant".
+/// Automaton is constructed LSB-first where a state is the remaining right-hand side,
+/// then it is reversed into MSB-first encoding.
+/// The table is explicit (2^n columns for n variables of the atom, see MAX_ATOM_TRACKS);
+/// only combinations of atoms are stored symbolically, see SYMBOLIC_MIN_TRACKS.
+/// Both constructions and the minimization are checked against limits.
+fn linear_dfa(domain: Domain, coeffs: &[i64], rel: Relation, constant: i64, limits: &Limits) -> Result<Dfa, Resource> {
+    assert!(coeffs.len() <= MAX_ATOM_TRACKS);
+    let asize = 1 << coeffs.len();
+    let sums = symbol_sums(coeffs);
+
+    let step = |q: Option<i128>, t: i128| -> Option<i128> {
+        let d = q? - t;
+        match rel {
+            Relation::Eq if d % 2 == 0 => Some(d / 2),
+            Relation::Eq => None,
+            Relation::Lte => Some(d.div_euclid(2)),
+        }
+    };
+
+    /* State is (remaining rhs or None when unsatisfiable, accepting flag).
+       In Nat the flag depends only on the rhs (higher bits are zeros);
+       in Int the flag says whether the last read symbol is a valid sign symbol */
+    let is_accepting = |q: Option<i128>, t: i128| q.is_some_and(|q| match domain {
+        Domain::Nat => rel.holds(0, q),
+        Domain::Int => rel.holds(-t, q),
+    });
+
+    let init = (Some(constant as i128), rel.holds(0, constant as i128));
+    let mut map = HashMap::new();
+    map.insert(init, 0 as StateId);
+    let mut stack = vec![init];
+    let mut transitions = vec![0; asize];
+    let mut accepting = vec![init.1];
+
+    while let Some(state) = stack.pop() {
+        let s_id = map[&state] as usize;
+        for (a, t) in sums.iter().enumerate() {
+            let q = step(state.0, *t);
+            let new_state = (q, match domain {
+                Domain::Nat => is_accepting(q, 0),
+                Domain::Int => is_accepting(state.0, *t),
+            });
+            let id = match map.get(&new_state) {
+                Some(id) => *id,
+                None => {
+                    accepting.push(new_state.1);
+                    transitions.resize(transitions.len() + asize, 0);
+                    stack.push(new_state);
+                    map.insert(new_state, (accepting.len() - 1) as StateId);
+                    limits.check(accepting.len())?;
+                    (accepting.len() - 1) as StateId
+                }
+            };
+            transitions[s_id * asize + a] = id;
+        }
+    }
+    let reversed = Dfa::new(TransitionTable::new(coeffs.len(), transitions), accepting).reverse();
+    reversed.determinize_with(|n| limits.check(n))?.minimize_checked(Minimizer::Hopcroft, |n| limits.check(n))
+}
+
+/* Value of "coeffs[0] * x0 + ... + coeffs[n] * xn" for each symbol; i128 does not overflow */
+fn symbol_sums(coeffs: &[i64]) -> Vec<i128> {
+    (0..1usize << coeffs.len()).map(|a| {
+        coeffs.iter().enumerate().filter(|(i, _)| (a >> i) & 1 == 1).map(|(_, c)| *c as i128).sum()
+    }).collect()
+}
+
+/// Builds DFA for "coeffs[0] * x0 + ... + coeffs[n] * xn == residue (mod modulus)".
+/// States are residues of the already read prefix, in Int domain there is an extra initial state
+/// because the first symbol is the sign; the number of states is checked against limits up front.
+fn congruence_dfa(domain: Domain, coeffs: &[i64], modulus: u64, residue: u64, limits: &Limits) -> Result<Dfa, Resource> {
+    assert!(modulus > 0);
+    assert!(residue < modulus);
+    assert!(coeffs.len() <= MAX_ATOM_TRACKS);
+    let m = modulus as i128;
+    let sums = symbol_sums(coeffs);
+    let shift = match domain {
+        Domain::Nat => 0,
+        Domain::Int => 1,
+    };
+    limits.check(usize::try_from(modulus).unwrap_or(usize::MAX).saturating_add(shift))?;
+    let mut transitions = Vec::with_capacity((m as usize + shift) * sums.len());
+    let mut accepting = Vec::with_capacity(m as usize + shift);
+    if domain == Domain::Int {
+        transitions.extend(sums.iter().map(|t| ((-t).rem_euclid(m) as usize + shift) as StateId));
+        accepting.push(residue == 0);
+    }
+    for r in 0..m {
+        transitions.extend(sums.iter().map(|t| ((2 * r + t).rem_euclid(m) as usize + shift) as StateId));
+        accepting.push(r as u64 == residue);
+    }
+    Dfa::new(TransitionTable::new(coeffs.len(), transitions), accepting).minimize_checked(Minimizer::Hopcroft, |n| limits.check(n))
+}
+
+/* Nat elements in the order of iterate_elements, skipping the first "offset" ones */
+fn nat_elements(dfa: &Dfa, offset: usize, limit: Option<usize>) -> Vec<Element> {
+    if dfa.n_tracks() == 0 {
+        /* Sentence; the only possible element is the empty tuple */
+        let empty = dfa.n_states() == 1 && !dfa.is_accepting(0);
+        return if offset == 0 && limit != Some(0) && !empty { vec![Element::new(0)] } else { Vec::new() };
+    }
+    if offset == 0 {
+        let mut result = Vec::new();
+        iterate_elements(dfa, limit, |e| result.push(e.clone()));
+        return result;
+    }
+    let end = match (number_of_elements(dfa), limit) {
+        (Some(size), Some(limit)) => size.min(offset + limit),
+        (Some(size), None) => size,
+        (None, Some(limit)) => offset + limit,
+        (None, None) => panic!("Enumeration of an infinite set without limit"),
+    };
+    (offset..end).map(|i| get_nth_element(dfa, i)).collect()
 }
 
 impl AutomaticSet {
+    pub fn congruence(domain: Domain, track_names: Vec<Name>, coeffs: &[i64], modulus: u64, residue: u64) -> AutomaticSet {
+        Self::congruence_within(domain, track_names, coeffs, modulus, residue, &Limits::none()).unwrap()
+    }
+
+    pub fn congruence_within(domain: Domain, track_names: Vec<Name>, coeffs: &[i64], modulus: u64, residue: u64, limits: &Limits) -> Result<AutomaticSet, Resource> {
+        assert_eq!(track_names.len(), coeffs.len());
+        Ok(AutomaticSet {
+            automaton: Automaton::Dfa(congruence_dfa(domain, coeffs, modulus, residue, limits)?),
+            track_names,
+            domain,
+        })
+    }
+
+    pub fn linear(domain: Domain, track_names: Vec<Name>, coeffs: &[i64], rel: Relation, constant: i64) -> AutomaticSet {
+        Self::linear_within(domain, track_names, coeffs, rel, constant, &Limits::none()).unwrap()
+    }
+
+    pub fn linear_within(domain: Domain, track_names: Vec<Name>, coeffs: &[i64], rel: Relation, constant: i64, limits: &Limits) -> Result<AutomaticSet, Resource> {
+        assert_eq!(track_names.len(), coeffs.len());
+        Ok(AutomaticSet {
+            automaton: Automaton::Dfa(linear_dfa(domain, coeffs, rel, constant, limits)?),
+            track_names,
+            domain,
+        })
+    }
+
+    /* name1 < name2 */
+    pub fn less_than(domain: Domain, name1: Name, name2: Name) -> AutomaticSet {
+        assert_ne!(name1, name2);
+        Self::linear(domain, vec![name1, name2], &[1, -1], Relation::Lte, -1)
+    }
+
+    pub fn int_singleton(track_name: Name, value: i64) -> AutomaticSet {
+        Self::linear(Domain::Int, vec![track_name], &[1], Relation::Eq, value)
+    }
+
+    pub fn int_double(name1: Name, name2: Name) -> AutomaticSet {
+        assert_ne!(name1, name2);
+        Self::linear(Domain::Int, vec![name1, name2], &[2, -1], Relation::Eq, 0)
+    }
+
+    pub fn int_equivalence(name1: Name, name2: Name) -> AutomaticSet {
+        assert_ne!(name1, name2);
+        Self::linear(Domain::Int, vec![name1, name2], &[1, -1], Relation::Eq, 0)
+    }
+
+    pub fn int_addition(name1: Name, name2: Name, name3: Name) -> AutomaticSet {
+        assert_ne!(name1, name2);
+        assert_ne!(name1, name3);
+        assert_ne!(name2, name3);
+        Self::linear(Domain::Int, vec![name1, name2, name3], &[1, 1, -1], Relation::Eq, 0)
+    }
+
     pub fn singleton(track_name: Name, value: u64) -> AutomaticSet {
         let mut transitions = Vec::new();
         let mut state_id = 0;
@@ -39,6 +244,7 @@ impl AutomaticSet {
         AutomaticSet {
             automaton: Automaton::Nfa(nfa),
             track_names: vec![track_name],
+            domain: Domain::Nat,
         }
     }
 
@@ -54,13 +260,29 @@ impl AutomaticSet {
         AutomaticSet {
             automaton: Automaton::Dfa(Dfa::new(table, vec![true, false, false])),
             track_names: vec![name1, name2],
+            domain: Domain::Nat,
+        }
+    }
+
+    /* State 0 of the DFA is initial; tracks are in the order of track_names */
+    pub fn from_dfa(domain: Domain, track_names: Vec<Name>, dfa: Dfa) -> AutomaticSet {
+        assert_eq!(dfa.n_tracks(), track_names.len());
+        AutomaticSet {
+            automaton: Automaton::Dfa(dfa),
+            track_names,
+            domain,
         }
     }
 
     pub fn trivial(accepting: bool) -> AutomaticSet {
+        Self::trivial_in(Domain::Nat, accepting)
+    }
+
+    pub fn trivial_in(domain: Domain, accepting: bool) -> AutomaticSet {
         AutomaticSet {
             automaton: Automaton::Dfa(Dfa::trivial(accepting)),
             track_names: Vec::new(),
+            domain,
         }
     }
 
@@ -73,6 +295,7 @@ impl AutomaticSet {
         AutomaticSet {
             automaton: Automaton::Dfa(Dfa::new(table, vec![true, false])),
             track_names: vec![name1, name2],
+            domain: Domain::Nat,
         }
     }
 
@@ -96,14 +319,15 @@ impl AutomaticSet {
         AutomaticSet {
             automaton: Automaton::Nfa(Nfa::new(table, vec![true, false], Nfa::simple_init())),
             track_names: vec![name1, name2, name3],
+            domain: Domain::Nat,
         }
     }
 
-    pub fn cut(&self, nth_element: usize, _lte: bool) -> AutomaticSet {
+    pub fn cut(&self, nth_element: usize, _lte: bool, dump: Option<&Dumper>) -> AutomaticSet {
         let self_dfa = self.automaton.make_dfa();
         let element = get_nth_element(&self_dfa, nth_element);
         assert_eq!(element.n_tracks(), self.track_names.len());
-        let cut_nfa = cut(&element);
+        let cut_nfa = cut(&element, dump);
         let neg_cut_nfa = cut_nfa.make_dfa().neg().to_nfa();
 
         let mut nfa = self_dfa.neg().to_nfa();
@@ -113,15 +337,16 @@ impl AutomaticSet {
         AutomaticSet {
             track_names: self.track_names.to_vec(),
             automaton: Automaton::Dfa(dfa),
+            domain: self.domain,
         }
     }
 
-    pub fn cut2(&self, nth_element: usize) -> (AutomaticSet, AutomaticSet) {
+    pub fn cut2(&self, nth_element: usize, dump: Option<&Dumper>) -> (AutomaticSet, AutomaticSet) {
         let self_dfa = self.automaton.make_dfa();
         let element = get_nth_element(&self_dfa, nth_element);
         assert_eq!(element.n_tracks(), self.track_names.len());
 
-        let cut_nfa = cut(&element);
+        let cut_nfa = cut(&element, dump);
         let neg_cut_nfa = cut_nfa.make_dfa().neg().to_nfa();
 
         let mut nfa1 = self_dfa.neg().to_nfa();
@@ -135,17 +360,24 @@ impl AutomaticSet {
         (AutomaticSet {
             track_names: self.track_names.to_vec(),
             automaton: Automaton::Dfa(dfa1),
+            domain: self.domain,
         },
          AutomaticSet {
              track_names: self.track_names.to_vec(),
              automaton: Automaton::Dfa(dfa2),
+             domain: self.domain,
          })
     }
 
     pub fn neg(self) -> AutomaticSet {
+        let automaton = match self.automaton {
+            Automaton::Symbolic(sdfa) => Automaton::Symbolic(sdfa.complement()),
+            automaton => Automaton::Dfa(automaton.into_dfa().neg()),
+        };
         AutomaticSet {
             track_names: self.track_names,
-            automaton: Automaton::Dfa(self.automaton.into_dfa().neg()),
+            automaton,
+            domain: self.domain,
         }
     }
 
@@ -153,25 +385,125 @@ impl AutomaticSet {
         &self.track_names
     }
 
+    #[inline]
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
     pub fn union(mut self, mut other: AutomaticSet) -> AutomaticSet {
+        assert_eq!(self.domain, other.domain);
         self.synchronize_tracks(&mut other);
+        if let Some((a1, a2)) = Self::symbolic_pair(&self, &other) {
+            return AutomaticSet {
+                automaton: Automaton::Symbolic(a1.product(a2, |x, y| x || y)),
+                track_names: self.track_names,
+                domain: self.domain,
+            };
+        }
         let mut a1 = self.automaton.into_nfa();
         let a2 = other.automaton.into_nfa();
         a1.join(&a2);
         let r = AutomaticSet {
             track_names: self.track_names,
             automaton: Automaton::Nfa(a1),
+            domain: self.domain,
         };
 
         r
     }
 
     pub fn intersection(self, other: AutomaticSet) -> AutomaticSet {
-        self.neg().union(other.neg()).neg()
+        self.intersection_within(other, &Limits::none(), None).unwrap()
+    }
+
+    /* Product and minimization of explicit automata are checked against limits;
+       the product is dumped as determinized and the result as minimized */
+    pub fn intersection_within(mut self, mut other: AutomaticSet, limits: &Limits, dump: Option<&Dumper>) -> Result<AutomaticSet, Resource> {
+        assert_eq!(self.domain, other.domain);
+        self.synchronize_tracks(&mut other);
+        if let Some((a1, a2)) = Self::symbolic_pair(&self, &other) {
+            let sdfa = a1.product(a2, |x, y| x && y);
+            dump_symbolic(dump, DumpStage::Minimized, &sdfa);
+            return Ok(AutomaticSet {
+                automaton: Automaton::Symbolic(sdfa),
+                track_names: self.track_names,
+                domain: self.domain,
+            });
+        }
+        /* Product of NFAs tends to blow up before determinization (especially for unions),
+           so operands are determinized first; it is still one determinization less
+           than complementing both operands */
+        let dfa = self.automaton.into_dfa().intersection_with(&other.automaton.into_dfa(), |n| limits.check(n))?;
+        dump_dfa(dump, DumpStage::Determinized, &dfa);
+        let dfa = dfa.minimize_checked(Minimizer::Hopcroft, |n| limits.check(n))?;
+        dump_dfa(dump, DumpStage::Minimized, &dfa);
+        Ok(AutomaticSet {
+            track_names: self.track_names,
+            automaton: Automaton::Dfa(dfa),
+            domain: self.domain,
+        })
     }
 
+    /* Symbolic automata are not expanded; integers are counted without building classes of signs */
     pub fn size(&self) -> Option<usize> {
-        number_of_elements(&self.automaton.make_dfa())
+        match (&self.automaton, self.domain) {
+            (Automaton::Symbolic(sdfa), domain) => number_of_symbolic_elements(sdfa, domain),
+            (automaton, Domain::Nat) => number_of_elements(&automaton.make_dfa().minimize()),
+            (automaton, Domain::Int) => number_of_symbolic_elements(&SymbolicDfa::from_dfa(&automaton.make_dfa()), Domain::Int),
+        }
+    }
+
+    /* Decoded elements, skipping the first "offset" ones; limit has to be given for infinite sets.
+       Nat elements are in the order of iterate_elements; Int elements are taken alternately from
+       classes of sign combinations (see decode_sign_class), each in the order of iterate_elements.
+       Classes are enumerated on the symbolic automaton (see SymbolicElements) without building them */
+    pub fn elements(&self, offset: usize, limit: Option<usize>) -> Vec<Vec<i128>> {
+        match (&self.automaton, self.domain) {
+            (Automaton::Symbolic(sdfa), _) => self.symbolic_elements(sdfa, offset, limit),
+            (automaton, Domain::Int) if !self.track_names.is_empty() => {
+                self.symbolic_elements(&SymbolicDfa::from_dfa(&automaton.make_dfa()), offset, limit)
+            }
+            (automaton, _) => {
+                nat_elements(&automaton.make_dfa(), offset, limit).into_iter().map(|e| e.into_vec().into_iter().map(|v| v as i128).collect()).collect()
+            }
+        }
+    }
+
+    /* Only the first "end" classes with elements can be taken */
+    fn symbolic_elements(&self, sdfa: &SymbolicDfa, offset: usize, limit: Option<usize>) -> Vec<Vec<i128>> {
+        let end = match limit {
+            Some(limit) => offset + limit,
+            None => self.size().expect("Enumeration of an infinite set without limit"),
+        };
+        let enumeration = SymbolicElements::new(sdfa);
+        let collect = |start: StateId, sign: usize| {
+            let mut result = Vec::new();
+            if end > 0 {
+                enumeration.iterate(start, sign, |e| {
+                    result.push(match self.domain {
+                        Domain::Nat => e.as_slice().iter().map(|v| *v as i128).collect(),
+                        Domain::Int => decode_sign_class(sign, e),
+                    });
+                    result.len() == end
+                });
+            }
+            result
+        };
+        if self.domain == Domain::Nat {
+            return collect(0, 0).into_iter().skip(offset).collect();
+        }
+        let classes: Vec<Vec<Vec<i128>>> = enumeration.signs().take(end).map(|(sign, start)| collect(start, sign)).collect();
+        let length = classes.iter().map(|c| c.len()).max().unwrap_or(0);
+        (0..length).flat_map(|i| classes.iter().filter_map(move |c| c.get(i).cloned())).skip(offset).take(end - offset).collect()
+    }
+
+    /* Smallest element (see get_witness) as values of track_names; None for the empty set */
+    pub fn witness(&self) -> PasResult<Option<Vec<(Name, i128)>>> {
+        let values = match &self.automaton {
+            Automaton::Symbolic(sdfa) => get_symbolic_witness(sdfa, self.domain)?,
+            automaton => get_witness(&automaton.make_dfa(), self.domain)?,
+        };
+        Ok(values.map(|values| self.track_names.iter().cloned().zip(values).collect()))
     }
 
     pub fn order_tracks(&mut self, names: &[Name]) {
@@ -189,8 +521,59 @@ impl AutomaticSet {
         debug_assert_eq!(names, self.track_names.as_slice());
     }
 
+    /* Sifting: each track is moved through all positions by adjacent swaps and left where the
+       decision diagrams are smallest. Explicit automata are kept, permuting tracks only permutes
+       their alphabet, so the number of states does not depend on the order */
+    pub fn reorder_tracks(&mut self) {
+        let size = |aset: &AutomaticSet| match &aset.automaton {
+            Automaton::Symbolic(sdfa) => Some(sdfa.n_nodes()),
+            _ => None,
+        };
+        if size(self).is_none() {
+            return;
+        }
+        let n = self.track_names.len();
+        for name in self.track_names.clone() {
+            let mut pos = self.track_names.iter().position(|t| *t == name).unwrap();
+            while pos > 0 {
+                self.swap_tracks(pos - 1, pos);
+                pos -= 1;
+            }
+            let mut best = (size(self).unwrap(), 0);
+            while pos + 1 < n {
+                self.swap_tracks(pos, pos + 1);
+                pos += 1;
+                best = best.min((size(self).unwrap(), pos));
+            }
+            while pos > best.1 {
+                self.swap_tracks(pos - 1, pos);
+                pos -= 1;
+            }
+        }
+    }
+
+    fn symbolic_pair<'a>(a: &'a AutomaticSet, b: &'a AutomaticSet) -> Option<(&'a SymbolicDfa, &'a SymbolicDfa)> {
+        match (&a.automaton, &b.automaton) {
+            (Automaton::Symbolic(a1), Automaton::Symbolic(a2)) => Some((a1, a2)),
+            _ => None,
+        }
+    }
+
+    fn make_symbolic(&mut self) {
+        if !self.automaton.is_symbolic() {
+            let automaton = std::mem::replace(&mut self.automaton, Automaton::Dfa(Dfa::trivial(false)));
+            self.automaton = Automaton::Symbolic(automaton.into_symbolic());
+        }
+    }
+
     fn synchronize_tracks(&mut self, other: &mut AutomaticSet) {
         let track_names = self.track_names().to_vec();
+        let n_tracks = track_names.len() + other.track_names().iter().filter(|t| !track_names.contains(t)).count();
+        if n_tracks >= SYMBOLIC_MIN_TRACKS {
+            /* Conversion is done before adding tracks, so explicit tables stay small */
+            self.make_symbolic();
+            other.make_symbolic();
+        }
 
         for t in other.track_names() {
             if !track_names.contains(t) {
@@ -200,6 +583,13 @@ impl AutomaticSet {
         other.order_tracks(self.track_names());
     }
 
+    /* Renames tracks in place, i.e. the i-th track gets the i-th name */
+    pub fn rename_tracks(mut self, names: &[Name]) -> AutomaticSet {
+        assert_eq!(names.len(), self.track_names.len());
+        self.track_names = names.to_vec();
+        self
+    }
+
     pub fn add_track(&mut self, name: Name) {
         self.track_names.push(name);
         self.automaton.add_track();
@@ -216,6 +606,10 @@ impl AutomaticSet {
         self.automaton.swap_tracks(index1, index2);
     }
 
+    pub fn automaton(&self) -> &Automaton {
+        &self.automaton
+    }
+
     pub fn into_dfa(self) -> Dfa {
         self.automaton.into_dfa()
     }
@@ -228,7 +622,28 @@ impl AutomaticSet {
         self.automaton.make_dfa()
     }
 
+    pub fn test_input_int(&mut self, values: &[(&str, i64)]) -> bool {
+        assert_eq!(self.domain, Domain::Int);
+        let map: HashMap<Name, i64> = values.iter().map(|(k, v)| (Name::from_str(k), *v)).collect();
+        let values: Vec<i64> = self.track_names.iter().map(|name| *map.get(name).unwrap()).collect();
+        /* Bits of the widest value + sign bit */
+        let length = values.iter().map(|v| 65 - (v ^ (v >> 63)).leading_zeros()).max().unwrap_or(0);
+        let tape: Vec<usize> = (0..length).rev().map(|j| {
+            values.iter().enumerate().map(|(i, v)| (((v >> j.min(63)) & 1) as usize) << i).sum()
+        }).collect();
+        self.test_tape(tape)
+    }
+
+    fn test_tape(&mut self, tape: Vec<usize>) -> bool {
+        if let Automaton::Symbolic(sdfa) = &self.automaton {
+            return sdfa.test_input(tape.into_iter());
+        }
+        let dfa = self.automaton.ensure_dfa();
+        dfa.test_input(tape.into_iter())
+    }
+
     pub fn test_input(&mut self, values: &[(&str, u64)]) -> bool {
+        assert_eq!(self.domain, Domain::Nat);
         let map: HashMap<Name, u64> = values.into_iter().map(|(k, v)| (Name::from_str(k), *v)).collect();
         let mut values = Vec::<u64>::new();
         for name in &self.track_names {
@@ -249,37 +664,118 @@ impl AutomaticSet {
             tape.push(v as usize);
         }
         tape.reverse();
-        let dfa = self.automaton.ensure_dfa();
-        dfa.test_input(tape.into_iter())
+        self.test_tape(tape)
     }
 
     pub fn track_id(&self, name: Name) -> Option<usize> {
         self.track_names.iter().position(|n| n == &name)
     }
 
-    pub fn exists(mut self, name: Name) -> AutomaticSet {
+    pub fn exists(self, name: Name) -> AutomaticSet {
+        self.exists_dumped(name, None)
+    }
+
+    /* The projected automaton is dumped (see Dumper) */
+    pub fn exists_dumped(mut self, name: Name, dump: Option<&Dumper>) -> AutomaticSet {
         if let Some(track) = self.track_id(name) {
+            if let Automaton::Symbolic(sdfa) = &self.automaton {
+                let sdfa = sdfa.project(track, self.domain == Domain::Int, dump);
+                self.track_names.remove(track);
+                self.automaton = if sdfa.n_tracks() < SYMBOLIC_MIN_TRACKS {
+                    Automaton::Dfa(sdfa.to_dfa())
+                } else {
+                    Automaton::Symbolic(sdfa)
+                };
+                return self;
+            }
             self.swap_tracks(0, track);
             let mut track_names = self.track_names;
             track_names.remove(0);
             let mut nfa = self.automaton.into_nfa();
             nfa.merge_first_track();
             //nfa.zero_suffix_closure();
-            nfa.zero_prefix_fix();
+            match self.domain {
+                Domain::Nat => nfa.zero_prefix_fix(),
+                Domain::Int => nfa.sign_extension_fix(),
+            }
+            dump_nfa(dump, DumpStage::Projected, &nfa);
             AutomaticSet {
                 track_names,
                 automaton: Automaton::Nfa(nfa),
+                domain: self.domain,
             }
         } else {
             self
         }
     }
 
+    /* Number of states of the current (possibly non-deterministic) automaton */
+    pub fn n_states(&self) -> usize {
+        self.automaton.n_states()
+    }
+
+    /* Number of states of the deterministic automaton; symbolic automata are not expanded */
+    pub fn dfa_size(&self) -> usize {
+        match &self.automaton {
+            Automaton::Nfa(nfa) => nfa.make_dfa().n_states(),
+            automaton => automaton.n_states(),
+        }
+    }
+
     pub fn ensure_dfa(&mut self) -> &Dfa {
         self.automaton.ensure_dfa()
     }
 
+    /* Element of self that is not in other given by the shortest word (over tracks of self followed by
+       the missing tracks of other); antichains work on NFAs, so neither set is complemented */
+    pub fn difference_witness(&self, other: &AutomaticSet) -> PasResult<Option<Vec<(Name, i128)>>> {
+        let mut names = self.track_names.clone();
+        names.extend(other.track_names.iter().filter(|t| !self.track_names.contains(t)).cloned());
+        let (a, b) = self.synchronized_nfas(other);
+        match a.inclusion_witness(&b) {
+            Some(word) => {
+                let values = decode_word(self.domain, names.len(), &word)
+                    .ok_or_else(|| PasError::Unsupported("witness does not fit into a 128-bit integer".to_string()))?;
+                Ok(Some(names.into_iter().zip(values).collect()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_subset(&self, other: &AutomaticSet) -> bool {
+        let (a, b) = self.synchronized_nfas(other);
+        a.is_included(&b)
+    }
+
+    pub fn is_universal(&self) -> bool {
+        self.automaton.clone().into_nfa().is_universal()
+    }
+
+    fn synchronized_nfas(&self, other: &AutomaticSet) -> (Nfa, Nfa) {
+        assert_eq!(self.domain, other.domain);
+        let mut a = self.clone();
+        let mut b = other.clone();
+        a.synchronize_tracks(&mut b);
+        (a.into_nfa(), b.into_nfa())
+    }
+
+    /* Like ensure_dfa, but symbolic automata are not expanded */
+    pub fn ensure_minimal(&mut self) {
+        self.automaton.ensure_minimal()
+    }
+
+    pub fn ensure_minimal_within(&mut self, limits: &Limits, dump: Option<&Dumper>) -> Result<(), Resource> {
+        self.automaton.ensure_minimal_with(|n| limits.check(n), dump)
+    }
+
+    pub fn is_symbolic(&self) -> bool {
+        self.automaton.is_symbolic()
+    }
+
     pub fn is_empty(&mut self) -> bool {
+        if let Automaton::Symbolic(sdfa) = &self.automaton {
+            return sdfa.is_empty();
+        }
         let dfa = self.ensure_dfa();
         dfa.n_states() == 1 && !dfa.is_accepting(0)
     }
@@ -407,16 +903,247 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_elements() {
+        let a = build_set(&parse_setdef("{ x, y | x + y < 5 and x > y }").unwrap()).unwrap();
+        let all = a.elements(0, None);
+        assert_eq!(all.len(), 6);
+        for offset in 0..all.len() + 2 {
+            for limit in 0..4 {
+                let end = all.len().min(offset + limit);
+                assert_eq!(a.elements(offset, Some(limit)), all[offset.min(end)..end].to_vec());
+            }
+        }
+        let a = build_set(&parse_setdef("{ x | x > 3 }").unwrap()).unwrap();
+        assert_eq!(a.elements(2, Some(2)), vec![vec![6], vec![7]]);
+        let a = build_set(&parse_setdef("{ | exists(x)(x > 3) }").unwrap()).unwrap();
+        assert_eq!(a.elements(0, None), vec![Vec::<i128>::new()]);
+    }
+
+    #[test]
+    fn test_difference_witness() {
+        let a = build_set(&parse_setdef("{ x, y | x < y and y < 10 }").unwrap()).unwrap();
+        let b = build_set(&parse_setdef("{ x, y | exists(z)(x + z == y) }").unwrap()).unwrap();
+        assert!(a.is_subset(&b));
+        assert_eq!(a.difference_witness(&b).unwrap(), None);
+        assert!(!b.is_subset(&a));
+        let (x, y, z) = (Name::from_str("x"), Name::from_str("y"), Name::from_str("z"));
+        assert_eq!(b.difference_witness(&a).unwrap(), Some(vec![(x.clone(), 0), (y.clone(), 0)]));
+        assert!(!b.is_universal());
+        assert!(a.clone().union(a.neg()).is_universal());
+
+        /* Missing track of the other set is appended */
+        let c = build_set(&parse_setdef("{ x | x > 2 }").unwrap()).unwrap();
+        let d = build_set(&parse_setdef("{ y | y > 2 }").unwrap()).unwrap();
+        assert_eq!(c.difference_witness(&d).unwrap(), Some(vec![(x.clone(), 3), (y.clone(), 0)]));
+
+        let e = build_set(&parse_setdef("int { x, z | x < z and z < 3 }").unwrap()).unwrap();
+        let f = build_set(&parse_setdef("int { x, z | x < z and -4 < x }").unwrap()).unwrap();
+        assert_eq!(f.difference_witness(&e).unwrap(), Some(vec![(x.clone(), 0), (z.clone(), 3)]));
+        assert_eq!(e.difference_witness(&f).unwrap(), Some(vec![(x, -4), (z, -3)]));
+    }
+
+    #[test]
+    fn test_linear_nat() {
+        let x = Name::from_str("x");
+        let y = Name::from_str("y");
+        let mut a = AutomaticSet::linear(Domain::Nat, vec![x.clone(), y.clone()], &[3, -2], Relation::Eq, 1);
+        let mut b = AutomaticSet::less_than(Domain::Nat, x, y);
+        for i in 0..30 {
+            for j in 0..30 {
+                assert_eq!(a.test_input(&[("x", i), ("y", j)]), 3 * i == 2 * j + 1);
+                assert_eq!(b.test_input(&[("x", i), ("y", j)]), i < j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_congruence() {
+        let x = Name::from_str("x");
+        let y = Name::from_str("y");
+        let mut a = AutomaticSet::congruence(Domain::Nat, vec![x.clone(), y.clone()], &[2, -1], 7, 3);
+        let mut b = AutomaticSet::congruence(Domain::Int, vec![x.clone(), y.clone()], &[2, -1], 7, 3);
+        for i in 0..30 {
+            for j in 0..30 {
+                assert_eq!(a.test_input(&[("x", i), ("y", j)]), (2 * i + 7 * 10 - j) % 7 == 3);
+            }
+        }
+        for i in -30..30i64 {
+            for j in -30..30 {
+                assert_eq!(b.test_input_int(&[("x", i), ("y", j)]), (2 * i - j).rem_euclid(7) == 3);
+            }
+        }
+        let mut a = AutomaticSet::congruence(Domain::Nat, vec![x], &[1], 1000, 7);
+        assert!(a.ensure_dfa().n_states() <= 1000);
+    }
+
+    #[test]
+    fn test_int_singleton() {
+        for v in &[0, 1, -1, 2, -2, 7, -8, 1234, -1234] {
+            let mut a = AutomaticSet::int_singleton(Name::from_str("x"), *v);
+            for i in -1300..1300 {
+                assert_eq!(a.test_input_int(&[("x", i)]), i == *v);
+            }
+        }
+        let dfa = AutomaticSet::int_singleton(Name::from_str("x"), -1).into_dfa();
+        assert!(dfa.test_input(vec![1].into_iter()));
+        assert!(dfa.test_input(vec![1, 1, 1].into_iter()));
+        assert!(!dfa.test_input(vec![0, 1].into_iter()));
+        assert!(!dfa.test_input(Vec::<usize>::new().into_iter()));
+
+        let dfa = AutomaticSet::int_singleton(Name::from_str("x"), 0).into_dfa();
+        assert!(dfa.test_input(Vec::<usize>::new().into_iter()));
+        assert!(dfa.test_input(vec![0, 0].into_iter()));
+    }
+
+    #[test]
+    fn test_int_relations() {
+        let x = Name::from_str("x");
+        let y = Name::from_str("y");
+        let z = Name::from_str("z");
+        let mut add = AutomaticSet::int_addition(x.clone(), y.clone(), z);
+        let mut double = AutomaticSet::int_double(x.clone(), y.clone());
+        let mut eq = AutomaticSet::int_equivalence(x.clone(), y.clone());
+        let mut lt = AutomaticSet::less_than(Domain::Int, x, y);
+        for i in -20..20 {
+            for j in -20..20 {
+                assert_eq!(double.test_input_int(&[("x", i), ("y", j)]), 2 * i == j);
+                assert_eq!(eq.test_input_int(&[("x", i), ("y", j)]), i == j);
+                assert_eq!(lt.test_input_int(&[("x", i), ("y", j)]), i < j);
+                for r in -40..40 {
+                    assert_eq!(add.test_input_int(&[("x", i), ("y", j), ("z", r)]), i + j == r);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reorder_tracks() {
+        /* Diagrams are small when equal tracks are adjacent */
+        let names: Vec<String> = ('a'..='l').map(|c| c.to_string()).collect();
+        let eqs: Vec<String> = (0..6).map(|i| format!("{} == {}", names[i], names[i + 6])).collect();
+        let setdef = format!("{{ {} | {} }}", names.join(", "), eqs.join(" and "));
+        let mut a = build_set(&parse_setdef(&setdef).unwrap()).unwrap();
+        let n_nodes = |a: &AutomaticSet| match a.automaton() {
+            Automaton::Symbolic(sdfa) => sdfa.n_nodes(),
+            _ => panic!("Symbolic automaton expected"),
+        };
+        let before = n_nodes(&a);
+        a.reorder_tracks();
+        assert!(n_nodes(&a) < before);
+        let input: Vec<(&str, u64)> = names.iter().enumerate().map(|(i, c)| (c.as_str(), (i % 6) as u64)).collect();
+        assert!(a.test_input(&input));
+        let mut wrong = input.clone();
+        wrong[3].1 += 1;
+        assert!(!a.test_input(&wrong));
+
+        /* Explicit automata are not changed */
+        let mut b = build_set(&parse_setdef("{ x, y | x + 1 == y }").unwrap()).unwrap();
+        b.reorder_tracks();
+        assert_eq!(b.track_names(), &[Name::from_str("x"), Name::from_str("y")]);
+    }
+
+    #[test]
+    fn test_symbolic_many_tracks() {
+        /* a == b == ... == p, q == p + 3; explicit tables would have 2^17 columns */
+        let names: Vec<String> = ('a'..='q').map(|c| c.to_string()).collect();
+        let mut chain: Vec<String> = names[..16].windows(2).map(|w| format!("{} == {}", w[0], w[1])).collect();
+        chain.push("p + 3 == q".to_string());
+
+        let setdef = format!("{{ a, q | {} }}", chain.join(" and "));
+        let mut a = build_set(&parse_setdef(&setdef).unwrap()).unwrap();
+        assert!(!a.is_symbolic());
+        for i in 0..20 {
+            for j in 0..30 {
+                assert_eq!(a.test_input(&[("a", i), ("q", j)]), j == i + 3);
+            }
+        }
+
+        let setdef = format!("{{ {} | {} }}", names.join(", "), chain.join(" and "));
+        let mut a = build_set(&parse_setdef(&setdef).unwrap()).unwrap();
+        assert!(a.is_symbolic());
+        assert!(!a.is_empty());
+        let input: Vec<(&str, u64)> = names.iter().map(|c| (c.as_str(), if c == "q" { 10 } else { 7 })).collect();
+        assert!(a.test_input(&input));
+        let mut wrong = input.clone();
+        wrong[7].1 += 1;
+        assert!(!a.test_input(&wrong));
+        assert!(a.clone().neg().test_input(&wrong));
+
+        let mut p = a.exists(Name::from_str("c"));
+        assert!(p.is_symbolic());
+        let without_c: Vec<(&str, u64)> = input.iter().filter(|(c, _)| *c != "c").cloned().collect();
+        assert!(p.test_input(&without_c));
+        let without_c: Vec<(&str, u64)> = wrong.iter().filter(|(c, _)| *c != "c").cloned().collect();
+        assert!(!p.test_input(&without_c));
+
+        /* Projected q needs more bits than the other tracks, so sign extension is needed */
+        let setdef = format!("int {{ {} | {} and a < 0 }}", names.join(", "), chain.join(" and ").replace("p + 3 == q", "p - 3 == q"));
+        let a = build_set(&parse_setdef(&setdef).unwrap()).unwrap();
+        assert!(a.is_symbolic());
+        let mut p = a.exists(Name::from_str("q"));
+        assert!(p.is_symbolic());
+        for v in &[-128, -129, -1, 0, 127] {
+            let input: Vec<(&str, i64)> = names[..16].iter().map(|c| (c.as_str(), *v)).collect();
+            assert_eq!(p.test_input_int(&input), *v < 0);
+            let mut wrong = input.clone();
+            wrong[3].1 += 1;
+            assert!(!p.test_input_int(&wrong));
+        }
+    }
+
+    #[test]
+    fn test_symbolic_elements() {
+        /* Results of symbolic automata are the same as of explicit ones */
+        let chain = "c == d and d == e and e == f and f == g and g == h and h == i and i == j";
+        for (setdef, size) in &[
+            (format!("{{ a, b, c, d, e, f, g, h, i, j | a + b < 3 and {} and j < 2 }}", chain), Some(12)),
+            (format!("{{ a, b, c, d, e, f, g, h, i, j | a < b and {} and j < 3 }}", chain), None),
+            (format!("int {{ a, b, c, d, e, f, g, h, i, j | a + b < 3 and -2 < a and -2 < b and {} and -3 < j and j < 2 }}", chain), Some(60)),
+            (format!("int {{ a, b, c, d, e, f, g, h, i, j | a < b and b < 0 and {} and -2 < j and j < 2 }}", chain), None),
+            (format!("int {{ a, b, c, d, e, f, g, h, i, j | a == b and {} and j < 0 and -2 < j and -3 < a and a < 2 }}", chain), Some(4)),
+        ] {
+            let a = build_set(&parse_setdef(setdef).unwrap()).unwrap();
+            assert!(a.is_symbolic());
+            let b = AutomaticSet::from_dfa(a.domain(), a.track_names().to_vec(), a.make_dfa());
+            assert_eq!(a.size(), *size, "{}", setdef);
+            assert_eq!(b.size(), *size, "{}", setdef);
+            assert_eq!(a.dfa_size(), b.make_dfa().minimize().n_states());
+            assert_eq!(a.witness().unwrap(), b.witness().unwrap());
+            assert_eq!(a.elements(0, Some(30)), b.elements(0, Some(30)), "{}", setdef);
+            assert_eq!(a.elements(4, Some(7)), b.elements(4, Some(7)), "{}", setdef);
+            if size.is_some() {
+                assert_eq!(a.elements(0, None), b.elements(0, None));
+            }
+        }
+        let a = build_set(&parse_setdef(&format!("{{ a, b, c, d, e, f, g, h, i, j | a + 1 == b and b == a and {} }}", chain)).unwrap()).unwrap();
+        assert!(a.is_symbolic());
+        assert_eq!((a.size(), a.witness().unwrap(), a.elements(0, Some(3))), (Some(0), None, Vec::new()));
+
+        /* Explicit tables would have 2^20 columns */
+        let names: Vec<String> = ('a'..='t').map(|c| c.to_string()).collect();
+        let chain: Vec<String> = names.windows(2).map(|w| format!("{} == {}", w[0], w[1])).collect();
+        for (domain, bound, size, values) in &[("nat", "0 < a", 3, vec![1, 2, 3]), ("int", "-2 < a", 5, vec![0, -1, 1])] {
+            let setdef = format!("{} {{ {} | {} and {} and a < 4 }}", domain, names.join(", "), chain.join(" and "), bound);
+            let a = build_set(&parse_setdef(&setdef).unwrap()).unwrap();
+            assert!(a.is_symbolic());
+            assert_eq!(a.size(), Some(*size));
+            assert_eq!(a.elements(0, Some(3)), values.iter().map(|v| vec![*v; 20]).collect::<Vec<_>>());
+            assert_eq!(a.witness().unwrap().unwrap()[19].1, values[0]);
+            assert!(a.dfa_size() < 10);
+        }
+    }
+
     #[test]
     fn test_cut() {
-        let a = build_set(&parse_setdef("{ x | x == 1 or x == 3}"));
-        assert_eq!(collect_elements(&a.cut(0, true).into_dfa(), None), vec![vec![1]]);
-        assert_eq!(collect_elements(&a.cut(1, true).into_dfa(), None), vec![vec![1], vec![3]]);
+        let a = build_set(&parse_setdef("{ x | x == 1 or x == 3}").unwrap()).unwrap();
+        assert_eq!(collect_elements(&a.cut(0, true, None).into_dfa(), None), vec![vec![1]]);
+        assert_eq!(collect_elements(&a.cut(1, true, None).into_dfa(), None), vec![vec![1], vec![3]]);
 
-        let a = build_set(&parse_setdef("{ x | x > 5 and x < 20 and 2 * y == x}"));
-        assert_eq!(collect_elements(&a.cut(3, true).into_dfa(), None), vec![vec![6], vec![8], vec![10], vec![12]]);
+        let a = build_set(&parse_setdef("{ x | x > 5 and x < 20 and 2 * y == x}").unwrap()).unwrap();
+        assert_eq!(collect_elements(&a.cut(3, true, None).into_dfa(), None), vec![vec![6], vec![8], vec![10], vec![12]]);
 
-        let a = build_set(&parse_setdef("{ x, y | x == y + 13 or x == y + 11}"));
-        assert_eq!(collect_elements(&a.cut(5, true).into_dfa(), None), vec![vec![11, 0], vec![13, 0], vec![12, 1], vec![14, 1], vec![13, 2], vec![15, 2]]);
+        let a = build_set(&parse_setdef("{ x, y | x == y + 13 or x == y + 11}").unwrap()).unwrap();
+        assert_eq!(collect_elements(&a.cut(5, true, None).into_dfa(), None), vec![vec![11, 0], vec![13, 0], vec![12, 1], vec![14, 1], vec![13, 2], vec![15, 2]]);
     }
 }
\ No newline at end of file
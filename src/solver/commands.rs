@@ -1,19 +1,25 @@
+use std::fmt::Display;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
 
-use crate::common::Name;
-use crate::highlevel::hiformula::HiFormula;
+use crate::automata::{Automaton, DumpConfig, Dumper};
+use crate::common::{Limits, Name, PasError, PasResult};
+use crate::highlevel::hiformula::{BinOp, Expression, HiFormula, HiPredicate};
 use crate::render::png::render_set_png;
-use crate::solver::{AutomaticSet, get_max_value};
-use crate::solver::evaluate_formula;
+use crate::solver::{AutomaticSet, Domain, get_max_value, LoPredicate, SetEnv};
+use crate::solver::{evaluate_minimal, format_trace, FormulaCache, export_set, import_set, load_set, save_set, TraceFormat, TraceNode};
 
 #[derive(Debug)]
 pub struct SetDef {
     pub vars: Vec<Name>,
     pub formula: HiFormula,
+    pub domain: Domain,
 }
 
 impl SetDef {
@@ -21,78 +27,519 @@ impl SetDef {
         &self.vars
     }
 
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
     pub fn formula(&self) -> &HiFormula {
         &self.formula
     }
 }
 
 
+#[derive(Debug)]
+pub enum SetExpr {
+    Ref(Name),
+    Union(Box<SetExpr>, Box<SetExpr>),
+    Intersection(Box<SetExpr>, Box<SetExpr>),
+    Difference(Box<SetExpr>, Box<SetExpr>),
+    Complement(Box<SetExpr>),
+    Project(Box<SetExpr>, Vec<Name>),
+    Load(String),
+    /// File, format and track names (HOA files have their own names)
+    Import(String, String, Vec<Name>),
+}
+
 #[derive(Debug)]
 pub enum Command {
     SetDef(String, SetDef),
+    SetExpr(String, SetExpr),
     Call(String, Vec<String>),
 }
 
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OutputFormat {
+    Plain,
+    Csv,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> PasResult<Self> {
+        match name {
+            "plain" => Ok(OutputFormat::Plain),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(PasError::InvalidArgument(name.to_string())),
+        }
+    }
+}
+
+/* Lines for the given elements; CSV starts with a header */
+pub fn format_elements(names: &[Name], elements: &[Vec<i128>], format: OutputFormat) -> Vec<String> {
+    let names: Vec<String> = names.iter().map(|n| format!("{:?}", n)).collect();
+    let mut lines = Vec::with_capacity(elements.len() + 1);
+    if format == OutputFormat::Csv {
+        lines.push(names.join(","));
+    }
+    for element in elements {
+        let values = element.as_slice();
+        lines.push(match format {
+            OutputFormat::Plain => {
+                names.iter().zip(values).map(|(n, v)| format!("{} = {}", n, v)).collect::<Vec<_>>().join(", ")
+            }
+            OutputFormat::Csv => values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","),
+            OutputFormat::Json => {
+                let items: Vec<String> = names.iter().zip(values).map(|(n, v)| format!("\"{}\": {}", n, v)).collect();
+                format!("{{{}}}", items.join(", "))
+            }
+        });
+    }
+    lines
+}
+
 #[derive(Debug)]
 pub struct Context {
-    sets: hashbrown::HashMap<Name, AutomaticSet>
+    sets: SetEnv,
+    cache: FormulaCache,
+    explain: bool,
+    max_states: Option<usize>,
+    timeout: Option<Duration>,
+    cancel: Arc<AtomicBool>,
+    trace: Option<TraceFormat>,
+    traces: HashMap<Name, Vec<TraceNode>>,
+    dump: Option<Dumper>,
+}
+
+fn check_arity(command: &str, args: &[String], expected: usize) -> PasResult<()> {
+    if args.len() != expected {
+        return Err(PasError::ArityMismatch { command: command.to_string(), expected, got: args.len() });
+    }
+    Ok(())
+}
+
+fn parse_number(arg: &str) -> PasResult<usize> {
+    arg.parse().map_err(|_| PasError::InvalidArgument(arg.to_string()))
 }
 
 impl Context {
     pub fn new() -> Self {
         Context {
-            sets: Default::default()
+            sets: Default::default(),
+            cache: FormulaCache::new(),
+            explain: false,
+            max_states: None,
+            timeout: None,
+            cancel: Default::default(),
+            trace: None,
+            traces: Default::default(),
+            dump: None,
         }
     }
 
-    pub fn get_set(&self, name: &Name) -> &AutomaticSet {
-        self.sets.get(&name).unwrap_or_else(|| {
-            panic!("Set '{:?}' not defined", name);
-        })
+    /* Prints size and depth of formulas before and after simplification */
+    pub fn set_explain(&mut self, value: bool) {
+        self.explain = value;
+    }
+
+    /* Track order of intermediate symbolic automata is optimized, the result uses the declared order */
+    pub fn set_reorder(&mut self, value: bool) {
+        self.cache.set_reorder(value);
+    }
+
+    /* Prints evaluation trace of each defined set; trace(S) uses the format too */
+    pub fn set_trace(&mut self, format: Option<TraceFormat>) {
+        self.trace = format;
     }
 
-    pub fn eval(&mut self, cmd: Command) {
+    /* Intermediate automata of set operations are written as dot files (see automata::Dumper);
+       numbering of files starts again with each configuration */
+    pub fn set_debug_dump(&mut self, config: Option<DumpConfig>) {
+        self.dump = config.map(Dumper::new);
+    }
+
+    /* Limits of each set construction (see Limits) */
+    pub fn set_max_states(&mut self, value: Option<usize>) {
+        self.max_states = value;
+    }
+
+    pub fn set_timeout(&mut self, value: Option<Duration>) {
+        self.timeout = value;
+    }
+
+    /* Setting the flag stops the current construction; it is cleared when a new one starts */
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancel.clone()
+    }
+
+    fn limits(&self) -> Limits {
+        let limits = Limits { max_states: self.max_states, deadline: None, cancel: Some(self.cancel.clone()) };
+        match self.timeout {
+            Some(timeout) => limits.with_timeout(timeout),
+            None => limits,
+        }
+    }
+
+    pub fn get_set(&self, name: &Name) -> PasResult<&AutomaticSet> {
+        self.sets.get(name).ok_or_else(|| PasError::UndefinedSet(format!("{:?}", name)))
+    }
+
+    pub fn sets(&self) -> impl Iterator<Item=(&Name, &AutomaticSet)> {
+        self.sets.iter()
+    }
+
+    pub fn remove_set(&mut self, name: &Name) -> PasResult<AutomaticSet> {
+        self.traces.remove(name);
+        self.sets.remove(name).ok_or_else(|| PasError::UndefinedSet(format!("{:?}", name)))
+    }
+
+    pub fn eval(&mut self, cmd: Command) -> PasResult<()> {
         match cmd {
             Command::SetDef(name, setdef) => {
+                /* A trace always belongs to the current definition of the set */
+                let name = Name::new(name);
+                self.traces.remove(&name);
+                let aset = self.build_named_set(Some(&name), &setdef)?;
+                self.sets.insert(name, aset);
+            }
+            Command::SetExpr(name, expr) => {
                 let name = Name::new(name);
-                self.sets.insert(name, build_set(&setdef));
+                self.traces.remove(&name);
+                let mut aset = self.eval_set_expr(&expr)?;
+                aset.ensure_minimal();
+                self.sets.insert(name, aset);
             }
             Command::Call(name, args) => {
                 match name.as_str() {
                     "render_png" => {
-                        let mut args = args.into_iter();
-                        let set_name = Name::new(args.next().unwrap());
-                        let output = format!("{}.png", args.next().unwrap());
-                        let dfa = self.get_set(&set_name).make_dfa();
-
-                        let file = File::create(output).unwrap();
+                        check_arity(&name, &args, 2)?;
+                        let aset = self.get_set(&Name::new(args[0].clone()))?;
+                        if aset.track_names().len() != 2 || aset.domain() != Domain::Nat {
+                            return Err(PasError::Unsupported("render_png needs a set of pairs of naturals".to_string()));
+                        }
+                        let dfa = aset.make_dfa();
+                        let nfa = dfa.make_nfa();
+                        if (0..2).any(|i| get_max_value(&nfa, i).to_limit().is_none()) {
+                            return Err(PasError::Unsupported("render_png of an infinite set".to_string()));
+                        }
+                        let file = File::create(format!("{}.png", args[1]))?;
                         let mut writer = BufWriter::new(file);
-                        render_set_png(&[&dfa], &[[255, 0, 0]], &mut writer);
+                        render_set_png(&[&dfa], &[[255, 0, 0]], &mut writer)?;
                     }
                     "nfa_dot" => {
-                        let mut args = args.into_iter();
-                        let set_name = Name::new(args.next().unwrap());
-                        let output = format!("{}.dot", args.next().unwrap());
-                        let nfa = self.get_set(&set_name).clone().into_nfa();
-                        nfa.write_dot(Path::new(&output), true).unwrap();
+                        check_arity(&name, &args, 2)?;
+                        let nfa = self.get_set(&Name::new(args[0].clone()))?.clone().into_nfa();
+                        nfa.write_dot(Path::new(&format!("{}.dot", args[1])), true)?;
+                    }
+                    "print" | "list" => {
+                        /* print(S[, format]), list(S, limit[, offset][, format]) */
+                        let (min_args, max_args) = if name == "print" { (1, 2) } else { (2, 4) };
+                        if args.len() < min_args || args.len() > max_args {
+                            let expected = if args.len() < min_args { min_args } else { max_args };
+                            return Err(PasError::ArityMismatch { command: name, expected, got: args.len() });
+                        }
+                        let aset = self.get_set(&Name::new(args[0].clone()))?;
+                        let mut rest = &args[1..];
+                        let mut limit = None;
+                        let mut offset = 0;
+                        if name == "list" {
+                            limit = Some(parse_number(&rest[0])?);
+                            rest = &rest[1..];
+                            if let Some(Ok(n)) = rest.first().map(|a| a.parse::<usize>()) {
+                                offset = n;
+                                rest = &rest[1..];
+                            }
+                        }
+                        let format = match rest {
+                            [] => OutputFormat::Plain,
+                            [f] => OutputFormat::from_name(f)?,
+                            _ => return Err(PasError::InvalidArgument(rest[1].clone())),
+                        };
+                        if limit.is_none() && aset.size().is_none() {
+                            return Err(PasError::Unsupported("print of an infinite set, use list(S, limit)".to_string()));
+                        }
+                        for line in format_elements(aset.track_names(), &aset.elements(offset, limit), format) {
+                            println!("{}", line);
+                        }
+                    }
+                    "assert_empty" => {
+                        check_arity(&name, &args, 1)?;
+                        let aset = self.get_set(&Name::new(args[0].clone()))?.clone();
+                        check_witness(&name, &args, aset)?;
+                    }
+                    "assert_universal" => {
+                        check_arity(&name, &args, 1)?;
+                        let aset = self.get_set(&Name::new(args[0].clone()))?.clone();
+                        /* Antichains answer without complementing; the smallest witness needs the complement */
+                        if aset.is_symbolic() || !aset.is_universal() {
+                            check_witness(&name, &args, aset.neg())?;
+                        }
+                    }
+                    "assert_subset" | "assert_equal" => {
+                        check_arity(&name, &args, 2)?;
+                        let (a, b) = self.eval_set_pair(&SetExpr::Ref(Name::new(args[0].clone())), &SetExpr::Ref(Name::new(args[1].clone())))?;
+                        if a.is_symbolic() || b.is_symbolic() {
+                            /* Antichains need explicit NFAs, symbolic sets are compared by their difference */
+                            let diff = if name == "assert_subset" {
+                                a.intersection(b.neg())
+                            } else {
+                                /* Symmetric difference */
+                                a.clone().intersection(b.clone().neg()).union(b.intersection(a.neg()))
+                            };
+                            check_witness(&name, &args, diff)?;
+                        } else {
+                            /* Antichains answer without complementing, the witness is the shortest word */
+                            let mut witness = a.difference_witness(&b)?;
+                            if witness.is_none() && name == "assert_equal" {
+                                witness = b.difference_witness(&a)?;
+                            }
+                            if let Some(values) = witness {
+                                return Err(PasError::AssertionFailed(format!("{}({}), witness: {}", name, args.join(", "), format_witness(&values))));
+                            }
+                        }
+                    }
+                    "assert_member" => {
+                        let mut aset = self.get_set(&Name::new(args.first().cloned().unwrap_or_default()))?.clone();
+                        check_arity(&name, &args, aset.track_names().len() + 1)?;
+                        let names: Vec<String> = aset.track_names().iter().map(|n| format!("{:?}", n)).collect();
+                        let member = match aset.domain() {
+                            Domain::Nat => {
+                                let values = args[1..].iter().map(|a| a.parse::<u64>().map_err(|_| PasError::InvalidArgument(a.clone()))).collect::<PasResult<Vec<_>>>()?;
+                                aset.test_input(&names.iter().map(|n| n.as_str()).zip(values).collect::<Vec<_>>())
+                            }
+                            Domain::Int => {
+                                let values = args[1..].iter().map(|a| a.parse::<i64>().map_err(|_| PasError::InvalidArgument(a.clone()))).collect::<PasResult<Vec<_>>>()?;
+                                aset.test_input_int(&names.iter().map(|n| n.as_str()).zip(values).collect::<Vec<_>>())
+                            }
+                        };
+                        if !member {
+                            return Err(PasError::AssertionFailed(format!("{}({})", name, args.join(", "))));
+                        }
+                    }
+                    "export" => {
+                        check_arity(&name, &args, 3)?;
+                        export_set(self.get_set(&Name::new(args[0].clone()))?, Path::new(&args[1]), args[2].parse()?)?;
+                    }
+                    "save" => {
+                        check_arity(&name, &args, 2)?;
+                        save_set(self.get_set(&Name::new(args[0].clone()))?, Path::new(&args[1]))?;
+                    }
+                    "witness" | "witness_min" => {
+                        /* witness(S), witness_min(S, x[, y, ...]) */
+                        if name == "witness" {
+                            check_arity(&name, &args, 1)?;
+                        } else if args.len() < 2 {
+                            return Err(PasError::ArityMismatch { command: name, expected: 2, got: args.len() });
+                        }
+                        let set_name = Name::new(args[0].clone());
+                        let priority: Vec<Name> = args[1..].iter().map(|a| Name::new(a.clone())).collect();
+                        match self.witness_min(&set_name, &priority)? {
+                            Some(values) => println!("{}", format_witness(&values)),
+                            None => println!("Set '{}' is empty", args[0]),
+                        }
+                    }
+                    "trace" => {
+                        check_arity(&name, &args, 1)?;
+                        let set_name = Name::new(args[0].clone());
+                        self.get_set(&set_name)?;
+                        match self.traces.get(&set_name) {
+                            Some(trace) => print!("{}", format_trace(trace, self.trace.unwrap_or(TraceFormat::Tree))),
+                            None => println!("No trace for '{}', it is not defined by a formula", args[0]),
+                        }
                     }
                     "stats" => {
-                        print_stats(self.get_set(&Name::new(args.into_iter().next().unwrap())))
+                        check_arity(&name, &args, 1)?;
+                        print_stats(self.get_set(&Name::new(args[0].clone()))?);
+                        println!("Formula cache: {} hits, {} misses, {} entries", self.cache.hits(), self.cache.misses(), self.cache.len());
                     }
-                    name => {
-                        panic!("Unknown command '{}'", name);
+                    _ => return Err(PasError::UnknownCommand(name)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build_set(&mut self, set_def: &SetDef) -> PasResult<AutomaticSet> {
+        self.build_named_set(None, set_def)
+    }
+
+    fn build_named_set(&mut self, name: Option<&Name>, set_def: &SetDef) -> PasResult<AutomaticSet> {
+        /* Check uniqueness of vars */
+        let mut uniq = HashSet::new();
+        if let Some(name) = set_def.vars().iter().find(|x| !uniq.insert((*x).clone())) {
+            return Err(PasError::DuplicateVariable(format!("{:?}", name)));
+        }
+
+        let formula = set_def.formula().make_lo_formula()?;
+        //dbg!(&formula);
+        let mut result = Ok(());
+        formula.for_each_predicate(&mut |pred| {
+            if let (Ok(()), LoPredicate::SetRef(name, vars)) = (&result, pred) {
+                result = self.check_set_ref(name, vars.len(), set_def.domain());
+            }
+        });
+        result?;
+
+        /* Variables that are not in the set are quantified before simplification, so they are miniscoped too */
+        let mut free_vars: Vec<Name> = formula.clone().free_vars().into_iter().filter(|n| !uniq.contains(n)).collect();
+        free_vars.sort();
+        let formula = free_vars.into_iter().fold(formula, |f, n| f.exists(n));
+        let simplified = formula.clone().simplify();
+        let label = name.map(|n| format!("{:?}", n)).unwrap_or_else(|| "set".to_string());
+        if self.explain {
+            println!("{}: size {} -> {}, depth {} -> {}", label, formula.size(), simplified.size(), formula.depth(), simplified.depth());
+        }
+        self.cancel.store(false, Ordering::Relaxed);
+        let limits = self.limits();
+        let result = evaluate_minimal(&simplified, set_def.domain(), &self.sets, &mut self.cache, &limits, self.dump.as_ref());
+        let trace = self.cache.take_trace();
+        match self.trace {
+            Some(TraceFormat::Tree) => print!("{}:\n{}", label, format_trace(&trace, TraceFormat::Tree)),
+            Some(TraceFormat::Json) => print!("{}", format_trace(&trace, TraceFormat::Json)),
+            None => { /* Do nothing */ }
+        }
+        let mut aset = result?;
+        if let Some(name) = name {
+            self.traces.insert(name.clone(), trace);
+        }
+        aset.order_tracks(set_def.vars());
+        Ok(aset)
+    }
+
+    /* Operands are evaluated on stored automata; tracks are aligned by names and the result has
+       the tracks of the left operand followed by the new tracks of the right operand */
+    pub fn eval_set_expr(&self, expr: &SetExpr) -> PasResult<AutomaticSet> {
+        Ok(match expr {
+            SetExpr::Ref(name) => self.get_set(name)?.clone(),
+            SetExpr::Union(e1, e2) => {
+                let (a, b) = self.eval_set_pair(e1, e2)?;
+                a.union(b)
+            }
+            SetExpr::Intersection(e1, e2) => {
+                let (a, b) = self.eval_set_pair(e1, e2)?;
+                a.intersection(b)
+            }
+            SetExpr::Difference(e1, e2) => {
+                let (a, b) = self.eval_set_pair(e1, e2)?;
+                a.intersection(b.neg())
+            }
+            SetExpr::Complement(e) => self.eval_set_expr(e)?.neg(),
+            SetExpr::Project(e, names) => {
+                let mut aset = self.eval_set_expr(e)?;
+                /* Remaining tracks keep their order, references to the set bind arguments by position */
+                let order: Vec<Name> = aset.track_names().iter().filter(|t| !names.contains(t)).cloned().collect();
+                for name in names {
+                    if aset.track_id(name.clone()).is_none() {
+                        return Err(PasError::UndefinedVariable(format!("{:?}", name)));
                     }
+                    aset = aset.exists_dumped(name.clone(), self.dump.as_ref());
                 }
+                aset.order_tracks(&order);
+                aset
+            }
+            SetExpr::Load(path) => load_set(Path::new(path))?,
+            SetExpr::Import(path, format, names) => import_set(Path::new(path), format.parse()?, names)?,
+        })
+    }
+
+    fn eval_set_pair(&self, e1: &SetExpr, e2: &SetExpr) -> PasResult<(AutomaticSet, AutomaticSet)> {
+        let a = self.eval_set_expr(e1)?;
+        let b = self.eval_set_expr(e2)?;
+        if a.domain() != b.domain() {
+            return Err(PasError::Unsupported("operation on sets over different domains".to_string()));
+        }
+        Ok((a, b))
+    }
+
+    /* Element whose priority tracks are lexicographically smallest (the first track has the highest
+       priority), the other tracks are given by witness(); None for the empty set.
+       Over integers the smallest element may not exist */
+    pub fn witness_min(&mut self, name: &Name, priority: &[Name]) -> PasResult<Option<Vec<(Name, i128)>>> {
+        let aset = self.get_set(name)?;
+        if priority.is_empty() {
+            return aset.witness();
+        }
+        let names = aset.track_names().to_vec();
+        let domain = aset.domain();
+        let mut uniq = HashSet::new();
+        for track in priority {
+            if !names.contains(track) {
+                return Err(PasError::UndefinedVariable(format!("{:?}", track)));
             }
+            if !uniq.insert(track.clone()) {
+                return Err(PasError::DuplicateVariable(format!("{:?}", track)));
+            }
+        }
+
+        /* S(x) and not exists(y)(S(y) and y < x lexicographically by priority) */
+        let others: Vec<Name> = names.iter().map(|_| Name::new_unnamed()).collect();
+        let variables = |ns: &[Name]| ns.iter().cloned().map(Expression::from_name).collect::<Vec<_>>();
+        let compare = |op: BinOp, track: &Name| {
+            let other = others[names.iter().position(|n| n == track).unwrap()].clone();
+            HiFormula::Predicate(HiPredicate::BinOp(op, Expression::from_name(other), Expression::from_name(track.clone())))
+        };
+        let less = priority.iter().rev().fold(HiFormula::Predicate(HiPredicate::False), |f, track| {
+            compare(BinOp::Lt, track).or(compare(BinOp::Eq, track).and(f))
+        });
+        let smaller = others.iter().rev().fold(HiFormula::Predicate(HiPredicate::SetRef(name.clone(), variables(&others))).and(less), |f, other| {
+            HiFormula::Exists(other.clone(), Box::new(f))
+        });
+        let formula = HiFormula::Predicate(HiPredicate::SetRef(name.clone(), variables(&names))).and(smaller.neg());
+        let minimal = self.build_set(&SetDef { vars: names, formula, domain })?;
+        match minimal.witness()? {
+            Some(values) => Ok(Some(values)),
+            None if self.get_set(name)?.witness()?.is_none() => Ok(None),
+            None => Err(PasError::Unsupported(format!("set '{:?}' has no smallest element", name))),
+        }
+    }
+
+    fn check_set_ref(&self, name: &Name, n_args: usize, domain: Domain) -> PasResult<()> {
+        let aset = self.get_set(name)?;
+        if aset.track_names().len() != n_args {
+            return Err(PasError::ArityMismatch { command: format!("{:?}", name), expected: aset.track_names().len(), got: n_args });
         }
+        if aset.domain() != domain {
+            return Err(PasError::Unsupported(format!("set '{:?}' is defined over another domain", name)));
+        }
+        Ok(())
+    }
+}
+
+/* Fails when the set is not empty; the smallest element is reported as a witness */
+fn check_witness(command: &str, args: &[String], mut aset: AutomaticSet) -> PasResult<()> {
+    if aset.is_empty() {
+        return Ok(());
+    }
+    let call = format!("{}({})", command, args.join(", "));
+    if aset.track_names().is_empty() {
+        return Err(PasError::AssertionFailed(call));
+    }
+    /* No witness means the automaton is not minimal, the failure is reported without it */
+    match format_elements(aset.track_names(), &aset.elements(0, Some(1)), OutputFormat::Plain).pop() {
+        Some(witness) => Err(PasError::AssertionFailed(format!("{}, witness: {}", call, witness))),
+        None => Err(PasError::AssertionFailed(call)),
+    }
+}
+
+fn format_witness<V: Display>(values: &[(Name, V)]) -> String {
+    if values.is_empty() {
+        return "()".to_string();
     }
+    values.iter().map(|(n, v)| format!("{:?} = {}", n, v)).collect::<Vec<_>>().join(", ")
 }
 
 fn print_stats(aset: &AutomaticSet) {
     let names = aset.track_names().to_vec();
+    if let Automaton::Symbolic(sdfa) = aset.automaton() {
+        println!("DFA size: {} (symbolic, {} nodes)", sdfa.n_states(), sdfa.n_nodes());
+        return;
+    }
     let dfa = aset.make_dfa();
     println!("DFA size: {}", dfa.n_states());
+    if aset.domain() != Domain::Nat {
+        return;
+    }
     let nfa = dfa.to_nfa();
     for (i, name) in names.iter().enumerate() {
         println!("Max {:?}: {}", name, get_max_value(&nfa, i).to_string());
@@ -100,22 +547,325 @@ fn print_stats(aset: &AutomaticSet) {
 }
 
 
-pub fn build_set(set_def: &SetDef) -> AutomaticSet {
-    /* Check uniqueness of vars */
-    let mut uniq = HashSet::new();
-    assert!(set_def.vars().iter().all(|x| uniq.insert(x.clone())));
+pub fn build_set(set_def: &SetDef) -> PasResult<AutomaticSet> {
+    Context::new().build_set(set_def)
+}
 
-    let formula = set_def.formula().make_lo_formula();
-    //dbg!(&formula);
-    let mut aset = evaluate_formula(&formula);
+#[cfg(test)]
+mod tests {
+    use crate::automata::DumpStage;
+    use crate::common::Resource;
+    use crate::highlevel::parser::parse_commands;
 
-    for name in formula.free_vars() {
-        if !uniq.contains(&name) {
-            aset = aset.exists(name.clone())
+    use super::*;
+
+    fn eval_all(context: &mut Context, input: &str) -> PasResult<()> {
+        for cmd in parse_commands(input)? {
+            context.eval(cmd)?;
         }
+        Ok(())
     }
 
-    aset.ensure_dfa();
-    aset.order_tracks(set_def.vars());
-    aset
+    #[test]
+    fn test_eval_errors() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x | x < 10 }; stats(a)").unwrap();
+        assert!(matches!(eval_all(&mut context, "stats(b)"), Err(PasError::UndefinedSet(_))));
+        assert!(matches!(eval_all(&mut context, "stat(a)"), Err(PasError::UnknownCommand(_))));
+        assert!(matches!(eval_all(&mut context, "stats(a, a)"), Err(PasError::ArityMismatch { expected: 1, got: 2, .. })));
+        assert!(matches!(eval_all(&mut context, "c = { x, x | x < 10 }"), Err(PasError::DuplicateVariable(_))));
+        assert!(matches!(eval_all(&mut context, "render_png(a, out)"), Err(PasError::Unsupported(_))));
+        assert!(matches!(eval_all(&mut context, "c = { x | x <"), Err(PasError::Parse { .. })));
+        assert!(matches!(eval_all(&mut context, "c = { x | Odd(x) }"), Err(PasError::UndefinedSet(_))));
+        assert!(matches!(eval_all(&mut context, "c = { x | (x, x) in a }"), Err(PasError::ArityMismatch { expected: 1, got: 2, .. })));
+        eval_all(&mut context, "i = int { x | x < 10 }").unwrap();
+        assert!(matches!(eval_all(&mut context, "c = { x | i(x) }"), Err(PasError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_print() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x, y | x + y < 3 and y < x }; b = { x | x > 3 }; e = { x | x < 0 };
+                                print(a); print(a, csv); list(a, 1, json); list(b, 2, 10, csv); print(e)").unwrap();
+        assert!(matches!(eval_all(&mut context, "print(b)"), Err(PasError::Unsupported(_))));
+        assert!(matches!(eval_all(&mut context, "list(b)"), Err(PasError::ArityMismatch { expected: 2, got: 1, .. })));
+        assert!(matches!(eval_all(&mut context, "list(b, x)"), Err(PasError::InvalidArgument(_))));
+        assert!(matches!(eval_all(&mut context, "list(b, 1, 2, xml)"), Err(PasError::InvalidArgument(_))));
+        assert!(matches!(eval_all(&mut context, "print(a, csv, csv)"), Err(PasError::ArityMismatch { expected: 2, got: 3, .. })));
+
+        let a = context.get_set(&Name::from_str("a")).unwrap();
+        let elements = a.elements(0, None);
+        let names = a.track_names();
+        assert_eq!(format_elements(names, &elements, OutputFormat::Plain), vec!["x = 1, y = 0", "x = 2, y = 0"]);
+        assert_eq!(format_elements(names, &elements, OutputFormat::Csv), vec!["x,y", "1,0", "2,0"]);
+        assert_eq!(format_elements(names, &elements[1..], OutputFormat::Json), vec!["{\"x\": 2, \"y\": 0}"]);
+
+        eval_all(&mut context, "n = int { x, y | x + y == 0 and -2 <= x and x <= 1 }; m = int { x | x < 3 }; print(n); list(m, 3)").unwrap();
+        assert!(matches!(eval_all(&mut context, "print(m)"), Err(PasError::Unsupported(_))));
+        let n = context.get_set(&Name::from_str("n")).unwrap();
+        assert_eq!(n.size(), Some(4));
+        assert_eq!(format_elements(n.track_names(), &n.elements(0, None), OutputFormat::Csv), vec!["x,y", "0,0", "-1,1", "1,-1", "-2,2"]);
+        assert_eq!(n.elements(1, Some(2)), vec![vec![-1, 1], vec![1, -1]]);
+        let m = context.get_set(&Name::from_str("m")).unwrap();
+        assert_eq!(m.elements(0, Some(5)), vec![vec![0], vec![-1], vec![1], vec![-2], vec![2]]);
+    }
+
+    #[test]
+    fn test_assertions() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x | x < 10 }; b = { x | x < 20 and x > 3 }; c = { x | exists(y)(x == 2 * y) and x < 10 };
+                                g = { x | x < 5 }; d = g | b & a; e = a & ~a; f = { x, y | x < y }; i = int { x | x < 2 };
+                                assert_empty(e); assert_subset(e, a); assert_subset(c, a); assert_equal(d, a);
+                                assert_member(a, 9); assert_member(f, 3, 5); assert_member(i, -7)").unwrap();
+        let failed = |context: &mut Context, input: &str| match eval_all(context, input) {
+            Err(PasError::AssertionFailed(msg)) => msg,
+            r => panic!("Assertion failure expected, got {:?}", r),
+        };
+        assert_eq!(failed(&mut context, "assert_empty(b)"), "assert_empty(b), witness: x = 4");
+        assert_eq!(failed(&mut context, "assert_subset(a, c)"), "assert_subset(a, c), witness: x = 1");
+        assert_eq!(failed(&mut context, "assert_equal(b, a)"), "assert_equal(b, a), witness: x = 10");
+        eval_all(&mut context, "j = int { x | x < -1 }; assert_subset(j, i)").unwrap();
+        assert_eq!(failed(&mut context, "assert_subset(i, j)"), "assert_subset(i, j), witness: x = 0");
+        assert_eq!(failed(&mut context, "assert_equal(j, i)"), "assert_equal(j, i), witness: x = 0");
+        eval_all(&mut context, "u = { x, y | x < y or y <= x }; assert_universal(u); ne = ~e; assert_universal(ne)").unwrap();
+        assert_eq!(failed(&mut context, "assert_universal(b)"), "assert_universal(b), witness: x = 0");
+        assert_eq!(failed(&mut context, "assert_universal(i)"), "assert_universal(i), witness: x = 2");
+        assert!(matches!(eval_all(&mut context, "assert_universal(a, b)"), Err(PasError::ArityMismatch { expected: 1, got: 2, .. })));
+        assert_eq!(failed(&mut context, "assert_member(f, 5, 3)"), "assert_member(f, 5, 3)");
+        assert_eq!(failed(&mut context, "assert_member(i, 2)"), "assert_member(i, 2)");
+        assert_eq!(failed(&mut context, "assert_empty(i)"), "assert_empty(i), witness: x = 0");
+        assert!(matches!(eval_all(&mut context, "assert_member(f, 1)"), Err(PasError::ArityMismatch { expected: 3, got: 2, .. })));
+        assert!(matches!(eval_all(&mut context, "assert_member(a, -1)"), Err(PasError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x | x < 10 }; b = { x | x > 5 }; p = { x, y | x < y and y < 4 };
+                                u = a | b; i = a & b; d = a \\ b; n = ~a; q = project(p, y); r = ~(a | b) & a \\ b;
+                                s = a & p; t = project(p, x, y)").unwrap();
+        let size = |name: &str| context.sets.get(&Name::from_str(name)).unwrap().size();
+        assert_eq!(size("u"), None);
+        assert_eq!(size("i"), Some(4));
+        assert_eq!(size("d"), Some(6));
+        assert_eq!(size("n"), None);
+        assert_eq!(size("q"), Some(3));
+        assert_eq!(size("r"), Some(0));
+        assert_eq!(size("s"), Some(6));
+        assert!(!context.sets.get_mut(&Name::from_str("t")).unwrap().is_empty());
+        assert_eq!(context.sets.get(&Name::from_str("s")).unwrap().track_names(), &[Name::from_str("x"), Name::from_str("y")]);
+        let n = context.sets.get_mut(&Name::from_str("n")).unwrap();
+        assert!(n.test_input(&[("x", 10)]));
+        assert!(!n.test_input(&[("x", 9)]));
+
+        assert!(matches!(eval_all(&mut context, "c = a | z"), Err(PasError::UndefinedSet(_))));
+        assert!(matches!(eval_all(&mut context, "c = project(a, y)"), Err(PasError::UndefinedVariable(_))));
+        eval_all(&mut context, "j = int { x | x < 10 }").unwrap();
+        assert!(matches!(eval_all(&mut context, "c = a & j"), Err(PasError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_formula_cache() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x | x % 5 < 2 }; b = { x | x % 5 < 2 and x > 10 }").unwrap();
+        assert_eq!(context.cache.hits(), 1);
+        let b = context.sets.get_mut(&Name::from_str("b")).unwrap();
+        assert!(b.test_input(&[("x", 16)]));
+        assert!(!b.test_input(&[("x", 17)]));
+        assert!(!b.test_input(&[("x", 6)]));
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut context = Context::new();
+        context.set_reorder(true);
+        eval_all(&mut context, "s = { a, b, c, d, e, f, g, h, i, j, k, l | a == g and b == h and c + 1 == i and d == j and e == k and f == l }").unwrap();
+        let s = context.sets.get_mut(&Name::from_str("s")).unwrap();
+        let names: Vec<String> = ('a'..='l').map(|c| c.to_string()).collect();
+        assert_eq!(s.track_names().iter().map(|n| format!("{:?}", n)).collect::<Vec<_>>(), names);
+        let mut input: Vec<(&str, u64)> = names.iter().enumerate().map(|(i, c)| (c.as_str(), (i % 6) as u64)).collect();
+        input[8].1 += 1;
+        assert!(s.test_input(&input));
+        input[0].1 += 1;
+        assert!(!s.test_input(&input));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut context = Context::new();
+        context.set_max_states(Some(20));
+        eval_all(&mut context, "a = { x | x % 5 < 2 }").unwrap();
+        match eval_all(&mut context, "b = { x | x % 41 == 0 or x < 2 }") {
+            Err(PasError::ResourceExhausted { resource, formula }) => {
+                assert_eq!(resource, Resource::States(20));
+                assert_eq!(formula, "1 * x == 0 (mod 41)");
+            }
+            r => panic!("Resource error expected, got {:?}", r),
+        }
+        assert!(context.get_set(&Name::from_str("b")).is_err());
+
+        context.set_max_states(None);
+        context.set_timeout(Some(Duration::from_secs(0)));
+        assert!(matches!(eval_all(&mut context, "c = { x | x < 3 }"), Err(PasError::ResourceExhausted { resource: Resource::Time, .. })));
+        context.set_timeout(None);
+        eval_all(&mut context, "c = { x | x < 3 }").unwrap();
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x | not x % 3 == 1 and exists(y)(x == 2 * y) }; b = a | a").unwrap();
+        let trace = &context.traces[&Name::from_str("a")];
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].label, "and");
+        assert_eq!(trace[0].states, context.sets[&Name::from_str("a")].n_states());
+        let labels: Vec<&str> = trace[0].children.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels.len(), 2);
+        assert!(labels.contains(&"not") && labels.contains(&"exists(y)"), "{:?}", labels);
+        let exists = trace[0].children.iter().find(|n| n.label == "exists(y)").unwrap();
+        assert_eq!(exists.children[0].label, "1 * x + -2 * y == 0");
+        /* The projection is determinized as an operand of "and" */
+        assert!(exists.minimal_states.is_some());
+
+        eval_all(&mut context, "trace(a); trace(b)").unwrap();
+        assert!(matches!(eval_all(&mut context, "trace(c)"), Err(PasError::UndefinedSet(_))));
+
+        /* Traces of failed and replaced definitions are dropped */
+        context.set_max_states(Some(3));
+        assert!(eval_all(&mut context, "c = { x | x % 100 == 7 }").is_err());
+        assert!(!context.traces.contains_key(&Name::from_str("c")));
+        context.set_max_states(None);
+        eval_all(&mut context, "a = b").unwrap();
+        assert!(!context.traces.contains_key(&Name::from_str("a")));
+        eval_all(&mut context, "c = { x | x < 3 }").unwrap();
+        context.set_max_states(Some(3));
+        assert!(eval_all(&mut context, "c = { x | x % 100 == 7 }").is_err());
+        assert!(!context.traces.contains_key(&Name::from_str("c")));
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let dir = std::env::temp_dir().join(format!("pas-dump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut context = Context::new();
+        context.set_debug_dump(Some(DumpConfig { dir: dir.clone(), stages: vec![DumpStage::Projected, DumpStage::Minimized] }));
+        eval_all(&mut context, "a = { x | exists(y)(x == 2 * y) }").unwrap();
+        context.set_debug_dump(None);
+        eval_all(&mut context, "b = { x | exists(y)(x == 3 * y) }").unwrap();
+
+        let mut files: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        files.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec!["0001-projected.dot", "0002-minimized.dot"]);
+
+        /* Symbolic projection; the other context does not share the configuration */
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut other = Context::new();
+        eval_all(&mut context, "s = { a, b, c, d, e, f, g, h, i, j, k, l | a == g and b == h and c + 1 == i and d == j and e == k and f == l }").unwrap();
+        assert!(context.sets[&Name::from_str("s")].is_symbolic());
+        context.set_debug_dump(Some(DumpConfig { dir: dir.clone(), stages: vec![DumpStage::Projected, DumpStage::Minimized] }));
+        eval_all(&mut other, "a = { x | exists(y)(x == 2 * y) }").unwrap();
+        eval_all(&mut context, "p = project(s, l)").unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        files.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec!["0001-projected.dot", "0002-minimized.dot"]);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join(format!("pas-save-{}.set", std::process::id()));
+        let mut context = Context::new();
+        eval_all(&mut context, &format!("a = {{ x, y | x + 2 == y }}; save(a, \"{0}\"); b = load(\"{0}\"); c = load(\"{0}\") & a",
+                                        path.display())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let b = context.sets.get_mut(&Name::from_str("b")).unwrap();
+        assert_eq!(b.track_names(), &[Name::from_str("x"), Name::from_str("y")]);
+        assert!(b.test_input(&[("x", 3), ("y", 5)]));
+        assert!(!b.test_input(&[("x", 3), ("y", 6)]));
+        assert!(matches!(eval_all(&mut context, &format!("d = load(\"{}\")", path.display())), Err(PasError::Io(_))));
+    }
+
+    #[test]
+    fn test_export_import() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x, y | x + 2 == y or x == 3 * y }").unwrap();
+        for format in &["timbuk", "ba", "hoa"] {
+            let path = std::env::temp_dir().join(format!("pas-export-{}.{}", std::process::id(), format));
+            let names = if *format == "hoa" { "" } else { ", x, y" };
+            eval_all(&mut context, &format!("export(a, \"{0}\", \"{1}\"); b = import(\"{0}\", \"{1}\"{2})", path.display(), format, names)).unwrap();
+            assert!(matches!(eval_all(&mut context, &format!("c = import(\"{}\", \"{}\", x)", path.display(), format)),
+                             Err(PasError::ArityMismatch { .. })));
+            std::fs::remove_file(&path).unwrap();
+            eval_all(&mut context, "assert_equal(a, b)").unwrap();
+        }
+        assert!(matches!(eval_all(&mut context, "export(a, \"x\", \"mona\")"), Err(PasError::InvalidArgument(_))));
+
+        /* Imported sets would be over naturals */
+        let path = std::env::temp_dir().join(format!("pas-export-int-{}.ba", std::process::id()));
+        assert!(matches!(eval_all(&mut context, &format!("i = int {{ x | x < -3 }}; export(i, \"{}\", \"ba\")", path.display())),
+                         Err(PasError::Unsupported(_))));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_set_ref() {
+        let mut context = Context::new();
+        eval_all(&mut context, "even = { x | exists(y)(x == 2 * y) };
+                                pair = { a, b | a < b and even(b) };
+                                c = { x, y | (y, x + 1) in pair and not x in even };
+                                d = { z | pair(z, z) or (z, 3) in pair }").unwrap();
+        let c = context.sets.get_mut(&Name::from_str("c")).unwrap();
+        assert!(c.test_input(&[("x", 3), ("y", 0)]));
+        assert!(c.test_input(&[("x", 7), ("y", 5)]));
+        assert!(!c.test_input(&[("x", 7), ("y", 8)]));
+        assert!(!c.test_input(&[("x", 4), ("y", 1)]));
+        assert!(!c.test_input(&[("x", 2), ("y", 1)]));
+        let d = context.sets.get_mut(&Name::from_str("d")).unwrap();
+        assert_eq!(d.size(), Some(0));
+
+        eval_all(&mut context, "s = { x, y, z | x < y and y < z }; p = project(s, z); q = { a, b | p(a, b) }").unwrap();
+        let q = context.sets.get_mut(&Name::from_str("q")).unwrap();
+        assert!(q.test_input(&[("a", 0), ("b", 1)]));
+        assert!(!q.test_input(&[("a", 1), ("b", 0)]));
+
+        eval_all(&mut context, "n = int { x | x < -2 }; m = int { x | n(x - 3) and -10 <= x }").unwrap();
+        let m = context.sets.get_mut(&Name::from_str("m")).unwrap();
+        assert!(m.test_input_int(&[("x", 0)]));
+        assert!(m.test_input_int(&[("x", -10)]));
+        assert!(!m.test_input_int(&[("x", 1)]));
+        assert!(!m.test_input_int(&[("x", -11)]));
+    }
+
+    #[test]
+    fn test_witness() {
+        let mut context = Context::new();
+        eval_all(&mut context, "a = { x, y | x + y == 10 and x > 3 };
+                                e = { x | x < 0 };
+                                i = int { x, y | x + y == 0 and -3 <= x and x < 5 };
+                                j = int { x | x < 2 };
+                                witness(a); witness_min(a, y, x); witness(e)").unwrap();
+        let value = |context: &mut Context, set: &str, priority: &[&str]| {
+            let priority: Vec<Name> = priority.iter().map(|n| Name::from_str(n)).collect();
+            context.witness_min(&Name::from_str(set), &priority).unwrap()
+                .map(|vs| vs.into_iter().map(|(n, v)| (format!("{:?}", n), v)).collect::<Vec<_>>())
+        };
+        let named = |values: &[(&str, i128)]| Some(values.iter().map(|(n, v)| (n.to_string(), *v)).collect::<Vec<_>>());
+        let witness = value(&mut context, "a", &[]).unwrap();
+        assert!(witness[0].1 > 3 && witness[0].1 + witness[1].1 == 10);
+        assert_eq!(value(&mut context, "a", &["x"]), named(&[("x", 4), ("y", 6)]));
+        assert_eq!(value(&mut context, "a", &["y", "x"]), named(&[("x", 10), ("y", 0)]));
+        assert_eq!(value(&mut context, "e", &["x"]), None);
+        assert_eq!(value(&mut context, "i", &["x"]), named(&[("x", -3), ("y", 3)]));
+        assert_eq!(value(&mut context, "i", &["y"]), named(&[("x", 4), ("y", -4)]));
+        assert_eq!(value(&mut context, "j", &[]), named(&[("x", 0)]));
+        assert!(matches!(context.witness_min(&Name::from_str("j"), &[Name::from_str("x")]), Err(PasError::Unsupported(_))));
+        assert!(matches!(eval_all(&mut context, "witness_min(a, z)"), Err(PasError::UndefinedVariable(_))));
+        assert!(matches!(eval_all(&mut context, "witness_min(a, x, x)"), Err(PasError::DuplicateVariable(_))));
+        assert!(matches!(eval_all(&mut context, "witness_min(a)"), Err(PasError::ArityMismatch { .. })));
+
+        /* Values beyond i64 are not wrapped */
+        eval_all(&mut context, "l = { x | x > 9223372036854775807 }").unwrap();
+        assert_eq!(value(&mut context, "l", &[]), named(&[("x", 1 << 63)]));
+    }
 }
@@ -1,9 +1,11 @@
-use itertools::Itertools;
+use std::convert::TryFrom;
 
 use crate::automata::{Bound, longest_words, number_of_words, number_of_words_next_length, number_of_words_zero_length, shortest_words};
-use crate::automata::{Dfa, Nfa, Transition};
+use crate::automata::{Dfa, dump_nfa, Dumper, DumpStage, Nfa, SymbolicDfa, Transition};
 use crate::automata::TransitionTable;
-use crate::common::StateId;
+use crate::common::{PasError, PasResult, StateId};
+
+use super::Domain;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Element {
@@ -19,6 +21,15 @@ impl Element {
         Element { values }
     }
 
+    /* Decodes MSB-first word */
+    pub fn from_word(n_tracks: usize, word: &[usize]) -> Self {
+        let mut element = Element::new(n_tracks);
+        for a in word {
+            element.push_symbol(*a);
+        }
+        element
+    }
+
     pub fn alphabet_size(&self) -> usize {
         1 << self.values.len()
     }
@@ -80,7 +91,9 @@ pub fn number_of_elements(dfa: &Dfa) -> Option<usize>
     //let dfa = dfa.reverse().make_dfa();
     let number_of_words = number_of_words(&dfa);
     let transitions = dfa.get_row(0);
-    let count = transitions[1..].iter().filter_map(|s| number_of_words[*s as usize]).fold1(|a, b| a + b);
+    /* None of a successor is an infinite number of words (the DFA has to be minimal, so that
+       the only state without accepted words is a sink) */
+    let count: Option<usize> = transitions[1..].iter().map(|s| number_of_words[*s as usize]).sum();
     count.map(|v| v + if dfa.is_accepting(0) { 1 } else { 0 })
 }
 
@@ -96,16 +109,12 @@ pub fn iterate_elements<F: FnMut(&Element)>(dfa: &Dfa, limit: Option<usize>, cal
         todo!()
     }
 
-    dfa.clone().to_nfa().write_dot(std::path::Path::new("/tmp/it.dot"), false).unwrap();
-
     let short = shortest_words(dfa);
     let long = longest_words(dfa);
 
     //let mut stack = Vec::new();
     let element = Element::new(dfa.n_tracks());
 
-
-
     struct ComputationDef<'a> {
         dfa: &'a Dfa,
         short: Vec<Option<usize>>,
@@ -207,7 +216,6 @@ pub fn get_max_value(nfa: &Nfa, track_id: usize) -> Bound {
 
     let mut state = dfa.get_row(0)[1];
 
-    //dfa.clone().to_nfa().write_dot(std::path::Path::new("/tmp/xx.dot"), false).unwrap();
 
     let max = if let Bound::None = lengths[state as usize] {
         Bound::None
@@ -238,7 +246,7 @@ pub fn get_max_value(nfa: &Nfa, track_id: usize) -> Bound {
     }
 }
 
-pub fn cut(element: &Element) -> Nfa {
+pub fn cut(element: &Element, dump: Option<&Dumper>) -> Nfa {
     let mut data = vec![];
 
     let length = element.length();
@@ -278,9 +286,7 @@ pub fn cut(element: &Element) -> Nfa {
     accepting[last] = false;
 
     let nfa = Nfa::new(TransitionTable::new(element.n_tracks(), data), accepting, Nfa::simple_init());
-    nfa.write_dot(std::path::Path::new("/tmp/xx.dot"), false).unwrap();
-    //nfa.make_dfa().to_nfa().write_dot(std::path::Path::new("/tmp/yy.dot"), false).unwrap();
-    //nfa.make_dfa().reverse().make_dfa().to_nfa().write_dot(std::path::Path::new("/tmp/rr.dot"), false).unwrap();
+    dump_nfa(dump, DumpStage::Cut, &nfa);
     nfa
 }
 
@@ -348,6 +354,187 @@ pub fn get_nth_element(dfa: &Dfa, mut nth_element: usize) -> Element {
     element
 }
 
+/* Class of a sign symbol: Int domain words starting with the sign symbol, read as Nat domain words
+   of the rest of the word where tracks with the sign bit are complemented (v ~> -v - 1).
+   Values of an element of the class */
+pub fn decode_sign_class(sign: usize, element: &Element) -> Vec<i128> {
+    element.as_slice().iter().enumerate().map(|(i, v)| {
+        if (sign >> i) & 1 == 1 { -(*v as i128) - 1 } else { *v as i128 }
+    }).collect()
+}
+
+/* Values of an MSB-first word, the first symbol holds sign bits in Int domain;
+   None when a value does not fit into i128 */
+pub fn decode_word(domain: Domain, n_tracks: usize, word: &[usize]) -> Option<Vec<i128>> {
+    let mut values = vec![0i128; n_tracks];
+    for (j, symbol) in word.iter().enumerate() {
+        for (i, v) in values.iter_mut().enumerate() {
+            let bit = ((symbol >> i) & 1) as i128;
+            *v = if j == 0 && domain == Domain::Int { -bit } else { v.checked_mul(2)?.checked_add(bit)? };
+        }
+    }
+    Some(values)
+}
+
+/* Shortest accepted word with the smallest symbols first; the language has to be nonempty */
+fn min_shortest_word(dfa: &Dfa) -> Vec<usize> {
+    let distances = shortest_words(dfa);
+    let mut word = Vec::new();
+    let mut state = 0;
+    loop {
+        let distance = distances[state as usize].unwrap();
+        if distance == 0 {
+            return word;
+        }
+        let row = dfa.get_row(state);
+        let symbol = (0..row.len()).find(|a| distances[row[*a] as usize] == Some(distance - 1)).unwrap();
+        word.push(symbol);
+        state = row[symbol];
+    }
+}
+
+/* Shortest word of an Int domain automaton decoded in two's complement (see decode_word);
+   the language has to be nonempty. None when a value does not fit into i128 */
+pub fn get_min_int_element(dfa: &Dfa) -> Option<Vec<i128>> {
+    decode_word(Domain::Int, dfa.n_tracks(), &min_shortest_word(dfa))
+}
+
+fn decode_witness(domain: Domain, n_tracks: usize, word: &[usize]) -> PasResult<Vec<i128>> {
+    decode_word(domain, n_tracks, word).ok_or_else(|| PasError::Unsupported("witness does not fit into a 128-bit integer".to_string()))
+}
+
+/* Values of the smallest element per track, i.e. of the shortest word with the smallest symbols first;
+   for Nat it is the first element of get_nth_element. None for the empty set */
+pub fn get_witness(dfa: &Dfa, domain: Domain) -> PasResult<Option<Vec<i128>>> {
+    if shortest_words(dfa)[0].is_none() {
+        return Ok(None);
+    }
+    decode_witness(domain, dfa.n_tracks(), &min_shortest_word(dfa)).map(Some)
+}
+
+/* get_witness of a symbolic automaton */
+pub fn get_symbolic_witness(sdfa: &SymbolicDfa, domain: Domain) -> PasResult<Option<Vec<i128>>> {
+    let distances = sdfa.shortest_words();
+    let mut word = Vec::new();
+    let mut state = 0;
+    loop {
+        match distances[state as usize] {
+            None => return Ok(None),
+            Some(0) => return decode_witness(domain, sdfa.n_tracks(), &word).map(Some),
+            Some(distance) => {
+                let (symbol, target) = sdfa.next_symbol(state, 0, 0, &|t| distances[t as usize] == Some(distance - 1)).unwrap();
+                word.push(symbol);
+                state = target;
+            }
+        }
+    }
+}
+
+/* Number of elements of a symbolic automaton, None for infinite sets; counts saturate at usize::MAX.
+   Nat: words that do not start with the zero symbol. Int: the sum over classes of sign symbols
+   (see decode_sign_class), i.e. words "s t w" where the sign symbol s is not repeated (t != s) and one-symbol words */
+pub fn number_of_symbolic_elements(sdfa: &SymbolicDfa, domain: Domain) -> Option<usize> {
+    let words = sdfa.longest_and_number_of_words().1;
+    let mut count = 0u128;
+    match domain {
+        Domain::Nat => {
+            count += sdfa.is_accepting(0) as u128;
+            let zero = sdfa.target(0, 0);
+            for (t, c) in sdfa.successors(0) {
+                let c = c - (t == zero) as u128;
+                if c > 0 {
+                    count = count.saturating_add(c.saturating_mul(words[t as usize]?));
+                }
+            }
+        }
+        Domain::Int => {
+            /* Infinite words after a repeated sign symbol are not counted; they are counted on both sides
+               of the subtraction of the diagonal (t == s) otherwise */
+            for (q, c) in sdfa.successors(0) {
+                count = count.saturating_add(c * sdfa.is_accepting(q) as u128);
+                let successors = sdfa.successors(q);
+                let infinite: u128 = successors.iter().filter(|(t, _)| words[*t as usize].is_none()).map(|(_, d)| d).sum();
+                if infinite > 1 || (infinite == 1 && c > 1) {
+                    return None;
+                }
+                if infinite == 1 {
+                    let (s, _) = sdfa.next_symbol(0, 0, 0, &|t| t == q).unwrap();
+                    let (t, _) = sdfa.next_symbol(q, 0, 0, &|t| words[t as usize].is_none()).unwrap();
+                    if s != t {
+                        return None;
+                    }
+                }
+                for (t, d) in successors {
+                    count = count.saturating_add(c.saturating_mul(d).saturating_mul(words[t as usize].unwrap_or(0)));
+                }
+            }
+            for (t, d) in sdfa.diagonal_successors(0) {
+                count = count.saturating_sub(d.saturating_mul(words[t as usize].unwrap_or(0)));
+            }
+        }
+    }
+    Some(usize::try_from(count).unwrap_or(usize::MAX))
+}
+
+/// Enumeration of a symbolic automaton in the order of iterate_elements; a class of sign symbols
+/// (see decode_sign_class) is enumerated from its start state with symbols xor-ed by the sign symbol,
+/// so neither the explicit table nor the class automaton is built
+pub struct SymbolicElements<'a> {
+    sdfa: &'a SymbolicDfa,
+    short: Vec<Option<usize>>,
+    long: Vec<Bound>,
+}
+
+impl<'a> SymbolicElements<'a> {
+    pub fn new(sdfa: &'a SymbolicDfa) -> Self {
+        SymbolicElements { sdfa, short: sdfa.shortest_words(), long: sdfa.longest_and_number_of_words().0 }
+    }
+
+    /* Sign symbols of nonempty classes in increasing order */
+    pub fn signs(&self) -> impl Iterator<Item=(usize, StateId)> + '_ {
+        let mut from = 0;
+        std::iter::from_fn(move || {
+            let (sign, start) = self.sdfa.next_symbol(0, from, 0, &|t| self.short[t as usize].is_some())?;
+            from = sign + 1;
+            Some((sign, start))
+        })
+    }
+
+    /* Stops when the callback returns true */
+    pub fn iterate<F: FnMut(&Element) -> bool>(&self, start: StateId, xor: usize, mut callback: F) {
+        let mut element = Element::new(self.sdfa.n_tracks());
+        if self.sdfa.is_accepting(start) && callback(&element) {
+            return;
+        }
+        for length in 1.. {
+            /* Leading symbol is not zero, 1 is correct here as in iterate_elements */
+            if self.sdfa.next_symbol(start, 1, xor, &|t| self.long[t as usize] >= Bound::Finite(length - 1)).is_none() {
+                return;
+            }
+            if self.compute(&mut element, start, length, 1, xor, &mut callback) {
+                return;
+            }
+        }
+    }
+
+    fn compute<F: FnMut(&Element) -> bool>(&self, element: &mut Element, state: StateId, length: usize, from: usize, xor: usize, callback: &mut F) -> bool {
+        if length == 0 {
+            return self.sdfa.is_accepting(state) && callback(element);
+        }
+        let rest = length - 1;
+        let fits = |t: StateId| self.short[t as usize].is_some_and(|s| s <= rest) && self.long[t as usize] >= Bound::Finite(rest);
+        let mut from = from;
+        while let Some((a, t)) = self.sdfa.next_symbol(state, from, xor, &fits) {
+            element.push_symbol(a);
+            if self.compute(element, t, rest, 0, xor, callback) {
+                return true;
+            }
+            element.pop_symbol();
+            from = a + 1;
+        }
+        false
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -365,120 +552,120 @@ mod tests {
 
     #[test]
     fn test_words_list1() {
-        //let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}")).to_dfa();
+        //let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}").unwrap()).unwrap().to_dfa();
 
-        /*let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}")).to_dfa();
+        /*let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}").unwrap()).unwrap().to_dfa();
         assert!(collect_words(&a, Some(0)).is_empty());
         assert_eq!(collect_words(&a, Some(7)), vec![vec![220], vec![10]]);
         assert_eq!(collect_words(&a, Some(1)), vec![vec![220]]);*/
-        let a = build_set(&parse_setdef("{ x, y | x > 3 and (x + y == 10)}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x, y | x > 3 and (x + y == 10)}").unwrap()).unwrap().into_dfa();
         println!("{}", collect_elements(&a, Some(700)).len());
         assert_eq!(collect_elements(&a, Some(3)), vec![vec![7, 3], vec![5, 5], vec![6, 4]]);
     }
 
     #[test]
     fn test_words_list2() {
-        //let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}")).to_dfa();
+        //let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}").unwrap()).unwrap().to_dfa();
 
-        /*let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}")).to_dfa();
+        /*let a = build_set(&parse_setdef("{ x | x == 220 or x == 10}").unwrap()).unwrap().to_dfa();
         assert!(collect_words(&a, Some(0)).is_empty());
         assert_eq!(collect_words(&a, Some(7)), vec![vec![220], vec![10]]);
         assert_eq!(collect_words(&a, Some(1)), vec![vec![220]]);*/
 
-        let a = build_set(&parse_setdef("{ x, y | 11 * x == 3 * y and not (x == 0) }")).into_dfa();
+        let a = build_set(&parse_setdef("{ x, y | 11 * x == 3 * y and not (x == 0) }").unwrap()).unwrap().into_dfa();
         println!("{}", collect_elements(&a, Some(2)).len());
         assert_eq!(collect_elements(&a, Some(3)), vec![vec![3, 11], vec![6, 22], vec![9, 33]]);
     }
 
     #[test]
     fn test_words_list3() {
-        let a = build_set(&parse_setdef("{ x | x == 1 }")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 1 }").unwrap()).unwrap().into_dfa();
         assert_eq!(collect_elements(&a, Some(2)), vec![vec![1]]);
 
-        let a = build_set(&parse_setdef("{ x | x == 2 }")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 2 }").unwrap()).unwrap().into_dfa();
         assert_eq!(collect_elements(&a, Some(2)), vec![vec![2]]);
 
-        let a = build_set(&parse_setdef("{ x | x == 1234567 }")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 1234567 }").unwrap()).unwrap().into_dfa();
         assert_eq!(collect_elements(&a, Some(2)), vec![vec![1234567]]);
 
-        let a = build_set(&parse_setdef("{ x | x == 314 or x == 25 }")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 314 or x == 25 }").unwrap()).unwrap().into_dfa();
         assert_eq!(collect_elements(&a, Some(2)), vec![vec![25], vec![314]]);
     }
 
     #[test]
     fn test_range() {
-        let a = build_set(&parse_setdef("{ x, y | x == 10}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x == 10}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::Finite(10));
         assert_eq!(get_max_value(&a, 1), Bound::Infinite);
 
-        let a = build_set(&parse_setdef("{ x, y | x < 0}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x < 0}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::None);
 
-        let a = build_set(&parse_setdef("{ x, y | x < 1}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x < 1}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::Finite(0));
 
-        let a = build_set(&parse_setdef("{ x, y | x < 2}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x < 2}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::Finite(1));
 
-        let a = build_set(&parse_setdef("{ x, y | x < 12 or x == 123}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x < 12 or x == 123}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::Finite(123));
 
-        let a = build_set(&parse_setdef("{ x | x == a + b and a < 10 and b < a + 4 and u + v == x and u == v}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | x == a + b and a < 10 and b < a + 4 and u + v == x and u == v}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::Finite(20));
 
-        let a = build_set(&parse_setdef("{ x | x == 72300 or x == 23 or x > 512}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | x == 72300 or x == 23 or x > 512}").unwrap()).unwrap().into_nfa();
         assert_eq!(get_max_value(&a, 0), Bound::Infinite);
     }
 
     #[test]
     fn test_size() {
-        let a = build_set(&parse_setdef("{ x | x == 1}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | x == 1}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(1));
-        let a = build_set(&parse_setdef("{ x | x == 0}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | x == 0}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(1));
-        let a = build_set(&parse_setdef("{ x | not (x == x)}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | not (x == x)}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(0));
-        let a = build_set(&parse_setdef("{ x | x < 10}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | x < 10}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(10));
-        let a = build_set(&parse_setdef("{ x | x < 10 and not x == 1}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x | x < 10 and not x == 1}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(9));
-        let a = build_set(&parse_setdef("{ x, y | x < 100 and y < 100}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x < 100 and y < 100}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(10000));
-        let a = build_set(&parse_setdef("{ x, y | x < 100 and y < 100 and not (x == y) or (x == 123 and y == 321)}")).into_nfa();
+        let a = build_set(&parse_setdef("{ x, y | x < 100 and y < 100 and not (x == y) or (x == 123 and y == 321)}").unwrap()).unwrap().into_nfa();
         assert_eq!(number_of_elements(&a.make_dfa()), Some(9901));
     }
 
     #[test]
     fn test_nth_element() {
-        let a = build_set(&parse_setdef("{ x | x == 0}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 0}").unwrap()).unwrap().into_dfa();
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![0]);
 
-        let a = build_set(&parse_setdef("{ x | x == 1}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 1}").unwrap()).unwrap().into_dfa();
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![1]);
 
-        let a = build_set(&parse_setdef("{ x | x == 1 or x == 3}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x == 1 or x == 3}").unwrap()).unwrap().into_dfa();
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![1]);
         assert_eq!(get_nth_element(&a, 1).into_vec(), vec![3]);
 
-        let a = build_set(&parse_setdef("{ x | x < 10}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x < 10}").unwrap()).unwrap().into_dfa();
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![0]);
         assert_eq!(get_nth_element(&a, 1).into_vec(), vec![1]);
         assert_eq!(get_nth_element(&a, 2).into_vec(), vec![2]);
         assert_eq!(get_nth_element(&a, 9).into_vec(), vec![9]);
 
-        let a = build_set(&parse_setdef("{ x | x > 55}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | x > 55}").unwrap()).unwrap().into_dfa();
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![56]);
         assert_eq!(get_nth_element(&a, 1).into_vec(), vec![57]);
         assert_eq!(get_nth_element(&a, 2).into_vec(), vec![58]);
         assert_eq!(get_nth_element(&a, 12077).into_vec(), vec![56 + 12077]);
 
-        let a = build_set(&parse_setdef("{ x | 111 * y == x and x > 100 and (not exists(z)(2 * z == x))}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x | 111 * y == x and x > 100 and (not exists(z)(2 * z == x))}").unwrap()).unwrap().into_dfa();
 
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![111]);
         assert_eq!(get_nth_element(&a, 1).into_vec(), vec![333]);
         assert_eq!(get_nth_element(&a, 2).into_vec(), vec![555]);
 
-        let a = build_set(&parse_setdef("{ x, y | x == y + 1}")).into_dfa();
+        let a = build_set(&parse_setdef("{ x, y | x == y + 1}").unwrap()).unwrap().into_dfa();
         assert_eq!(get_nth_element(&a, 0).into_vec(), vec![1, 0]);
         assert_eq!(get_nth_element(&a, 1).into_vec(), vec![2, 1]);
         assert_eq!(get_nth_element(&a, 2).into_vec(), vec![3, 2]);
@@ -489,9 +676,9 @@ mod tests {
     #[test]
     fn test_nth_element2() {
         let def = "{ x, y | x < 500 and y < 500 and ((x < 100 or x > 400) and 10 * z == y or (y + 100 < x and x < 400) or ((x > 240 and x < 260 and y > 240 and y < 290 ))) }";
-        let a = build_set(&parse_setdef(def));
+        let a = build_set(&parse_setdef(def).unwrap()).unwrap();
 
-        let (c, _d) = a.cut2(16);
+        let (c, _d) = a.cut2(16, None);
         assert_eq!(number_of_elements(&c.into_dfa()), Some(17));
 
 
@@ -502,6 +689,21 @@ mod tests {
         });*/
     }
 
+    #[test]
+    fn test_witness() {
+        let a = build_set(&parse_setdef("{ x, y | x == y + 1 and y > 4}").unwrap()).unwrap().into_dfa();
+        assert_eq!(get_witness(&a, Domain::Nat).unwrap(), Some(vec![6, 5]));
+        let a = build_set(&parse_setdef("{ x | not (x == x)}").unwrap()).unwrap().into_dfa();
+        assert_eq!(get_witness(&a, Domain::Nat).unwrap(), None);
+
+        /* Only the word of 129 ones, state 130 is a sink */
+        let transitions = (0..131).flat_map(|s| if s < 129 { vec![130, s + 1] } else { vec![130, 130] }).collect();
+        let accepting = (0..131).map(|s| s == 129).collect();
+        let a = Dfa::new(TransitionTable::new(1, transitions), accepting);
+        assert!(matches!(get_witness(&a, Domain::Nat), Err(PasError::Unsupported(_))));
+        assert_eq!(get_witness(&a, Domain::Int).unwrap(), Some(vec![-1]));
+    }
+
     #[test]
     fn test_get_symbol() {
         let mut e = Element::new(2);
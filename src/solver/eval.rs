@@ -1,49 +1,350 @@
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+
+use crate::automata::{Automaton, Dumper};
+use crate::common::{Limits, Name, PasError, PasResult, Resource};
+
 use super::{LoFormula, LoPredicate};
-use super::AutomaticSet;
+use super::{AutomaticSet, Domain, Trace, TraceNode};
 
-pub fn evaluate_predicate(pred: &LoPredicate) -> AutomaticSet {
-    match pred {
-        LoPredicate::EqConst(name, value) => AutomaticSet::singleton(name.clone(), value.clone()),
-        LoPredicate::Eq(name1, name2) => AutomaticSet::equivalence(name1.clone(), name2.clone()),
-        LoPredicate::Add(name1, name2, name3) => AutomaticSet::addition(name1.clone(), name2.clone(), name3.clone()),
-        LoPredicate::Double(name1, name2) => AutomaticSet::double(name1.clone(), name2.clone()),
-        LoPredicate::True => AutomaticSet::trivial(true),
-        LoPredicate::False => AutomaticSet::trivial(false),
-    }
+pub type SetEnv = HashMap<Name, AutomaticSet>;
+
+/* Set references have to be checked before evaluation (see Context::build_set);
+   constructions of linear atoms and congruences are checked against limits */
+pub fn evaluate_predicate(pred: &LoPredicate, domain: Domain, sets: &SetEnv, limits: &Limits) -> Result<AutomaticSet, Resource> {
+    Ok(match (pred, domain) {
+        (LoPredicate::EqConst(name, value), Domain::Nat) => AutomaticSet::singleton(name.clone(), value.clone()),
+        (LoPredicate::Eq(name1, name2), Domain::Nat) => AutomaticSet::equivalence(name1.clone(), name2.clone()),
+        (LoPredicate::Add(name1, name2, name3), Domain::Nat) => AutomaticSet::addition(name1.clone(), name2.clone(), name3.clone()),
+        (LoPredicate::Double(name1, name2), Domain::Nat) => AutomaticSet::double(name1.clone(), name2.clone()),
+        (LoPredicate::EqConst(name, value), Domain::Int) => AutomaticSet::int_singleton(name.clone(), *value as i64),
+        (LoPredicate::Eq(name1, name2), Domain::Int) => AutomaticSet::int_equivalence(name1.clone(), name2.clone()),
+        (LoPredicate::Add(name1, name2, name3), Domain::Int) => AutomaticSet::int_addition(name1.clone(), name2.clone(), name3.clone()),
+        (LoPredicate::Double(name1, name2), Domain::Int) => AutomaticSet::int_double(name1.clone(), name2.clone()),
+        (LoPredicate::Linear { vars, coeffs, rel, constant }, _) => AutomaticSet::linear_within(domain, vars.clone(), coeffs, *rel, *constant, limits)?,
+        (LoPredicate::Congruence(vars, coeffs, modulus, residue), _) => AutomaticSet::congruence_within(domain, vars.clone(), coeffs, *modulus, *residue, limits)?,
+        (LoPredicate::SetRef(name, vars), _) => sets[name].clone().rename_tracks(vars),
+        (LoPredicate::True, _) => AutomaticSet::trivial_in(domain, true),
+        (LoPredicate::False, _) => AutomaticSet::trivial_in(domain, false),
+    })
 }
 
 pub fn evaluate_formula(formula: &LoFormula) -> AutomaticSet {
-    match formula {
-        LoFormula::Predicate(pred) => evaluate_predicate(pred),
-        LoFormula::Or(fs) => evaluate_formula(&fs.0).union(evaluate_formula(&fs.1)),
-        LoFormula::Neg(f) => evaluate_formula(f).neg(),
-        LoFormula::Exists(name, f) => evaluate_formula(f).exists(name.clone()),
+    evaluate_formula_in(formula, Domain::Nat)
+}
+
+pub fn evaluate_formula_in(formula: &LoFormula, domain: Domain) -> AutomaticSet {
+    evaluate_formula_with(formula, domain, &SetEnv::new())
+}
+
+pub fn evaluate_formula_with(formula: &LoFormula, domain: Domain, sets: &SetEnv) -> AutomaticSet {
+    evaluate_formula_cached(formula, domain, sets, &mut FormulaCache::new(), &Limits::none()).unwrap()
+}
+
+pub fn evaluate_formula_limited(formula: &LoFormula, domain: Domain, sets: &SetEnv, limits: &Limits) -> PasResult<AutomaticSet> {
+    evaluate_formula_cached(formula, domain, sets, &mut FormulaCache::new(), limits)
+}
+
+/// Entries kept by default in FormulaCache
+pub const CACHE_CAPACITY: usize = 4096;
+
+/// Automata of subformulas up to names of free variables (see Shape); tracks of stored sets are
+/// named #0, #1, ... by positions of the variables. Least recently used entries are dropped
+/// when there are more than "capacity" of them
+#[derive(Debug)]
+pub struct FormulaCache {
+    sets: HashMap<(Domain, usize), (AutomaticSet, usize)>,
+    shapes: HashMap<Shape, usize>,
+    capacity: usize,
+    clock: usize,
+    hits: usize,
+    misses: usize,
+    reorder: bool,
+    trace: Trace,
+}
+
+/// Formula up to names of variables, operands are ids of interned shapes
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Shape {
+    /* Variables are renamed by LoPredicate::canonical */
+    Predicate(LoPredicate),
+    Neg(usize),
+    /* Positions of the variables of the second operand among the variables of the node,
+       variables of the first operand go first */
+    Or(usize, usize, Vec<usize>),
+    And(usize, usize, Vec<usize>),
+    /* Position of the bound variable among the variables of the operand */
+    Exists(usize, Option<usize>),
+}
+
+/* Shape id and free variables of every node of a formula, computed bottom-up once per evaluation;
+   subformulas referring to sets have no key, the sets may be redefined */
+struct Keys {
+    key: Option<(usize, Vec<Name>)>,
+    children: Vec<Keys>,
+}
+
+impl Default for FormulaCache {
+    fn default() -> Self {
+        FormulaCache {
+            sets: HashMap::new(),
+            shapes: HashMap::new(),
+            capacity: CACHE_CAPACITY,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            reorder: false,
+            trace: Trace::default(),
+        }
     }
 }
 
+impl FormulaCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    #[inline]
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /* Nodes evaluated since the last call */
+    pub fn take_trace(&mut self) -> Vec<TraceNode> {
+        self.trace.take()
+    }
+
+    /* Tracks of evaluated subformulas are reordered (see AutomaticSet::reorder_tracks) */
+    pub fn set_reorder(&mut self, value: bool) {
+        self.reorder = value;
+    }
+
+    fn keys(&mut self, formula: &LoFormula) -> Keys {
+        let children = match formula {
+            LoFormula::Predicate(_) => Vec::new(),
+            LoFormula::Neg(f) | LoFormula::Exists(_, f) => vec![self.keys(f)],
+            LoFormula::Or(fs) | LoFormula::And(fs) => vec![self.keys(&fs.0), self.keys(&fs.1)],
+        };
+        let operands: Option<Vec<&(usize, Vec<Name>)>> = children.iter().map(|c| c.key.as_ref()).collect();
+        let key = match (formula, operands.as_deref()) {
+            (LoFormula::Predicate(LoPredicate::SetRef(_, _)), _) | (_, None) => None,
+            (LoFormula::Predicate(p), _) => {
+                let (p, vars) = p.canonical();
+                Some((Shape::Predicate(p), vars))
+            }
+            (LoFormula::Neg(_), Some([(id, vars)])) => Some((Shape::Neg(*id), vars.clone())),
+            (LoFormula::Exists(name, _), Some([(id, vars)])) => {
+                let position = vars.iter().position(|v| v == name);
+                Some((Shape::Exists(*id, position), vars.iter().filter(|v| *v != name).cloned().collect()))
+            }
+            (_, Some([(id1, vars1), (id2, vars2)])) => {
+                let mut vars = vars1.clone();
+                let mut positions = Vec::with_capacity(vars2.len());
+                for v in vars2 {
+                    positions.push(vars1.iter().position(|u| u == v).unwrap_or_else(|| {
+                        vars.push(v.clone());
+                        vars.len() - 1
+                    }));
+                }
+                let shape = match formula {
+                    LoFormula::And(_) => Shape::And(*id1, *id2, positions),
+                    _ => Shape::Or(*id1, *id2, positions),
+                };
+                Some((shape, vars))
+            }
+            _ => unreachable!(),
+        };
+        let shapes = &mut self.shapes;
+        let key = key.map(|(shape, vars)| {
+            let n = shapes.len();
+            (*shapes.entry(shape).or_insert(n), vars)
+        });
+        Keys { key, children }
+    }
+
+    fn get(&mut self, domain: Domain, id: usize, vars: &[Name]) -> Option<AutomaticSet> {
+        self.clock += 1;
+        let clock = self.clock;
+        let (aset, used) = self.sets.get_mut(&(domain, id))?;
+        *used = clock;
+        let names: Vec<Name> = aset.track_names().iter().map(|n| match n {
+            Name::Tmp(i) => vars[*i].clone(),
+            n => n.clone(),
+        }).collect();
+        Some(aset.clone().rename_tracks(&names))
+    }
+
+    fn insert(&mut self, domain: Domain, id: usize, vars: &[Name], aset: &AutomaticSet) {
+        self.clock += 1;
+        let names: Vec<Name> = aset.track_names().iter().map(|n| match vars.iter().position(|v| v == n) {
+            Some(i) => Name::Tmp(i),
+            None => n.clone(),
+        }).collect();
+        self.sets.insert((domain, id), (aset.clone().rename_tracks(&names), self.clock));
+        self.evict();
+    }
+
+    /* The older half of entries is dropped at once, so eviction is amortized */
+    fn evict(&mut self) {
+        if self.sets.len() <= self.capacity {
+            return;
+        }
+        let mut used: Vec<usize> = self.sets.values().map(|(_, u)| *u).collect();
+        used.sort_unstable();
+        let threshold = used[used.len() - self.capacity / 2 - 1];
+        self.sets.retain(|_, (_, u)| *u > threshold);
+    }
+}
+
+fn exhausted(resource: Resource, formula: &LoFormula) -> PasError {
+    PasError::ResourceExhausted { resource, formula: formula.to_string() }
+}
+
+/* Arguments shared by all nodes of one evaluation */
+struct Env<'a> {
+    domain: Domain,
+    sets: &'a SetEnv,
+    limits: &'a Limits,
+    dump: Option<&'a Dumper>,
+}
+
+/* Every evaluated node is recorded into the trace of the cache */
+pub fn evaluate_formula_cached(formula: &LoFormula, domain: Domain, sets: &SetEnv, cache: &mut FormulaCache, limits: &Limits) -> PasResult<AutomaticSet> {
+    let keys = cache.keys(formula);
+    evaluate_keyed(formula, &keys, &Env { domain, sets, limits, dump: None }, cache)
+}
+
+fn evaluate_keyed(formula: &LoFormula, keys: &Keys, env: &Env, cache: &mut FormulaCache) -> PasResult<AutomaticSet> {
+    let start = Instant::now();
+    cache.trace.enter();
+    let mut node = TraceNode {
+        label: TraceNode::label_of(formula),
+        cached: false,
+        states: 0,
+        minimal_states: None,
+        time: Duration::default(),
+        children: Vec::new(),
+    };
+    let result = lookup_or_evaluate(formula, keys, env, cache, &mut node);
+    node.time = start.elapsed();
+    cache.trace.leave(node);
+    result
+}
+
+/* Sets are stored as they are evaluated, determinized sets are stored back by minimal_keyed */
+fn lookup_or_evaluate(formula: &LoFormula, keys: &Keys, env: &Env, cache: &mut FormulaCache, node: &mut TraceNode) -> PasResult<AutomaticSet> {
+    let (id, vars) = match &keys.key {
+        Some(key) => key,
+        None => {
+            let aset = evaluate_node(formula, keys, env, cache)?;
+            node.states = aset.n_states();
+            return Ok(aset);
+        }
+    };
+    if let Some(aset) = cache.get(env.domain, *id, vars) {
+        cache.hits += 1;
+        node.cached = true;
+        node.states = aset.n_states();
+        return Ok(aset);
+    }
+    cache.misses += 1;
+    let aset = evaluate_node(formula, keys, env, cache)?;
+    node.states = aset.n_states();
+    cache.insert(env.domain, *id, vars, &aset);
+    Ok(aset)
+}
+
+/* Operands of complement and intersection are determinized, it is done here under limits;
+   the determinization is recorded to the trace node of the formula. Intermediate automata
+   are written by "dump" (see Dumper) */
+pub fn evaluate_minimal(formula: &LoFormula, domain: Domain, sets: &SetEnv, cache: &mut FormulaCache, limits: &Limits, dump: Option<&Dumper>) -> PasResult<AutomaticSet> {
+    let keys = cache.keys(formula);
+    minimal_keyed(formula, &keys, &Env { domain, sets, limits, dump }, cache)
+}
+
+fn minimal_keyed(formula: &LoFormula, keys: &Keys, env: &Env, cache: &mut FormulaCache) -> PasResult<AutomaticSet> {
+    let mut aset = evaluate_keyed(formula, keys, env, cache)?;
+    if let Automaton::Nfa(_) = aset.automaton() {
+        let start = Instant::now();
+        aset.ensure_minimal_within(env.limits, env.dump).map_err(|r| exhausted(r, formula))?;
+        cache.trace.add_minimization(aset.n_states(), start.elapsed());
+        if let Some((id, vars)) = &keys.key {
+            cache.insert(env.domain, *id, vars, &aset);
+        }
+    }
+    Ok(aset)
+}
+
+fn evaluate_node(formula: &LoFormula, keys: &Keys, env: &Env, cache: &mut FormulaCache) -> PasResult<AutomaticSet> {
+    let children = &keys.children;
+    let mut aset = match formula {
+        LoFormula::Predicate(pred) => evaluate_predicate(pred, env.domain, env.sets, env.limits).map_err(|r| exhausted(r, formula))?,
+        LoFormula::Or(fs) => {
+            let a = evaluate_keyed(&fs.0, &children[0], env, cache)?;
+            a.union(evaluate_keyed(&fs.1, &children[1], env, cache)?)
+        }
+        LoFormula::And(fs) => {
+            let a = minimal_keyed(&fs.0, &children[0], env, cache)?;
+            let b = minimal_keyed(&fs.1, &children[1], env, cache)?;
+            a.intersection_within(b, env.limits, env.dump).map_err(|r| exhausted(r, formula))?
+        }
+        LoFormula::Neg(f) => minimal_keyed(f, &children[0], env, cache)?.neg(),
+        LoFormula::Exists(name, f) => evaluate_keyed(f, &children[0], env, cache)?.exists_dumped(name.clone(), env.dump),
+    };
+    if cache.reorder {
+        aset.reorder_tracks();
+    }
+    env.limits.check(aset.n_states()).map_err(|r| exhausted(r, formula))?;
+    Ok(aset)
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::common::Name;
+    use crate::highlevel::hiformula::{BinOp, Expression, HiFormula, HiPredicate};
     use crate::highlevel::parser::parse_formula;
+    use crate::solver::Relation;
 
     use super::*;
 
     #[test]
     fn test_eval_eq_formula() {
-        let f = parse_formula("x == y").make_lo_formula();
+        let f = parse_formula("x == y").unwrap().make_lo_formula().unwrap();
         assert!(evaluate_formula(&f).test_input(&[("x", 10), ("y", 10)]));
         assert!(!evaluate_formula(&f).test_input(&[("x", 10), ("y", 11)]));
 
-        let f = parse_formula("x == x").make_lo_formula();
+        let f = parse_formula("x == x").unwrap().make_lo_formula().unwrap();
         assert!(evaluate_formula(&f).test_input(&[("x", 10)]));
         assert!(evaluate_formula(&f).test_input(&[("x", 11)]));
 
-        let f = parse_formula("x == y and y == z").make_lo_formula();
+        let f = parse_formula("x == y and y == z").unwrap().make_lo_formula().unwrap();
         assert!(evaluate_formula(&f).test_input(&[("x", 10), ("y", 10), ("z", 10)]));
         assert!(!evaluate_formula(&f).test_input(&[("x", 11), ("y", 10), ("z", 10)]));
         assert!(!evaluate_formula(&f).test_input(&[("x", 9), ("y", 10), ("z", 9)]));
         assert!(evaluate_formula(&f).test_input(&[("x", 0), ("y", 0), ("z", 0)]));
 
-        let f = parse_formula("x == y or y == z").make_lo_formula();
+        let f = parse_formula("x == y or y == z").unwrap().make_lo_formula().unwrap();
         assert!(evaluate_formula(&f).test_input(&[("x", 10), ("y", 10), ("z", 10)]));
         assert!(evaluate_formula(&f).test_input(&[("x", 10), ("y", 10), ("z", 0)]));
         assert!(evaluate_formula(&f).test_input(&[("x", 10), ("y", 0), ("z", 0)]));
@@ -52,8 +353,8 @@ mod tests {
 
     #[test]
     fn test_eval_simple_plus_formula() {
-        let f = parse_formula("x + y == z").make_lo_formula();
-        let g = parse_formula("x == y + z").make_lo_formula();
+        let f = parse_formula("x + y == z").unwrap().make_lo_formula().unwrap();
+        let g = parse_formula("x == y + z").unwrap().make_lo_formula().unwrap();
         assert!(evaluate_formula(&f).test_input(&[("x", 4), ("y", 6), ("z", 10)]));
 
         let mut a = evaluate_formula(&f);
@@ -75,7 +376,7 @@ mod tests {
 
     #[test]
     fn test_eval_eq_const_formula() {
-        let mut a = evaluate_formula(&parse_formula("x == 5 or x == 7").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x == 5 or x == 7").unwrap().make_lo_formula().unwrap());
         assert!(a.test_input(&[("x", 5)]));
         assert!(a.test_input(&[("x", 7)]));
         assert!(!a.test_input(&[("x", 6)]));
@@ -84,29 +385,28 @@ mod tests {
 
     #[test]
     fn test_eval_combined_plus_formula() {
-        let mut a = evaluate_formula(&parse_formula("x + y + z == w").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x + y + z == w").unwrap().make_lo_formula().unwrap());
         assert!(a.test_input(&[("x", 1), ("y", 2), ("z", 3), ("w", 6)]));
         assert!(!a.test_input(&[("x", 1), ("y", 2), ("z", 3), ("w", 7)]));
 
-        let mut a = evaluate_formula(&parse_formula("x + y + z == v + w + x").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x + y + z == v + w + x").unwrap().make_lo_formula().unwrap());
         assert!(a.test_input(&[("x", 1), ("y", 2), ("z", 3), ("w", 1), ("v", 4)]));
         assert!(a.test_input(&[("x", 0), ("y", 2), ("z", 3), ("w", 1), ("v", 4)]));
         assert!(!a.test_input(&[("x", 0), ("y", 6), ("z", 3), ("w", 1), ("v", 4)]));
         assert!(!a.test_input(&[("x", 1), ("y", 2), ("z", 3), ("w", 1), ("v", 3)]));
 
 
-        let mut a = evaluate_formula(&parse_formula("x + 2 == y + 3").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x + 2 == y + 3").unwrap().make_lo_formula().unwrap());
         assert!(a.test_input(&[("x", 2), ("y", 1)]));
         assert!(!a.test_input(&[("x", 1), ("y", 2)]));
 
-        let mut a = evaluate_formula(&parse_formula("x + y + z + 2 + 7 == v + w + x + 3").make_lo_formula());
-        //a.clone().to_dfa().to_nfa().write_dot(std::path::Path::new("/tmp/xx.dot")).unwrap();
+        let mut a = evaluate_formula(&parse_formula("x + y + z + 2 + 7 == v + w + x + 3").unwrap().make_lo_formula().unwrap());
         assert!(a.test_input(&[("x", 1), ("y", 2), ("z", 3), ("w", 7), ("v", 4)]));
     }
 
     #[test]
     fn test_eval_combined_lt_formula() {
-        let mut a = evaluate_formula(&parse_formula("x < 10").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x < 10").unwrap().make_lo_formula().unwrap());
         for i in 0..10 {
             assert!(a.test_input(&[("x", i)]));
         }
@@ -117,7 +417,7 @@ mod tests {
 
     #[test]
     fn test_eval_mul_formula() {
-        let mut a = evaluate_formula(&parse_formula("2 * x < 10").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("2 * x < 10").unwrap().make_lo_formula().unwrap());
         for i in 0..5 {
             assert!(a.test_input(&[("x", i)]));
         }
@@ -125,30 +425,28 @@ mod tests {
             assert!(!a.test_input(&[("x", i)]));
         }
 
-        let mut a = evaluate_formula(&parse_formula("3 * x == 60").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("3 * x == 60").unwrap().make_lo_formula().unwrap());
         for i in 0..100 {
             assert_eq!(a.test_input(&[("x", i)]), i == 20);
         }
 
-        //let mut a = evaluate_formula(&parse_formula("1325 * x == 147075").make_lo_formula());
+        //let mut a = evaluate_formula(&parse_formula("1325 * x == 147075").unwrap().make_lo_formula().unwrap());
 
-        let f = parse_formula("11 * x == 3 * y").make_lo_formula();
+        let f = parse_formula("11 * x == 3 * y").unwrap().make_lo_formula().unwrap();
         let mut a = evaluate_formula(&f);
         assert!(a.test_input(&[("x", 0), ("y", 0)]));
         assert!(a.test_input(&[("x", 3), ("y", 11)]));
 
 
         let _a = evaluate_formula(&f);
-        /*a.clone().to_dfa().to_nfa().write_dot(std::path::Path::new("/tmp/x.dot"), true).unwrap();
-        iterate_words(&a.clone().to_dfa(), Some(10), |w|  println!("WW {:?}", w));*/
 
-        let f = parse_formula("111 * x == 30 * y").make_lo_formula();
+        let f = parse_formula("111 * x == 30 * y").unwrap().make_lo_formula().unwrap();
         let mut a = evaluate_formula(&f);
         assert!(a.test_input(&[("x", 30), ("y", 111)]));
         assert!(!a.test_input(&[("x", 31), ("y", 111)]));
         assert!(!a.test_input(&[("x", 30), ("y", 110)]));
 
-        let mut a = evaluate_formula(&parse_formula("x % 7 == 2 and x > 100").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x % 7 == 2 and x > 100").unwrap().make_lo_formula().unwrap());
         for i in 0..200 {
             assert_eq!(a.test_input(&[("x", i)]), i % 7 == 2 && i > 100);
         }
@@ -156,7 +454,7 @@ mod tests {
 
     #[test]
     fn test_eval_combined_lte_formula() {
-        let mut a = evaluate_formula(&parse_formula("x <= 10").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x <= 10").unwrap().make_lo_formula().unwrap());
         for i in 0..11 {
             assert!(a.test_input(&[("x", i)]));
         }
@@ -165,16 +463,352 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_eval_int_formula() {
+        let mut a = evaluate_formula_in(&parse_formula("x + 3 == 1").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -10..10 {
+            assert_eq!(a.test_input_int(&[("x", i)]), i == -2);
+        }
+
+        let mut a = evaluate_formula_in(&parse_formula("x < 3 and 2 * x > y").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -10..10 {
+            for j in -10..10 {
+                assert_eq!(a.test_input_int(&[("x", i), ("y", j)]), i < 3 && 2 * i > j);
+            }
+        }
+
+        let mut a = evaluate_formula_in(&parse_formula("exists(y)(x + y == 0 and y > 4)").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -10..10 {
+            assert_eq!(a.test_input_int(&[("x", i)]), i < -4);
+        }
+
+        let mut a = evaluate_formula_in(&parse_formula("forall(y)(y < x or x + 5 < y)").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        assert!(a.is_empty());
+
+        let mut a = evaluate_formula_in(&parse_formula("x % 3 == 1").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -10..10 {
+            assert_eq!(a.test_input_int(&[("x", i)]), i.rem_euclid(3) == 1);
+        }
+    }
+
+    #[test]
+    fn test_eval_sub_formula() {
+        let mut a = evaluate_formula(&parse_formula("x - y < 3").unwrap().make_lo_formula().unwrap());
+        for i in 0..20 {
+            for j in 0..20 {
+                assert_eq!(a.test_input(&[("x", i), ("y", j)]), i < j + 3);
+            }
+        }
+
+        let mut a = evaluate_formula(&parse_formula("x - 2 * y == -3 + z").unwrap().make_lo_formula().unwrap());
+        assert!(a.test_input(&[("x", 1), ("y", 2), ("z", 0)]));
+        assert!(a.test_input(&[("x", 3), ("y", 1), ("z", 4)]));
+        assert!(!a.test_input(&[("x", 3), ("y", 1), ("z", 3)]));
+
+        let mut a = evaluate_formula(&parse_formula("-x == 1").unwrap().make_lo_formula().unwrap());
+        assert!(a.is_empty());
+
+        let mut a = evaluate_formula_in(&parse_formula("-x == 3 - y").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -10..10 {
+            for j in -10..10 {
+                assert_eq!(a.test_input_int(&[("x", i), ("y", j)]), -i == 3 - j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_linear_formula() {
+        let f = parse_formula("111 * x == 30 * y").unwrap().make_lo_formula().unwrap();
+        match &f {
+            LoFormula::Predicate(LoPredicate::Linear { coeffs, constant, .. }) => {
+                assert_eq!(coeffs, &vec![37, -10]);
+                assert_eq!(*constant, 0);
+            }
+            _ => panic!("Linear predicate expected"),
+        }
+        let mut a = evaluate_formula(&f);
+        assert_eq!(a.track_names(), &[Name::from_str("x"), Name::from_str("y")]);
+        assert!(a.ensure_dfa().n_states() < 50);
+
+        let mut a = evaluate_formula(&parse_formula("2 * x + 4 * y == 7").unwrap().make_lo_formula().unwrap());
+        assert!(a.is_empty());
+
+        let mut a = evaluate_formula(&parse_formula("0 * x == 0 and 3 * y - x <= 2 * x + 1").unwrap().make_lo_formula().unwrap());
+        for i in 0..30 {
+            for j in 0..30 {
+                assert_eq!(a.test_input(&[("x", i), ("y", j)]), 3 * j <= 3 * i + 1);
+            }
+        }
+
+        let mut a = evaluate_formula_in(&parse_formula("3 * x - 5 * y < 2 * z + 1").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -8..8 {
+            for j in -8..8 {
+                for k in -8..8 {
+                    assert_eq!(a.test_input_int(&[("x", i), ("y", j), ("z", k)]), 3 * i - 5 * j < 2 * k + 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_linear_overflow() {
+        let overflows = |f: &str| matches!(parse_formula(f).unwrap().make_lo_formula(), Err(PasError::Unsupported(_)));
+        assert!(overflows("x == 9223372036854775808"));
+        assert!(overflows("9223372036854775808 * x < 1"));
+        assert!(overflows("4611686018427387904 * x + 4611686018427387904 * x == 1"));
+        assert!(overflows("x % 18446744073709551615 == 1"));
+        assert!(overflows("x ≡ 1 (mod 18446744073709551615)"));
+        assert!(overflows("-x % 5 == 9223372036854775807 + 1"));
+        assert!(!overflows("x - 9223372036854775806 < 1"));
+
+        /* Explicit tables of atoms would have 2^17 columns */
+        let names: Vec<String> = ('a'..='q').map(|c| c.to_string()).collect();
+        assert!(overflows(&format!("{} == 1", names.join(" + "))));
+        assert!(overflows(&format!("{} ≡ 1 (mod 3)", names.join(" + "))));
+        assert!(!overflows(&format!("{} == {}", names[..15].join(" + "), names[15])));
+
+        /* Nested multipliers */
+        let x = Expression::from_name(Name::from_str("x"));
+        let big = Expression::Mul(Box::new(Expression::Mul(Box::new(x), 1 << 40)), 1 << 40);
+        let f = HiFormula::Predicate(HiPredicate::BinOp(BinOp::Eq, big, Expression::Constant(0)));
+        assert!(matches!(f.make_lo_formula(), Err(PasError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_eval_congruence_formula() {
+        let f = parse_formula("x % 1000 == 7").unwrap().make_lo_formula().unwrap();
+        assert_eq!(f.size(), 1);
+        let mut a = evaluate_formula(&f);
+        assert!(a.ensure_dfa().n_states() <= 1000);
+        for i in 0..3000 {
+            assert_eq!(a.test_input(&[("x", i)]), i % 1000 == 7);
+        }
+
+        let mut a = evaluate_formula(&parse_formula("x ≡ 2 * y + 1 (mod 5)").unwrap().make_lo_formula().unwrap());
+        for i in 0..20 {
+            for j in 0..20 {
+                assert_eq!(a.test_input(&[("x", i), ("y", j)]), (i + 5 * 20 - 2 * j - 1) % 5 == 0);
+            }
+        }
+
+        let mut a = evaluate_formula(&parse_formula("x % 4 + y % 3 == 4").unwrap().make_lo_formula().unwrap());
+        for i in 0..20 {
+            for j in 0..20 {
+                assert_eq!(a.test_input(&[("x", i), ("y", j)]), i % 4 + j % 3 == 4);
+            }
+        }
+
+        let mut a = evaluate_formula(&parse_formula("x % 4 == 4").unwrap().make_lo_formula().unwrap());
+        assert!(a.is_empty());
+
+        let mut a = evaluate_formula_in(&parse_formula("x % 3 == 2 and x ≡ y (mod 4)").unwrap().make_lo_formula().unwrap(), Domain::Int);
+        for i in -20..20i64 {
+            for j in -20..20i64 {
+                assert_eq!(a.test_input_int(&[("x", i), ("y", j)]), i.rem_euclid(3) == 2 && (i - j).rem_euclid(4) == 0);
+            }
+        }
+    }
+
     #[test]
     fn test_eval_is_empty() {
-        let mut a = evaluate_formula(&parse_formula("x < 10 and x > 10").make_lo_formula());
-        //a.make_dfa().to_nfa().write_dot(std::path::Path::new("/tmp/xxx.dot"), false).unwrap();
+        let mut a = evaluate_formula(&parse_formula("x < 10 and x > 10").unwrap().make_lo_formula().unwrap());
         assert!(a.is_empty());
     }
 
     #[test]
     fn test_eval_is_not_empty() {
-        let mut a = evaluate_formula(&parse_formula("x < 10 and x > 5").make_lo_formula());
+        let mut a = evaluate_formula(&parse_formula("x < 10 and x > 5").unwrap().make_lo_formula().unwrap());
         assert!(!a.is_empty());
     }
+
+    /* Evaluation where conjunction is done by the given operation (product or through complements),
+       returns the set and the largest number of states of an intermediate automaton */
+    fn evaluate_with_and(formula: &LoFormula, and: fn(AutomaticSet, AutomaticSet) -> AutomaticSet) -> (AutomaticSet, usize) {
+        let (aset, max) = match formula {
+            LoFormula::Predicate(pred) => (evaluate_predicate(pred, Domain::Nat, &SetEnv::new(), &Limits::none()).unwrap(), 0),
+            LoFormula::And(fs) | LoFormula::Or(fs) => {
+                let (a, m1) = evaluate_with_and(&fs.0, and);
+                let (b, m2) = evaluate_with_and(&fs.1, and);
+                let aset = match formula {
+                    LoFormula::And(_) => and(a, b),
+                    _ => a.union(b),
+                };
+                (aset, m1.max(m2))
+            }
+            LoFormula::Neg(f) => {
+                let (a, m) = evaluate_with_and(f, and);
+                (a.neg(), m)
+            }
+            LoFormula::Exists(name, f) => {
+                let (a, m) = evaluate_with_and(f, and);
+                (a.exists(name.clone()), m)
+            }
+        };
+        let n = aset.n_states();
+        (aset, max.max(n))
+    }
+
+    fn and_by_complement(a: AutomaticSet, b: AutomaticSet) -> AutomaticSet {
+        a.neg().union(b.neg()).neg()
+    }
+
+    #[test]
+    fn test_simplify() {
+        let formulas = [
+            "exists(y)(x < 10 and y == 2 * x and x > 2)",
+            "exists(y)(x == 2 * y or x == 3 * y) and exists(z)(z < 5)",
+            "not not exists(y)(exists(z)(x + y == z and x < 5))",
+            "forall(y)(y < x or y > 3 and x > 0)",
+            "exists(y)(x < 7 and (y == x + 1 or x == 2 * y))",
+        ];
+        for formula in &formulas {
+            let f = parse_formula(formula).unwrap().make_lo_formula().unwrap();
+            /* Size may grow by pushing a quantifier over a disjunction */
+            let g = f.clone().simplify();
+            let a = evaluate_formula(&f);
+            let b = evaluate_formula(&g);
+            assert!(a.is_subset(&b) && b.is_subset(&a), "{}", formula);
+        }
+
+        let f = parse_formula("exists(y)(x < 10 and y == 2 * x and x > 2)").unwrap().make_lo_formula().unwrap().simplify();
+        /* x < 10 and x > 2 and exists(y)(y == 2 * x) */
+        assert!(matches!(&f, LoFormula::And(fs) if matches!(fs.1, LoFormula::Exists(_, _))));
+        let f = parse_formula("exists(y)(x < 10) and not not x > 2").unwrap().make_lo_formula().unwrap().simplify();
+        assert_eq!(f.size(), 3);
+        let f = parse_formula("exists(y)(x == 2 * y or x == 3 * y)").unwrap().make_lo_formula().unwrap().simplify();
+        assert!(matches!(&f, LoFormula::Or(fs) if matches!(fs.0, LoFormula::Exists(_, _)) && matches!(fs.1, LoFormula::Exists(_, _))));
+    }
+
+    #[test]
+    fn test_eval_cache() {
+        /* Both "x % 7" introduce their own temporary variable */
+        let f = parse_formula("x % 7 < 3 or x % 7 < 3").unwrap().make_lo_formula().unwrap();
+        if let LoFormula::Or(fs) = &f {
+            assert_ne!(fs.0, fs.1);
+            let mut cache = FormulaCache::new();
+            assert_eq!(cache.keys(&fs.0).key.unwrap().0, cache.keys(&fs.1).key.unwrap().0);
+        } else {
+            panic!("Or expected");
+        }
+        let mut cache = FormulaCache::new();
+        let mut a = evaluate_formula_cached(&f, Domain::Nat, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+        assert_eq!(cache.hits(), 1);
+        for i in 0..30 {
+            assert_eq!(a.test_input(&[("x", i)]), i % 7 < 3);
+        }
+
+        /* Free temporary variables are renamed back */
+        let t1 = Name::new_tmp();
+        let t2 = Name::new_tmp();
+        let f1 = LoPredicate::linear(vec![(t1.clone(), 1), (Name::from_str("y"), -2)], Relation::Eq, 0).unwrap().to_formula();
+        let f2 = LoPredicate::linear(vec![(t2.clone(), 1), (Name::from_str("y"), -2)], Relation::Eq, 0).unwrap().to_formula();
+        let mut cache = FormulaCache::new();
+        evaluate_formula_cached(&f1, Domain::Nat, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+        let b = evaluate_formula_cached(&f2, Domain::Nat, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert!(b.track_names().contains(&t2));
+        assert!(!b.track_names().contains(&t1));
+
+        /* Domain is a part of the key */
+        evaluate_formula_cached(&f2, Domain::Int, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+        assert_eq!(cache.misses(), 2);
+
+        /* Bound variables are renamed, free ones are matched by position */
+        let mut cache = FormulaCache::new();
+        let key = |cache: &mut FormulaCache, s: &str| cache.keys(&parse_formula(s).unwrap().make_lo_formula().unwrap()).key;
+        let (id, vars) = key(&mut cache, "exists(y)(x == 2 * y) and z < 3").unwrap();
+        assert_eq!(vars, vec![Name::from_str("x"), Name::from_str("z")]);
+        assert_eq!(key(&mut cache, "exists(b)(a == 2 * b) and w < 3").unwrap().0, id);
+        assert_ne!(key(&mut cache, "exists(b)(a == 2 * b) and a < 3").unwrap().0, id);
+        assert!(key(&mut cache, "x in s and x < 3").is_none());
+
+        /* Subformulas with constant predicates are cached too */
+        let f = parse_formula("x < 3").unwrap().make_lo_formula().unwrap().or(LoPredicate::False.to_formula());
+        let f = f.clone().and(f);
+        evaluate_formula_cached(&f, Domain::Nat, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+        assert_eq!(cache.hits(), 1);
+
+        /* Least recently used entries are dropped */
+        cache.set_capacity(4);
+        assert!(cache.len() <= 4);
+        for i in 0..10 {
+            let f = parse_formula(&format!("x == {}", i)).unwrap().make_lo_formula().unwrap();
+            evaluate_formula_cached(&f, Domain::Nat, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+            assert!(cache.len() <= 4);
+        }
+        let f = parse_formula("x == 9").unwrap().make_lo_formula().unwrap();
+        evaluate_formula_cached(&f, Domain::Nat, &SetEnv::new(), &mut cache, &Limits::none()).unwrap();
+        assert_eq!(cache.hits(), 2);
+    }
+
+    #[test]
+    fn test_eval_limits() {
+        /* Determinization of the projection needs more than 100 states */
+        let f = parse_formula("exists(y)(x == 129 * y)").unwrap().make_lo_formula().unwrap();
+        let limits = Limits { max_states: Some(100), ..Limits::none() };
+        assert!(evaluate_formula_limited(&f.clone().neg(), Domain::Nat, &SetEnv::new(), &Limits::none()).is_ok());
+        match evaluate_formula_limited(&f.clone().neg(), Domain::Nat, &SetEnv::new(), &limits) {
+            Err(PasError::ResourceExhausted { resource, .. }) => assert_eq!(resource, Resource::States(100)),
+            r => panic!("Resource error expected, got {:?}", r.map(|_| ())),
+        }
+
+        let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let limits = Limits { cancel: Some(cancel), ..Limits::none() };
+        assert!(matches!(evaluate_formula_limited(&f, Domain::Nat, &SetEnv::new(), &limits),
+                         Err(PasError::ResourceExhausted { resource: Resource::Cancelled, .. })));
+
+        /* Atoms and products are checked while they are built, not only afterwards */
+        let limits = Limits { max_states: Some(100), ..Limits::none() };
+        for formula in &["x % 1000000 == 3", "x + 1000 * y == 123456", "x % 60 == 1 and x % 77 == 2"] {
+            let f = parse_formula(formula).unwrap().make_lo_formula().unwrap();
+            assert!(matches!(evaluate_formula_limited(&f, Domain::Nat, &SetEnv::new(), &limits),
+                             Err(PasError::ResourceExhausted { resource: Resource::States(100), .. })), "{}", formula);
+        }
+
+        let mut sets = SetEnv::new();
+        sets.insert(Name::from_str("s"), evaluate_formula(&parse_formula("x < 5").unwrap().make_lo_formula().unwrap()));
+        let f = parse_formula("x in s").unwrap().make_lo_formula().unwrap();
+        let a = evaluate_formula_limited(&f, Domain::Nat, &sets, &limits).unwrap();
+        assert_eq!(a.size(), Some(5));
+    }
+
+    #[test]
+    fn test_eval_and_product() {
+        for formula in &["x == y and y == z", "x < 10 and x > 3 and not x == 5", "x + y == z and x < y and z < 100"] {
+            let f = parse_formula(formula).unwrap().make_lo_formula().unwrap();
+            let (mut a, _) = evaluate_with_and(&f, and_by_complement);
+            let (mut b, _) = evaluate_with_and(&f, AutomaticSet::intersection);
+            assert_eq!(a.ensure_dfa().n_states(), b.ensure_dfa().n_states());
+            assert_eq!(a.size(), b.size());
+        }
+    }
+
+    /* Run by "cargo test --release bench_and -- --ignored --nocapture" */
+    #[test]
+    #[ignore]
+    fn bench_and() {
+        let formulas = [
+            "x == y and y == z",
+            "x + y == z and x < y and z < 100",
+            "x < 3 and 2 * x > y",
+            "3 * x - 5 * y < 2 * z + 1 and x < 1000 and y < 1000",
+            "x % 3 == 2 and x ≡ y (mod 4) and y < 500",
+            "x < 500 and y < 500 and ((x < 100 or x > 400) and 10 * z == y or (y + 100 < x and x < 400))",
+            "111 * y == x and x > 100 and (not exists(z)(2 * z == x))",
+        ];
+        println!("{:<90} {:>12} {:>12} {:>10} {:>10}", "formula", "compl. [ms]", "product [ms]", "compl. st", "product st");
+        for formula in &formulas {
+            let f = parse_formula(formula).unwrap().make_lo_formula().unwrap();
+            let start = std::time::Instant::now();
+            let (mut a, m1) = evaluate_with_and(&f, and_by_complement);
+            a.ensure_dfa();
+            let t1 = start.elapsed();
+            let start = std::time::Instant::now();
+            let (mut b, m2) = evaluate_with_and(&f, AutomaticSet::intersection);
+            b.ensure_dfa();
+            let t2 = start.elapsed();
+            assert_eq!(a.n_states(), b.n_states());
+            println!("{:<90} {:>12.3} {:>12.3} {:>10} {:>10}", formula, t1.as_secs_f64() * 1000.0, t2.as_secs_f64() * 1000.0, m1, m2);
+        }
+    }
 }
@@ -1,8 +1,14 @@
+use std::convert::TryFrom;
+use std::fmt;
+
 use hashbrown::HashSet;
 
-use crate::common::Name;
+use crate::common::{Name, PasError, PasResult};
+
+use super::aset::MAX_ATOM_TRACKS;
+use super::Relation;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum LoPredicate {
     Add(Name, Name, Name),
     // x + y = z
@@ -12,6 +18,12 @@ pub enum LoPredicate {
     // x == y
     EqConst(Name, u64),
     // x == C
+    Linear { vars: Vec<Name>, coeffs: Vec<i64>, rel: Relation, constant: i64 },
+    // c1 * x1 + ... + cn * xn REL C
+    Congruence(Vec<Name>, Vec<i64>, u64, u64),
+    // c1 * x1 + ... + cn * xn == R (mod M)
+    SetRef(Name, Vec<Name>),
+    // (x1, ..., xn) in S; variables are distinct
     True,
     False,
 }
@@ -32,60 +44,130 @@ impl LoPredicate {
             Self::EqConst(name1, _) => {
                 out.insert(name1.clone());
             }
+            Self::Linear { vars, .. } | Self::Congruence(vars, _, _, _) | Self::SetRef(_, vars) => {
+                out.extend(vars.iter().cloned());
+            }
             Self::True | Self::False => { /* Do nothing */ }
         };
         out
     }
 
-    pub fn safe_eq(name1: Name, name2: Name) -> LoPredicate {
-        if name1 == name2 {
-            LoPredicate::True
-        } else {
-            LoPredicate::Eq(name1, name2)
-        }
-    }
+    /* Creates normalized linear predicate: variables are sorted and unique,
+       coefficients are non-zero and divided by their gcd */
+    pub fn linear(terms: Vec<(Name, i64)>, rel: Relation, constant: i64) -> PasResult<LoPredicate> {
+        let (vars, mut coeffs) = merge_terms(terms, |c| i64::try_from(c).ok())?;
 
-    pub fn safe_add(name1: Name, name2: Name, name3: Name) -> LoPredicate {
-        if name1 == name3 {
-            LoPredicate::EqConst(name2, 0)
-        } else if name2 == name3 {
-            LoPredicate::EqConst(name1, 0)
-        } else if name1 == name2 {
-            LoPredicate::Double(name1, name2)
-        } else {
-            LoPredicate::Add(name1, name2, name3)
+        let gcd = coeffs.iter().try_fold(0, |a, b| b.checked_abs().map(|b| gcd(a, b))).ok_or_else(PasError::overflow)?;
+        if gcd == 0 {
+            return Ok(if rel.holds(0, constant as i128) { LoPredicate::True } else { LoPredicate::False });
+        }
+        let constant = match rel {
+            Relation::Eq if constant % gcd != 0 => return Ok(LoPredicate::False),
+            Relation::Eq => constant / gcd,
+            Relation::Lte => constant.div_euclid(gcd),
+        };
+        for c in coeffs.iter_mut() {
+            *c /= gcd;
         }
+        Ok(LoPredicate::Linear { vars, coeffs, rel, constant })
     }
 
-    pub fn safe_double(name1: Name, name2: Name) -> LoPredicate {
-        if name1 == name2 {
-            LoPredicate::EqConst(name2, 0)
-        } else {
-            LoPredicate::Double(name1, name2)
+    /* Creates normalized congruence: variables are sorted and unique,
+       coefficients and residue are reduced modulo modulus */
+    pub fn congruence(terms: Vec<(Name, i64)>, modulus: u64, residue: i64) -> PasResult<LoPredicate> {
+        if modulus == 0 {
+            return Self::linear(terms, Relation::Eq, residue);
+        }
+        let m = i64::try_from(modulus).map_err(|_| PasError::overflow())?;
+        let (vars, coeffs) = merge_terms(terms, |c| Some(c.rem_euclid(m as i128) as i64))?;
+        let residue = residue.rem_euclid(m) as u64;
+        if vars.is_empty() {
+            return Ok(if residue == 0 { LoPredicate::True } else { LoPredicate::False });
         }
+        Ok(LoPredicate::Congruence(vars, coeffs, modulus, residue))
     }
 
     pub fn to_formula(self) -> LoFormula {
         LoFormula::Predicate(self)
     }
 
-    pub fn rename_free_var(self, name_from: &Name, name_to: &Name) -> Self {
-        let change = |name| if name_from == &name { name_to.clone() } else { name };
+    /* Predicate where variables are renamed to #0, #1, ... in the order of the first occurrence
+       and the original variables in that order; predicates that differ only by names of variables
+       (e.g. repeated lowering of the same expression) get the same canonical form */
+    pub fn canonical(&self) -> (LoPredicate, Vec<Name>) {
+        let mut vars: Vec<Name> = Vec::new();
+        let predicate = self.map_names(&mut |name| {
+            let position = vars.iter().position(|v| v == name).unwrap_or_else(|| {
+                vars.push(name.clone());
+                vars.len() - 1
+            });
+            Name::Tmp(position)
+        });
+        (predicate, vars)
+    }
+
+    fn map_names<F: FnMut(&Name) -> Name>(&self, f: &mut F) -> Self {
         match self {
-            Self::Add(name1, name2, name3) => Self::Add(change(name1), change(name2), change(name3)),
-            Self::Eq(name1, name2) => Self::Eq(change(name1), change(name2)),
-            Self::Double(name1, name2) => Self::Double(change(name1), change(name2)),
-            Self::EqConst(name1, v) => Self::EqConst(change(name1), v),
-            Self::True | Self::False => self,
+            Self::Add(name1, name2, name3) => Self::Add(f(name1), f(name2), f(name3)),
+            Self::Eq(name1, name2) => Self::Eq(f(name1), f(name2)),
+            Self::Double(name1, name2) => Self::Double(f(name1), f(name2)),
+            Self::Linear { vars, coeffs, rel, constant } => {
+                let (vars, coeffs) = sort_terms(vars.iter().map(f).zip(coeffs.iter().copied()).collect());
+                Self::Linear { vars, coeffs, rel: *rel, constant: *constant }
+            }
+            Self::Congruence(vars, coeffs, modulus, residue) => {
+                let (vars, coeffs) = sort_terms(vars.iter().map(f).zip(coeffs.iter().copied()).collect());
+                Self::Congruence(vars, coeffs, *modulus, *residue)
+            }
+            Self::EqConst(name1, v) => Self::EqConst(f(name1), *v),
+            Self::SetRef(set, vars) => Self::SetRef(set.clone(), vars.iter().map(f).collect()),
+            Self::True | Self::False => self.clone(),
         }
     }
 }
 
-#[derive(Debug)]
+/* Sorts terms by variables, sums coefficients of the same variable and removes zero terms;
+   sums are reduced to i64 by "reduce", None is an overflow; atoms with more than
+   MAX_ATOM_TRACKS variables are refused */
+fn merge_terms<F: Fn(i128) -> Option<i64>>(mut terms: Vec<(Name, i64)>, reduce: F) -> PasResult<(Vec<Name>, Vec<i64>)> {
+    terms.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut vars: Vec<Name> = Vec::with_capacity(terms.len());
+    let mut sums: Vec<i128> = Vec::with_capacity(terms.len());
+    for (name, c) in terms {
+        if vars.last() == Some(&name) {
+            *sums.last_mut().unwrap() += c as i128;
+        } else {
+            vars.push(name);
+            sums.push(c as i128);
+        }
+    }
+    let mut coeffs = Vec::with_capacity(sums.len());
+    for c in sums {
+        coeffs.push(reduce(c).ok_or_else(PasError::overflow)?);
+    }
+    let (vars, coeffs): (Vec<Name>, Vec<i64>) = vars.into_iter().zip(coeffs).filter(|(_, c)| *c != 0).unzip();
+    if vars.len() > MAX_ATOM_TRACKS {
+        return Err(PasError::Unsupported(format!("atom with more than {} variables", MAX_ATOM_TRACKS)));
+    }
+    Ok((vars, coeffs))
+}
+
+/* Sorts terms of an injective renaming, no coefficients are merged */
+fn sort_terms(mut terms: Vec<(Name, i64)>) -> (Vec<Name>, Vec<i64>) {
+    terms.sort_by(|a, b| a.0.cmp(&b.0));
+    terms.into_iter().unzip()
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum LoFormula {
     Predicate(LoPredicate),
     Neg(Box<LoFormula>),
     Or(Box<(LoFormula, LoFormula)>),
+    And(Box<(LoFormula, LoFormula)>),
     Exists(Name, Box<LoFormula>),
 }
 
@@ -110,7 +192,13 @@ impl LoFormula {
     }
 
     pub fn and(self, other: LoFormula) -> LoFormula {
-        self.neg().or(other.neg()).neg()
+        match (self, other) {
+            (LoFormula::Predicate(LoPredicate::False), _) => LoFormula::Predicate(LoPredicate::False),
+            (_, LoFormula::Predicate(LoPredicate::False)) => LoFormula::Predicate(LoPredicate::False),
+            (LoFormula::Predicate(LoPredicate::True), x) => x,
+            (x, LoFormula::Predicate(LoPredicate::True)) => x,
+            (x, y) => LoFormula::And(Box::new((x, y)))
+        }
     }
 
     pub fn exists(self, name: Name) -> LoFormula {
@@ -133,7 +221,7 @@ impl LoFormula {
         match self {
             Self::Predicate(_p) => 1,
             Self::Neg(f) | Self::Exists(_, f) => f.size() + 1,
-            Self::Or(fs) => fs.0.size() + fs.1.size() + 1,
+            Self::Or(fs) | Self::And(fs) => fs.0.size() + fs.1.size() + 1,
         }
     }
 
@@ -141,7 +229,18 @@ impl LoFormula {
         match self {
             Self::Predicate(_p) => 1,
             Self::Neg(f) | Self::Exists(_, f) => f.depth() + 1,
-            Self::Or(fs) => fs.0.depth().max(fs.1.depth()) + 1,
+            Self::Or(fs) | Self::And(fs) => fs.0.depth().max(fs.1.depth()) + 1,
+        }
+    }
+
+    pub fn for_each_predicate<F: FnMut(&LoPredicate)>(&self, f: &mut F) {
+        match self {
+            Self::Predicate(p) => f(p),
+            Self::Neg(g) | Self::Exists(_, g) => g.for_each_predicate(f),
+            Self::Or(gs) | Self::And(gs) => {
+                gs.0.for_each_predicate(f);
+                gs.1.for_each_predicate(f);
+            }
         }
     }
 
@@ -149,7 +248,7 @@ impl LoFormula {
         match self {
             Self::Predicate(p) => p.free_vars(),
             Self::Neg(f) => f.free_vars(),
-            Self::Or(fs) => {
+            Self::Or(fs) | Self::And(fs) => {
                 let mut vars = fs.0.free_vars();
                 vars.extend(fs.1.free_vars());
                 vars
@@ -162,15 +261,103 @@ impl LoFormula {
         }
     }
 
-    pub fn rename_free_var(self, name_from: &Name, name_to: &Name) -> Self {
+    pub fn has_free_var(&self, name: &Name) -> bool {
+        match self {
+            Self::Predicate(p) => p.free_vars().contains(name),
+            Self::Neg(f) => f.has_free_var(name),
+            Self::Or(fs) | Self::And(fs) => fs.0.has_free_var(name) || fs.1.has_free_var(name),
+            Self::Exists(n, f) => n != name && f.has_free_var(name),
+        }
+    }
+
+    /* Folds True/False, removes double negations and quantifiers of unused variables and pushes
+       quantifiers inward: over disjunctions and into the conjuncts that mention the variable,
+       so projections are done on smaller automata */
+    pub fn simplify(self) -> LoFormula {
         match self {
-            Self::Predicate(p) => Self::Predicate(p.rename_free_var(name_from, name_to)),
-            Self::Neg(f) => Self::Neg(Box::new(f.rename_free_var(name_from, name_to))),
+            Self::Predicate(p) => Self::Predicate(p),
+            Self::Neg(f) => f.simplify().neg(),
             Self::Or(fs) => {
-                Self::Or(Box::new((fs.0.rename_free_var(name_from, name_to), fs.1.rename_free_var(name_from, name_to))))
+                let (f1, f2) = *fs;
+                f1.simplify().or(f2.simplify())
             }
-            Self::Exists(name, f) if &name != name_from => Self::Exists(name, Box::new(f.rename_free_var(name_from, name_to))),
-            x @ Self::Exists(_, _) => x,
+            Self::And(fs) => {
+                let (f1, f2) = *fs;
+                f1.simplify().and(f2.simplify())
+            }
+            Self::Exists(name, f) => f.simplify().push_exists(name),
+        }
+    }
+
+    /* Exists(name, self) for a simplified formula */
+    fn push_exists(self, name: Name) -> LoFormula {
+        if !self.has_free_var(&name) {
+            return self;
+        }
+        match self {
+            Self::Or(fs) => {
+                let (f1, f2) = *fs;
+                f1.push_exists(name.clone()).or(f2.push_exists(name))
+            }
+            f @ Self::And(_) => {
+                let mut conjuncts = Vec::new();
+                f.into_conjuncts(&mut conjuncts);
+                let (with, without): (Vec<_>, Vec<_>) = conjuncts.into_iter().partition(|f| f.has_free_var(&name));
+                if without.is_empty() {
+                    Self::conjunction(with).exists(name)
+                } else {
+                    Self::conjunction(without).and(Self::conjunction(with).push_exists(name))
+                }
+            }
+            f => f.exists(name),
+        }
+    }
+
+    fn into_conjuncts(self, out: &mut Vec<LoFormula>) {
+        match self {
+            Self::And(fs) => {
+                let (f1, f2) = *fs;
+                f1.into_conjuncts(out);
+                f2.into_conjuncts(out);
+            }
+            f => out.push(f),
+        }
+    }
+
+    fn conjunction(formulas: Vec<LoFormula>) -> LoFormula {
+        formulas.into_iter().fold(LoFormula::Predicate(LoPredicate::True), |f, g| f.and(g))
+    }
+}
+
+impl fmt::Display for LoPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sum = |vars: &[Name], coeffs: &[i64]| -> String {
+            vars.iter().zip(coeffs).map(|(v, c)| format!("{} * {:?}", c, v)).collect::<Vec<_>>().join(" + ")
+        };
+        match self {
+            Self::Add(name1, name2, name3) => write!(f, "{:?} + {:?} == {:?}", name1, name2, name3),
+            Self::Double(name1, name2) => write!(f, "2 * {:?} == {:?}", name1, name2),
+            Self::Eq(name1, name2) => write!(f, "{:?} == {:?}", name1, name2),
+            Self::EqConst(name, value) => write!(f, "{:?} == {}", name, value),
+            Self::Linear { vars, coeffs, rel, constant } => {
+                write!(f, "{} {} {}", sum(vars, coeffs), if *rel == Relation::Eq { "==" } else { "<=" }, constant)
+            }
+            Self::Congruence(vars, coeffs, modulus, residue) => write!(f, "{} == {} (mod {})", sum(vars, coeffs), residue, modulus),
+            Self::SetRef(name, vars) => write!(f, "{:?}({})", name, vars.iter().map(|v| format!("{:?}", v)).collect::<Vec<_>>().join(", ")),
+            Self::True => write!(f, "true"),
+            Self::False => write!(f, "false"),
+        }
+    }
+}
+
+impl fmt::Display for LoFormula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Predicate(p) => write!(f, "{}", p),
+            Self::Neg(g) => write!(f, "not ({})", g),
+            Self::Or(gs) => write!(f, "({} or {})", gs.0, gs.1),
+            Self::And(gs) => write!(f, "({} and {})", gs.0, gs.1),
+            Self::Exists(name, g) => write!(f, "exists({:?})({})", name, g),
         }
     }
 }
\ No newline at end of file
@@ -1,11 +1,15 @@
-pub use aset::AutomaticSet;
-pub use elements::{cut, Element, get_max_value, get_nth_element, iterate_elements, number_of_elements};
-pub use eval::{evaluate_formula, evaluate_predicate};
+pub use aset::{AutomaticSet, Domain, Relation};
+pub use elements::{cut, decode_sign_class, decode_word, Element, get_max_value, get_min_int_element, get_nth_element, get_symbolic_witness, get_witness, iterate_elements, number_of_elements, number_of_symbolic_elements, SymbolicElements};
+pub use eval::{evaluate_formula, evaluate_formula_cached, evaluate_formula_in, evaluate_formula_limited, evaluate_formula_with, evaluate_minimal, evaluate_predicate, FormulaCache, SetEnv};
 pub use formula::{LoFormula, LoPredicate};
+pub use storage::{export_set, import_set, load_set, read_set, save_set, write_set};
+pub use trace::{format_trace, Trace, TraceFormat, TraceNode};
 
 pub mod aset;
 pub mod elements;
 pub mod formula;
 pub mod eval;
+pub mod trace;
+pub mod storage;
 pub mod commands;
 
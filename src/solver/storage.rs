@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use hashbrown::HashSet;
+
+use crate::automata::{AutomatonFormat, Dfa, export_nfa, import_nfa, TransitionTable};
+use crate::common::{Name, PasError, PasResult, StateId};
+
+use super::{AutomaticSet, Domain};
+
+/* Format of stored sets (integers are little-endian u32):
+   magic "PASSET", version, domain (0 = nat, 1 = int), number of tracks,
+   track names (length + UTF-8 bytes), number of states, accepting flags (one byte per state),
+   transitions (state by state, 2^n_tracks targets per state); state 0 is initial */
+const MAGIC: &[u8; 6] = b"PASSET";
+const VERSION: u32 = 1;
+
+/// Sets with more tracks are refused on save and load; the table has 2^n_tracks columns
+pub const MAX_STORED_TRACKS: usize = 24;
+
+fn invalid(message: &str) -> PasError {
+    PasError::InvalidFormat(message.to_string())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: usize) -> PasResult<()> {
+    writer.write_all(&(value as u32).to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> PasResult<usize> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes).map_err(|_| invalid("unexpected end of file"))?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn read_bytes<R: Read>(reader: &mut R, length: usize) -> PasResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(invalid("unexpected end of file"));
+    }
+    Ok(bytes)
+}
+
+/* Symbolic sets are expanded into the table; sets with too many tracks are refused before anything is written */
+pub fn write_set<W: Write>(aset: &AutomaticSet, writer: &mut W) -> PasResult<()> {
+    if aset.track_names().len() > MAX_STORED_TRACKS {
+        return Err(PasError::Unsupported(format!("sets with more than {} tracks cannot be stored", MAX_STORED_TRACKS)));
+    }
+    let dfa = aset.make_dfa();
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION as usize)?;
+    write_u32(writer, match aset.domain() {
+        Domain::Nat => 0,
+        Domain::Int => 1,
+    })?;
+    write_u32(writer, aset.track_names().len())?;
+    for name in aset.track_names() {
+        let name = format!("{:?}", name);
+        write_u32(writer, name.len())?;
+        writer.write_all(name.as_bytes())?;
+    }
+    write_u32(writer, dfa.n_states())?;
+    writer.write_all(&dfa.accepting().iter().map(|a| *a as u8).collect::<Vec<u8>>())?;
+    for row in dfa.rows() {
+        for t in row {
+            write_u32(writer, *t as usize)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_set<R: Read>(reader: &mut R) -> PasResult<AutomaticSet> {
+    if read_bytes(reader, MAGIC.len())? != MAGIC {
+        return Err(invalid("not a set file"));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION as usize {
+        return Err(PasError::InvalidFormat(format!("unsupported version {}", version)));
+    }
+    let domain = match read_u32(reader)? {
+        0 => Domain::Nat,
+        1 => Domain::Int,
+        _ => return Err(invalid("unknown domain")),
+    };
+    let n_tracks = read_u32(reader)?;
+    if n_tracks > MAX_STORED_TRACKS {
+        return Err(PasError::InvalidFormat(format!("too many tracks ({})", n_tracks)));
+    }
+    let mut track_names = Vec::with_capacity(n_tracks);
+    let mut uniq = HashSet::new();
+    for _ in 0..n_tracks {
+        let length = read_u32(reader)?;
+        let name = String::from_utf8(read_bytes(reader, length)?).map_err(|_| invalid("track name is not UTF-8"))?;
+        if name.is_empty() || !uniq.insert(name.clone()) {
+            return Err(PasError::InvalidFormat(format!("invalid track name '{}'", name)));
+        }
+        track_names.push(Name::new(name));
+    }
+    let n_states = read_u32(reader)?;
+    if n_states == 0 {
+        return Err(invalid("automaton without states"));
+    }
+    let accepting = read_bytes(reader, n_states)?.into_iter().map(|a| match a {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid("invalid accepting flag")),
+    }).collect::<PasResult<Vec<bool>>>()?;
+    let alphabet_size = 1usize << n_tracks;
+    let bytes = read_bytes(reader, n_states * alphabet_size * 4)?;
+    let transitions: Vec<StateId> = bytes.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+    if transitions.iter().any(|t| *t as usize >= n_states) {
+        return Err(invalid("transition to a non-existing state"));
+    }
+    if reader.read(&mut [0u8])? != 0 {
+        return Err(invalid("unexpected data after the automaton"));
+    }
+    /* Stored automata need not be minimal, emptiness and elements rely on it */
+    let dfa = Dfa::new(TransitionTable::new(n_tracks, transitions), accepting).minimize();
+    Ok(AutomaticSet::from_dfa(domain, track_names, dfa))
+}
+
+pub fn save_set(aset: &AutomaticSet, path: &Path) -> PasResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_set(aset, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_set(path: &Path) -> PasResult<AutomaticSet> {
+    read_set(&mut BufReader::new(File::open(path)?))
+}
+
+/* Symbols are words over tracks in the order of track names (see AutomatonFormat); the formats
+   cannot record the domain and imported sets are over natural numbers, so sets of integers are refused */
+pub fn export_set(aset: &AutomaticSet, path: &Path, format: AutomatonFormat) -> PasResult<()> {
+    if aset.domain() != Domain::Nat {
+        return Err(PasError::Unsupported("only sets of natural numbers can be exported".to_string()));
+    }
+    let names: Vec<String> = aset.track_names().iter().map(|n| format!("{:?}", n)).collect();
+    std::fs::write(path, export_nfa(&aset.make_dfa().to_nfa(), format, &names))?;
+    Ok(())
+}
+
+/* Imported sets are over natural numbers; the language is closed under leading zeros,
+   as external automata may accept only some paddings. When no names are given,
+   names from the file are used */
+pub fn import_set(path: &Path, format: AutomatonFormat, names: &[Name]) -> PasResult<AutomaticSet> {
+    let (mut nfa, file_names) = import_nfa(&std::fs::read_to_string(path)?, format)?;
+    let names: Vec<Name> = if names.is_empty() { file_names.into_iter().map(Name::new).collect() } else { names.to_vec() };
+    if names.len() != nfa.n_tracks() {
+        return Err(PasError::ArityMismatch { command: "import".to_string(), expected: nfa.n_tracks(), got: names.len() });
+    }
+    let mut uniq = HashSet::new();
+    if let Some(name) = names.iter().find(|n| !uniq.insert((*n).clone())) {
+        return Err(PasError::DuplicateVariable(format!("{:?}", name)));
+    }
+    nfa.zero_prefix_fix();
+    nfa.zero_prefix_closure();
+    Ok(AutomaticSet::from_dfa(Domain::Nat, names, nfa.make_dfa()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlevel::parser::parse_setdef;
+    use crate::solver::commands::build_set;
+
+    use super::*;
+
+    fn roundtrip(aset: &AutomaticSet) -> AutomaticSet {
+        let mut data = Vec::new();
+        write_set(aset, &mut data).unwrap();
+        read_set(&mut data.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_storage_roundtrip() {
+        let a = build_set(&parse_setdef("{ x, y | x + 3 == y or x % 5 == 2 }").unwrap()).unwrap();
+        let mut b = roundtrip(&a);
+        assert_eq!(b.track_names(), a.track_names());
+        assert_eq!(b.domain(), Domain::Nat);
+        assert!(a.is_subset(&b) && b.is_subset(&a));
+        assert!(b.test_input(&[("x", 7), ("y", 10)]));
+        assert!(!b.test_input(&[("x", 8), ("y", 10)]));
+
+        let a = build_set(&parse_setdef("int { x | x < -3 }").unwrap()).unwrap();
+        let mut b = roundtrip(&a);
+        assert_eq!(b.domain(), Domain::Int);
+        assert!(b.test_input_int(&[("x", -4)]));
+        assert!(!b.test_input_int(&[("x", -3)]));
+
+        let a = build_set(&parse_setdef("{ | exists(x)(x > 3) }").unwrap()).unwrap();
+        assert!(!roundtrip(&a).is_empty());
+
+        /* Symbolic sets are stored expanded */
+        let a = build_set(&parse_setdef("{ a, b, c, d, e, f, g, h, i, j, k, l | a == g and b == h and c + 1 == i and d == j and e == k and f == l }").unwrap()).unwrap();
+        assert!(a.is_symbolic());
+        let mut b = roundtrip(&a);
+        assert_eq!(b.track_names(), a.track_names());
+        assert!(a.is_subset(&b) && b.is_subset(&a));
+        assert!(b.test_input(&[("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5), ("f", 6), ("g", 1), ("h", 2), ("i", 4), ("j", 4), ("k", 5), ("l", 6)]));
+        assert!(!b.test_input(&[("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5), ("f", 6), ("g", 1), ("h", 2), ("i", 3), ("j", 4), ("k", 5), ("l", 6)]));
+    }
+
+    #[test]
+    fn test_storage_not_minimal() {
+        /* Two states, neither accepting: empty, although the table is not minimal */
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        for value in &[VERSION, 0, 1, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.push(b'x');
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        for target in &[1u32, 1, 0, 0] {
+            data.extend_from_slice(&target.to_le_bytes());
+        }
+        let mut a = read_set(&mut data.as_slice()).unwrap();
+        assert!(a.is_empty());
+        assert_eq!(a.make_dfa().n_states(), 1);
+    }
+
+    #[test]
+    fn test_storage_refused() {
+        /* Nothing is written for sets the format cannot hold */
+        let check = |setdef: &str, message: &str| {
+            let a = build_set(&parse_setdef(setdef).unwrap()).unwrap();
+            let mut data = Vec::new();
+            assert!(matches!(write_set(&a, &mut data), Err(PasError::Unsupported(m)) if m.contains(message)));
+            assert!(data.is_empty());
+        };
+        let names: Vec<String> = (0..=MAX_STORED_TRACKS as u8).map(|i| format!("v{}{}", (b'a' + i / 26) as char, (b'a' + i % 26) as char)).collect();
+        check(&format!("{{ {} | vaa == vab }}", names.join(", ")), "tracks");
+    }
+
+    #[test]
+    fn test_storage_invalid() {
+        let a = build_set(&parse_setdef("{ x, y | x < y }").unwrap()).unwrap();
+        let mut data = Vec::new();
+        write_set(&a, &mut data).unwrap();
+
+        let check = |data: &[u8]| assert!(matches!(read_set(&mut &data[..]), Err(PasError::InvalidFormat(_))));
+        check(b"PASSEX");
+        check(&data[..data.len() - 1]);
+        let mut extra = data.clone();
+        extra.push(0);
+        check(&extra);
+        let mut version = data.clone();
+        version[6] = 2;
+        check(&version);
+        /* Track count that does not match the names and the table */
+        let mut tracks = data.clone();
+        tracks[14] = 3;
+        check(&tracks);
+        let mut target = data.clone();
+        let last = target.len() - 1;
+        target[last] = 0xff;
+        check(&target);
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::LoFormula;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TraceFormat {
+    Tree,
+    Json,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "tree" => Ok(TraceFormat::Tree),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(format!("Trace format '{}' does not exist", format)),
+        }
+    }
+}
+
+/// Evaluation of one LoFormula node; "time" includes evaluation of children
+#[derive(Debug, Clone)]
+pub struct TraceNode {
+    pub label: String,
+    pub cached: bool,
+    /// States of the automaton produced by the node operation
+    pub states: usize,
+    /// States after determinization and minimization, if it was done
+    pub minimal_states: Option<usize>,
+    pub time: Duration,
+    pub children: Vec<TraceNode>,
+}
+
+impl TraceNode {
+    /* Operator of the node, children are shown as subtrees */
+    pub fn label_of(formula: &LoFormula) -> String {
+        match formula {
+            LoFormula::Predicate(p) => p.to_string(),
+            LoFormula::Neg(_) => "not".to_string(),
+            LoFormula::Or(_) => "or".to_string(),
+            LoFormula::And(_) => "and".to_string(),
+            LoFormula::Exists(name, _) => format!("exists({:?})", name),
+        }
+    }
+}
+
+/* Collects nodes in post-order; children of unfinished nodes are kept on the stack */
+#[derive(Debug)]
+pub struct Trace {
+    stack: Vec<Vec<TraceNode>>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Trace { stack: vec![Vec::new()] }
+    }
+}
+
+impl Trace {
+    pub fn enter(&mut self) {
+        self.stack.push(Vec::new());
+    }
+
+    pub fn leave(&mut self, mut node: TraceNode) {
+        node.children = self.stack.pop().unwrap();
+        self.stack.last_mut().unwrap().push(node);
+    }
+
+    /* Determinization of the last finished node done by its parent */
+    pub fn add_minimization(&mut self, states: usize, time: Duration) {
+        if let Some(node) = self.stack.last_mut().unwrap().last_mut() {
+            node.minimal_states = Some(states);
+            node.time += time;
+        }
+    }
+
+    /* Finished top-level nodes; the trace is cleared */
+    pub fn take(&mut self) -> Vec<TraceNode> {
+        self.stack.truncate(1);
+        std::mem::take(&mut self.stack[0])
+    }
+}
+
+fn write_node(out: &mut String, node: &TraceNode, indent: usize) {
+    write!(out, "{:indent$}{} [{} states", "", node.label, node.states, indent = indent).unwrap();
+    if let Some(m) = node.minimal_states {
+        write!(out, " -> {}", m).unwrap();
+    }
+    writeln!(out, ", {:.3} ms{}]", node.time.as_secs_f64() * 1000.0, if node.cached { ", cached" } else { "" }).unwrap();
+    for child in &node.children {
+        write_node(out, child, indent + 2);
+    }
+}
+
+pub fn trace_to_tree(nodes: &[TraceNode]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        write_node(&mut out, node, 0);
+    }
+    out
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn node_to_json(node: &TraceNode) -> String {
+    format!("{{\"label\":{},\"cached\":{},\"states\":{},\"minimal_states\":{},\"time_ms\":{},\"children\":{}}}",
+            json_string(&node.label), node.cached, node.states,
+            node.minimal_states.map(|m| m.to_string()).unwrap_or_else(|| "null".to_string()),
+            node.time.as_secs_f64() * 1000.0, trace_to_json(&node.children))
+}
+
+pub fn trace_to_json(nodes: &[TraceNode]) -> String {
+    format!("[{}]", nodes.iter().map(node_to_json).collect::<Vec<_>>().join(","))
+}
+
+pub fn format_trace(nodes: &[TraceNode], format: TraceFormat) -> String {
+    match format {
+        TraceFormat::Tree => trace_to_tree(nodes),
+        TraceFormat::Json => trace_to_json(nodes) + "\n",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(label: &str, states: usize) -> TraceNode {
+        TraceNode { label: label.to_string(), cached: false, states, minimal_states: None, time: Duration::from_millis(2), children: Vec::new() }
+    }
+
+    #[test]
+    fn test_trace_output() {
+        let mut trace = Trace::default();
+        trace.enter();
+        trace.enter();
+        trace.leave(node("x == \"1\"", 3));
+        trace.add_minimization(2, Duration::from_millis(1));
+        trace.leave(node("not", 2));
+        let nodes = trace.take();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].children[0].minimal_states, Some(2));
+        assert_eq!(trace_to_tree(&nodes), "not [2 states, 2.000 ms]\n  x == \"1\" [3 states -> 2, 3.000 ms]\n");
+        assert_eq!(trace_to_json(&nodes),
+                   "[{\"label\":\"not\",\"cached\":false,\"states\":2,\"minimal_states\":null,\"time_ms\":2,\"children\":\
+                   [{\"label\":\"x == \\\"1\\\"\",\"cached\":false,\"states\":3,\"minimal_states\":2,\"time_ms\":3,\"children\":[]}]}]");
+        assert!(trace.take().is_empty());
+    }
+}